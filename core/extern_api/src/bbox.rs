@@ -0,0 +1,579 @@
+//! Conservative bounds on where shapes are filled, ported from the OCaml
+//! frontend's `Bbox` and `Compute_bb` modules.
+//!
+//! A shape is bounded from both sides: `positive` bounds the region that it
+//! fills, and `negative` bounds the region that it leaves empty.  `Not`
+//! swaps the two, which is what lets a subtraction stay bounded.
+
+use crate::{Bbox, Command, Id, Matrix, Point, Polygon, Shape, Terminal, Value, Viewport, Warp};
+use euclid::point2;
+use std::collections::HashMap;
+
+impl Bbox {
+    pub fn from_extrema(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Bbox {
+        Bbox {
+            x: min_x,
+            y: min_y,
+            w: max_x - min_x,
+            h: max_y - min_y,
+        }
+    }
+
+    /// The smallest box containing every point, or `None` if there aren't
+    /// any.
+    pub fn of_points(points: &[Point]) -> Option<Bbox> {
+        let first = points.first()?;
+        let (mut min, mut max) = (*first, *first);
+        for p in &points[1..] {
+            min = min.min(*p);
+            max = max.max(*p);
+        }
+        Some(Bbox::from_extrema(min.x, min.y, max.x, max.y))
+    }
+
+    fn max_x(&self) -> f32 {
+        self.x + self.w
+    }
+
+    fn max_y(&self) -> f32 {
+        self.y + self.h
+    }
+
+    fn corners(&self) -> [Point; 4] {
+        [
+            point2(self.x, self.y),
+            point2(self.max_x(), self.y),
+            point2(self.x, self.max_y()),
+            point2(self.max_x(), self.max_y()),
+        ]
+    }
+
+    pub fn intersects(&self, other: &Bbox) -> bool {
+        self.x < other.max_x()
+            && other.x < self.max_x()
+            && self.y < other.max_y()
+            && other.y < self.max_y()
+    }
+
+    pub fn union(&self, other: &Bbox) -> Bbox {
+        Bbox::from_extrema(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.max_x().max(other.max_x()),
+            self.max_y().max(other.max_y()),
+        )
+    }
+
+    pub fn intersection(&self, other: &Bbox) -> Option<Bbox> {
+        if !self.intersects(other) {
+            return None;
+        }
+        Some(Bbox::from_extrema(
+            self.x.max(other.x),
+            self.y.max(other.y),
+            self.max_x().min(other.max_x()),
+            self.max_y().min(other.max_y()),
+        ))
+    }
+
+    /// Moves every side out by `by`.
+    pub fn expand(&self, by: f32) -> Bbox {
+        Bbox {
+            x: self.x - by,
+            y: self.y - by,
+            w: self.w + by * 2.0,
+            h: self.h + by * 2.0,
+        }
+    }
+
+    /// Moves each side out by `factor` of the box's size along that axis,
+    /// but always by at least two units so that small shapes get a margin
+    /// too.
+    pub fn grow_by(&self, factor: f32) -> Bbox {
+        let dx = (self.w * factor).max(2.0);
+        let dy = (self.h * factor).max(2.0);
+        Bbox {
+            x: self.x - dx,
+            y: self.y - dy,
+            w: self.w + dx * 2.0,
+            h: self.h + dy * 2.0,
+        }
+    }
+
+    /// The smallest box containing this one after it has been transformed
+    /// by `matrix`.
+    pub fn transform(&self, matrix: &Matrix) -> Bbox {
+        let corners: Vec<_> = self
+            .corners()
+            .iter()
+            .map(|p| matrix.transform_point(p))
+            .collect();
+        Bbox::of_points(&corners).unwrap()
+    }
+
+    /// The box around the circle centered on `center` that contains this
+    /// box, which also contains every rotation of it around `center`.
+    fn spun_around(&self, center: Point) -> Bbox {
+        let r = self
+            .corners()
+            .iter()
+            .map(|p| (*p - center).length())
+            .fold(0.0, f32::max);
+        Bbox::from_extrema(center.x - r, center.y - r, center.x + r, center.y + r)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bounds {
+    Everything,
+    Nothing,
+    /// Somewhere inside of the box.
+    Something(Bbox),
+    /// Everywhere outside of the box, and maybe some of the inside.
+    Hole(Bbox),
+}
+
+impl Bounds {
+    pub fn union(self, other: Bounds) -> Bounds {
+        match (self, other) {
+            (Bounds::Nothing, other) | (other, Bounds::Nothing) => other,
+            (Bounds::Everything, _) | (_, Bounds::Everything) => Bounds::Everything,
+            (Bounds::Something(a), Bounds::Something(b)) => Bounds::Something(a.union(&b)),
+            (Bounds::Hole(a), Bounds::Hole(b)) => match a.intersection(&b) {
+                Some(hole) => Bounds::Hole(hole),
+                None => Bounds::Everything,
+            },
+            (Bounds::Hole(h), Bounds::Something(_)) | (Bounds::Something(_), Bounds::Hole(h)) => {
+                Bounds::Hole(h)
+            }
+        }
+    }
+
+    pub fn intersection(self, other: Bounds) -> Bounds {
+        match (self, other) {
+            (Bounds::Nothing, _) | (_, Bounds::Nothing) => Bounds::Nothing,
+            (Bounds::Everything, other) | (other, Bounds::Everything) => other,
+            (Bounds::Something(a), Bounds::Something(b)) => match a.intersection(&b) {
+                Some(b) => Bounds::Something(b),
+                None => Bounds::Nothing,
+            },
+            (Bounds::Hole(a), Bounds::Hole(b)) => Bounds::Hole(a.union(&b)),
+            (Bounds::Hole(_), Bounds::Something(s)) | (Bounds::Something(s), Bounds::Hole(_)) => {
+                Bounds::Something(s)
+            }
+        }
+    }
+
+    fn map_box<F: Fn(&Bbox) -> Bbox>(self, f: F) -> Bounds {
+        match self {
+            Bounds::Something(b) => Bounds::Something(f(&b)),
+            Bounds::Hole(b) => Bounds::Hole(f(&b)),
+            other => other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounding {
+    pub positive: Bounds,
+    pub negative: Bounds,
+}
+
+impl Bounding {
+    /// Nothing is known about the shape.
+    pub fn unknown() -> Bounding {
+        Bounding {
+            positive: Bounds::Everything,
+            negative: Bounds::Everything,
+        }
+    }
+
+    pub fn empty() -> Bounding {
+        Bounding {
+            positive: Bounds::Nothing,
+            negative: Bounds::Everything,
+        }
+    }
+
+    pub fn full() -> Bounding {
+        Bounding {
+            positive: Bounds::Everything,
+            negative: Bounds::Nothing,
+        }
+    }
+
+    /// A shape that fills some of `bbox` and none of the space around it.
+    pub fn inside(bbox: Bbox) -> Bounding {
+        Bounding {
+            positive: Bounds::Something(bbox),
+            negative: Bounds::Hole(bbox),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positive == Bounds::Nothing
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.negative == Bounds::Nothing
+    }
+
+    pub fn inverse(self) -> Bounding {
+        Bounding {
+            positive: self.negative,
+            negative: self.positive,
+        }
+    }
+
+    pub fn union(self, other: Bounding) -> Bounding {
+        Bounding {
+            positive: self.positive.union(other.positive),
+            negative: self.negative.intersection(other.negative),
+        }
+    }
+
+    pub fn intersection(self, other: Bounding) -> Bounding {
+        Bounding {
+            positive: self.positive.intersection(other.positive),
+            negative: self.negative.union(other.negative),
+        }
+    }
+
+    /// Unlike the OCaml version, an intersection of no shapes is full,
+    /// which is what makes `full` the identity for `intersection`.
+    pub fn intersection_all<I: IntoIterator<Item = Bounding>>(all: I) -> Bounding {
+        all.into_iter()
+            .fold(Bounding::full(), Bounding::intersection)
+    }
+
+    pub fn union_all<I: IntoIterator<Item = Bounding>>(all: I) -> Bounding {
+        all.into_iter().fold(Bounding::empty(), Bounding::union)
+    }
+
+    /// Moves every side of both bounds out by `by`.
+    pub fn grow(self, by: f32) -> Bounding {
+        Bounding {
+            positive: self.positive.map_box(|b| b.expand(by)),
+            negative: self.negative.map_box(|b| b.expand(by)),
+        }
+    }
+
+    pub fn transform(self, matrix: &Matrix) -> Bounding {
+        Bounding {
+            positive: self.positive.map_box(|b| b.transform(matrix)),
+            negative: self.negative.map_box(|b| b.transform(matrix)),
+        }
+    }
+
+    /// The bounds of the shape swept along (`dx`, `dy`), as it is by
+    /// `Command::Drag`.
+    pub fn dragged(self, dx: f32, dy: f32) -> Bounding {
+        self.union(self.transform(&Matrix::create_translation(dx, dy)))
+    }
+
+    // For shapes that copy their target around: anywhere they are filled,
+    // one of the copies was, so only a box around the target can be moved.
+    fn copied<F: Fn(&Bbox) -> Bbox>(self, f: F) -> Bounding {
+        let positive = match self.positive {
+            Bounds::Nothing => Bounds::Nothing,
+            Bounds::Something(b) => Bounds::Something(f(&b)),
+            Bounds::Everything | Bounds::Hole(_) => Bounds::Everything,
+        };
+        Bounding {
+            positive,
+            negative: Bounds::Everything,
+        }
+    }
+}
+
+/// Bounds `shape`, looking up the bounds of the fields that it reads from
+/// with `fields`.
+pub fn shape_bounds<F: Fn(&Id) -> Bounding>(shape: &Shape, fields: &F) -> Bounding {
+    match shape {
+        Shape::Terminal(Terminal::Circle(c)) => {
+            if c.r <= 0.0 {
+                Bounding::empty()
+            } else {
+                Bounding::inside(Bbox {
+                    x: c.x - c.r,
+                    y: c.y - c.r,
+                    w: c.r * 2.0,
+                    h: c.r * 2.0,
+                })
+            }
+        }
+        Shape::Terminal(Terminal::Rect(r)) => {
+            if r.w <= 0.0 || r.h <= 0.0 {
+                Bounding::empty()
+            } else {
+                Bounding::inside(Bbox {
+                    x: r.x,
+                    y: r.y,
+                    w: r.w,
+                    h: r.h,
+                })
+            }
+        }
+        Shape::Terminal(Terminal::Field(id)) => fields(id),
+        Shape::Not(target) => shape_bounds(target, fields).inverse(),
+        Shape::Union(shapes) => Bounding::union_all(shapes.iter().map(|s| shape_bounds(s, fields))),
+        Shape::Intersection(shapes) => {
+            Bounding::intersection_all(shapes.iter().map(|s| shape_bounds(s, fields)))
+        }
+        // A negative modulation shrinks the shape, so only its size is used.
+        Shape::Modulate(target, by) => shape_bounds(target, fields).grow(by.abs()),
+        Shape::Transform(target, matrix) => shape_bounds(target, fields).transform(matrix),
+        Shape::RepeatGrid {
+            target,
+            spacing_x,
+            spacing_y,
+            count_x,
+            count_y,
+        } => {
+            let dx = spacing_x * (count_x.max(&1) - 1) as f32;
+            let dy = spacing_y * (count_y.max(&1) - 1) as f32;
+            let last = Matrix::create_translation(dx, dy);
+            shape_bounds(target, fields).copied(|b| b.union(&b.transform(&last)))
+        }
+        Shape::RepeatPolar { target, x, y, .. } | Shape::Symmetry { target, x, y, .. } => {
+            shape_bounds(target, fields).copied(|b| b.spun_around(point2(*x, *y)))
+        }
+        Shape::Mirror {
+            target,
+            x,
+            y,
+            nx,
+            ny,
+        } => {
+            let (nx, ny) = match Shape::mirror_normal(*nx, *ny) {
+                Some(normal) => normal,
+                None => return shape_bounds(target, fields),
+            };
+            let d = x * nx + y * ny;
+            let reflection = Matrix::row_major(
+                1.0 - 2.0 * nx * nx,
+                -2.0 * nx * ny,
+                -2.0 * nx * ny,
+                1.0 - 2.0 * ny * ny,
+                2.0 * d * nx,
+                2.0 * d * ny,
+            );
+            shape_bounds(target, fields).copied(|b| b.union(&b.transform(&reflection)))
+        }
+        Shape::Warp(target, Warp::Twist { x, y, .. }) => {
+            shape_bounds(target, fields).copied(|b| b.spun_around(point2(*x, *y)))
+        }
+        // Bends and tapers can move any part of the target almost anywhere.
+        Shape::Warp(target, _) => {
+            let bounds = shape_bounds(target, fields);
+            if bounds.is_empty() {
+                Bounding::empty()
+            } else {
+                Bounding::unknown()
+            }
+        }
+        Shape::Displace { .. } => Bounding::unknown(),
+        // A blend of two fields is only inside (or outside) where one of
+        // them is.
+        Shape::Morph(a, b, _) => {
+            let (a, b) = (shape_bounds(a, fields), shape_bounds(b, fields));
+            Bounding {
+                positive: a.positive.union(b.positive),
+                negative: a.negative.union(b.negative),
+            }
+        }
+    }
+}
+
+pub fn polygon_bounds(polygon: &Polygon) -> Bounding {
+    match Bbox::of_points(&polygon.points) {
+        Some(bbox) => Bounding {
+            positive: Bounds::Something(bbox.transform(&polygon.matrix)),
+            negative: Bounds::Everything,
+        },
+        None => Bounding::empty(),
+    }
+}
+
+/// The bounds of every field that `command` exports, in the order that they
+/// are exported, or `None` if `command` reads an id before defining it.
+pub fn export_bounds(command: &Command) -> Option<Vec<(Id, Bounding)>> {
+    fn walk(
+        command: &Command,
+        fields: &mut HashMap<Id, Bounding>,
+        exports: &mut Vec<(Id, Bounding)>,
+    ) {
+        match command {
+            Command::Concurrently(commands) | Command::Serially(commands) => {
+                for command in commands {
+                    walk(command, fields, exports);
+                }
+            }
+            Command::Define(id, Value::BasicShape(shape)) => {
+                let bounds = shape_bounds(shape, &|id| fields[id]);
+                fields.insert(id.clone(), bounds);
+            }
+            Command::Define(id, Value::Polygon(polygon)) => {
+                fields.insert(id.clone(), polygon_bounds(polygon));
+            }
+            Command::Simplex(id, _) => {
+                fields.insert(id.clone(), Bounding::unknown());
+            }
+            Command::Freeze { target, id } => {
+                let bounds = fields[target];
+                fields.insert(id.clone(), bounds);
+            }
+            Command::Drag { target, id, dx, dy } => {
+                let bounds = fields[target].dragged(*dx, *dy);
+                fields.insert(id.clone(), bounds);
+            }
+            Command::Export(id) | Command::ExportWith(id, _) => {
+                exports.push((id.clone(), fields[id]))
+            }
+            // Resolved when the program is loaded.
+            Command::Include { .. } => {}
+        }
+    }
+
+    // `walk` looks up every id that it reads.
+    if command.undefined_id().is_some() {
+        return None;
+    }
+    let mut exports = vec![];
+    walk(command, &mut HashMap::new(), &mut exports);
+    Some(exports)
+}
+
+impl Viewport {
+    /// A viewport around everything that `command` exports, with a margin,
+    /// or `None` if the exports are either empty or unbounded, or `command`
+    /// reads an id before defining it.  Shapes that are only bounded by a
+    /// hole get room around the hole, but run off of the edges of the
+    /// viewport.
+    pub fn fit(command: &Command, resolution: f32) -> Option<Viewport> {
+        let exported = export_bounds(command)?
+            .into_iter()
+            .fold(Bounds::Nothing, |all, (_, bounds)| {
+                all.union(bounds.positive)
+            });
+        let bbox = match exported {
+            Bounds::Something(bbox) => bbox.grow_by(0.1),
+            // The shape fills the viewport up to its edges, so the hole gets
+            // twice the margin to keep its outline clear of them.
+            Bounds::Hole(bbox) => bbox.grow_by(0.2),
+            Bounds::Everything | Bounds::Nothing => return None,
+        };
+        Some(Viewport { bbox, resolution })
+    }
+}
+
+#[cfg(test)]
+fn bounds_of(figure: crate::builder::Figure) -> Bounding {
+    match crate::builder::compile(&figure) {
+        Command::Serially(commands) => match &commands[0] {
+            Command::Define(_, Value::BasicShape(shape)) => {
+                shape_bounds(shape, &|_| Bounding::unknown())
+            }
+            other => panic!("expected a shape, got {:?}", other),
+        },
+        other => panic!("expected a list of commands, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+fn bbox(x: f32, y: f32, w: f32, h: f32) -> Bbox {
+    Bbox { x, y, w, h }
+}
+
+#[test]
+fn boxes_combine() {
+    let a = bbox(0.0, 0.0, 10.0, 10.0);
+    let b = bbox(5.0, 5.0, 10.0, 10.0);
+    assert_eq!(a.union(&b), bbox(0.0, 0.0, 15.0, 15.0));
+    assert_eq!(a.intersection(&b), Some(bbox(5.0, 5.0, 5.0, 5.0)));
+    assert_eq!(a.intersection(&bbox(20.0, 20.0, 1.0, 1.0)), None);
+
+    let (something, hole) = (Bounds::Something(a), Bounds::Hole(b));
+    assert_eq!(something.union(hole), hole);
+    assert_eq!(something.intersection(hole), something);
+    assert_eq!(
+        hole.union(Bounds::Hole(bbox(20.0, 0.0, 1.0, 1.0))),
+        Bounds::Everything
+    );
+}
+
+#[test]
+fn transforms_move_the_box() {
+    use crate::builder::*;
+    let scaled = rect(0.0, 0.0, 10.0, 10.0)
+        .scale(3.0, 3.0)
+        .translate(5.0, 5.0);
+    assert_eq!(
+        bounds_of(scaled),
+        Bounding::inside(bbox(5.0, 5.0, 30.0, 30.0))
+    );
+
+    let scaled = circle(0.0, 0.0, 10.0).scale(3.0, 3.0);
+    assert_eq!(
+        bounds_of(scaled),
+        Bounding::inside(bbox(-30.0, -30.0, 60.0, 60.0))
+    );
+}
+
+#[test]
+fn subtraction_keeps_the_outer_box() {
+    use crate::builder::*;
+    let ring = subtract(circle(0.0, 0.0, 10.0), circle(0.0, 0.0, 5.0));
+    let bounds = bounds_of(ring);
+    assert_eq!(
+        bounds.positive,
+        Bounds::Something(bbox(-10.0, -10.0, 20.0, 20.0))
+    );
+
+    let disjoint = intersection(vec![circle(0.0, 0.0, 1.0), circle(10.0, 0.0, 1.0)]);
+    assert!(bounds_of(disjoint).is_empty());
+    assert!(bounds_of(not(circle(0.0, 0.0, 0.0))).is_full());
+}
+
+#[test]
+fn mirrors_without_a_normal_leave_the_box_alone() {
+    use crate::Circle;
+    let circle = Shape::Terminal(Terminal::Circle(Circle {
+        x: 5.0,
+        y: 0.0,
+        r: 1.0,
+    }));
+    let mirror = |nx, ny| Shape::Mirror {
+        target: Box::new(circle.clone()),
+        x: 0.0,
+        y: 0.0,
+        nx,
+        ny,
+    };
+    let bounds = |shape: &Shape| shape_bounds(shape, &|_| Bounding::unknown());
+    assert_eq!(bounds(&mirror(0.0, 0.0)), bounds(&circle));
+    assert_eq!(
+        bounds(&mirror(2.0, 0.0)).positive,
+        Bounds::Something(bbox(-6.0, -1.0, 12.0, 2.0))
+    );
+}
+
+#[test]
+fn fits_a_viewport_around_the_exports() {
+    use crate::builder::*;
+    let mut builder = Builder::new();
+    builder.export(&circle(0.0, 0.0, 10.0).freeze().drag(10.0, 0.0));
+    builder.export(&rect(0.0, 0.0, 1.0, 1.0));
+    let viewport = Viewport::fit(&builder.into_command(), 2.0).unwrap();
+    // The dragged circle covers (-10, -10) to (20, 10), and gets a margin
+    // of a tenth of its size, but no less than 2.
+    assert_eq!(viewport.bbox, bbox(-13.0, -12.0, 36.0, 24.0));
+    assert_eq!(viewport.resolution, 2.0);
+
+    assert_eq!(Viewport::fit(&compile(&noise(0.5)), 1.0), None);
+    assert_eq!(Viewport::fit(&compile(&circle(0.0, 0.0, -1.0)), 1.0), None);
+    assert_eq!(
+        Viewport::fit(&Command::Export(Id::new("missing")), 1.0),
+        None
+    );
+}
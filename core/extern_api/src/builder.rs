@@ -0,0 +1,321 @@
+//! Builds programs out of nested shapes, in the style of the OCaml frontend's
+//! `Creator` module.
+//!
+//! A `Figure` can freeze, drag, or contain polygons and noise at any depth,
+//! none of which a `Shape` can express directly.  `Builder` lowers a figure
+//! into the flat list of `Command`s that computes it, allocating ids as it
+//! goes.
+//!
+//! ```
+//! use extern_api::builder::*;
+//!
+//! let ring = subtract(circle(0.0, 0.0, 10.0), circle(0.0, 0.0, 8.0));
+//! let shadow = ring.clone().freeze().drag(2.0, 2.0);
+//! let command = compile(&union(vec![ring, shadow]));
+//! ```
+
+use crate::{Circle, Command, Id, Matrix, Point, Polygon, Rect, Shape, Simplex, Terminal, Value};
+use euclid::{vec2, Angle};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Figure {
+    Circle(Circle),
+    Rect(Rect),
+    Poly(Vec<Point>),
+    /// Simplex noise, thresholded at `cutoff`.
+    Noise(f32),
+    /// Any shape that doesn't read from fields.
+    Basic(Shape),
+
+    Transform(Box<Figure>, Matrix),
+
+    Not(Box<Figure>),
+    Union(Vec<Figure>),
+    Intersection(Vec<Figure>),
+
+    Modulate(Box<Figure>, f32),
+    Freeze(Box<Figure>),
+    Drag(Box<Figure>, f32, f32),
+}
+
+pub fn circle(x: f32, y: f32, r: f32) -> Figure {
+    Figure::Circle(Circle { x, y, r })
+}
+
+pub fn rect(x: f32, y: f32, w: f32, h: f32) -> Figure {
+    Figure::Rect(Rect { x, y, w, h })
+}
+
+pub fn poly(points: Vec<Point>) -> Figure {
+    Figure::Poly(points)
+}
+
+pub fn noise(cutoff: f32) -> Figure {
+    Figure::Noise(cutoff)
+}
+
+pub fn union(figures: Vec<Figure>) -> Figure {
+    Figure::Union(figures)
+}
+
+pub fn intersection(figures: Vec<Figure>) -> Figure {
+    Figure::Intersection(figures)
+}
+
+pub fn not(figure: Figure) -> Figure {
+    Figure::Not(Box::new(figure))
+}
+
+pub fn subtract(a: Figure, b: Figure) -> Figure {
+    intersection(vec![a, not(b)])
+}
+
+impl From<Shape> for Figure {
+    fn from(shape: Shape) -> Figure {
+        Figure::Basic(shape)
+    }
+}
+
+impl Figure {
+    pub fn transform(self, matrix: Matrix) -> Figure {
+        Figure::Transform(Box::new(self), matrix)
+    }
+
+    pub fn scale(self, dx: f32, dy: f32) -> Figure {
+        self.transform(Matrix::create_scale(dx, dy))
+    }
+
+    pub fn translate(self, dx: f32, dy: f32) -> Figure {
+        self.transform(Matrix::create_translation(dx, dy))
+    }
+
+    /// Rotates by `r` radians around the origin.
+    pub fn rotate(self, r: f32) -> Figure {
+        self.transform(Matrix::create_rotation(Angle::radians(r)))
+    }
+
+    pub fn rotate_around(self, r: f32, x: f32, y: f32) -> Figure {
+        self.translate(-x, -y).rotate(r).translate(x, y)
+    }
+
+    pub fn modulate(self, by: f32) -> Figure {
+        Figure::Modulate(Box::new(self), by)
+    }
+
+    pub fn freeze(self) -> Figure {
+        Figure::Freeze(Box::new(self))
+    }
+
+    pub fn drag(self, dx: f32, dy: f32) -> Figure {
+        Figure::Drag(Box::new(self), dx, dy)
+    }
+}
+
+/// Lowers figures into commands.
+#[derive(Debug, Default)]
+pub struct Builder {
+    next_id: u32,
+    commands: Vec<Command>,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    /// A builder whose ids start at `first_id`, for adding to a program that
+    /// already uses the ids below it.
+    pub fn starting_at(first_id: u32) -> Builder {
+        Builder {
+            next_id: first_id,
+            commands: vec![],
+        }
+    }
+
+    /// Adds the commands that compute `figure`, returning the id of the field
+    /// that it ends up in.
+    pub fn define(&mut self, figure: &Figure) -> Id {
+        let shape = self.lower(figure, &Matrix::identity());
+        let id = self.fresh_id();
+        self.commands
+            .push(Command::Define(id.clone(), Value::BasicShape(shape)));
+        id
+    }
+
+    pub fn export(&mut self, figure: &Figure) -> Id {
+        let id = self.define(figure);
+        self.commands.push(Command::Export(id.clone()));
+        id
+    }
+
+    pub fn into_command(self) -> Command {
+        Command::Serially(self.commands)
+    }
+
+    fn fresh_id(&mut self) -> Id {
+        let id = Id::from(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    // Fields are computed in the space of the whole program, and don't move
+    // with the transforms around them, so `matrix` tracks every transform
+    // between `figure` and the root.  Anything that is broken out into its
+    // own field is drawn with it.
+    fn lower(&mut self, figure: &Figure, matrix: &Matrix) -> Shape {
+        match figure {
+            Figure::Circle(c) => Shape::Terminal(Terminal::Circle(c.clone())),
+            Figure::Rect(r) => Shape::Terminal(Terminal::Rect(r.clone())),
+            Figure::Basic(shape) => shape.clone(),
+            Figure::Poly(points) => {
+                let id = self.fresh_id();
+                let polygon = Polygon {
+                    points: points.clone(),
+                    matrix: *matrix,
+                };
+                self.commands
+                    .push(Command::Define(id.clone(), Value::Polygon(polygon)));
+                Shape::Terminal(Terminal::Field(id))
+            }
+            Figure::Noise(cutoff) => {
+                let id = self.fresh_id();
+                let simplex = Simplex {
+                    cutoff: *cutoff,
+                    matrix: *matrix,
+                };
+                self.commands.push(Command::Simplex(id.clone(), simplex));
+                Shape::Terminal(Terminal::Field(id))
+            }
+            Figure::Transform(target, m) => {
+                let target = self.lower(target, &m.post_mul(matrix));
+                Shape::Transform(Box::new(target), *m)
+            }
+            Figure::Not(target) => Shape::Not(Box::new(self.lower(target, matrix))),
+            Figure::Union(figures) => Shape::Union(self.lower_all(figures, matrix)),
+            Figure::Intersection(figures) => Shape::Intersection(self.lower_all(figures, matrix)),
+            Figure::Modulate(target, by) => {
+                Shape::Modulate(Box::new(self.lower(target, matrix)), *by)
+            }
+            Figure::Freeze(target) => {
+                let target = self.define_in(target, matrix);
+                let id = self.fresh_id();
+                self.commands.push(Command::Freeze {
+                    target,
+                    id: id.clone(),
+                });
+                Shape::Terminal(Terminal::Field(id))
+            }
+            Figure::Drag(target, dx, dy) => {
+                let target = self.define_in(target, matrix);
+                let id = self.fresh_id();
+                let d = matrix.transform_vector(&vec2(*dx, *dy));
+                self.commands.push(Command::Drag {
+                    target,
+                    id: id.clone(),
+                    dx: d.x,
+                    dy: d.y,
+                });
+                Shape::Terminal(Terminal::Field(id))
+            }
+        }
+    }
+
+    fn lower_all(&mut self, figures: &[Figure], matrix: &Matrix) -> Vec<Shape> {
+        figures.iter().map(|f| self.lower(f, matrix)).collect()
+    }
+
+    fn define_in(&mut self, figure: &Figure, matrix: &Matrix) -> Id {
+        let shape = self.lower(figure, matrix);
+        let shape = if *matrix == Matrix::identity() {
+            shape
+        } else {
+            Shape::Transform(Box::new(shape), *matrix)
+        };
+        let id = self.fresh_id();
+        self.commands
+            .push(Command::Define(id.clone(), Value::BasicShape(shape)));
+        id
+    }
+}
+
+/// A program that exports `figure`, with ids starting from 0.
+pub fn compile(figure: &Figure) -> Command {
+    let mut builder = Builder::new();
+    builder.export(figure);
+    builder.into_command()
+}
+
+#[test]
+fn lowers_a_single_shape() {
+    let command = compile(&circle(1.0, 2.0, 3.0));
+    let expected = Command::Serially(vec![
+        Command::Define(
+            0.into(),
+            Value::BasicShape(Shape::Terminal(Terminal::Circle(Circle {
+                x: 1.0,
+                y: 2.0,
+                r: 3.0,
+            }))),
+        ),
+        Command::Export(0.into()),
+    ]);
+    assert_eq!(command, expected);
+}
+
+#[test]
+fn breaks_out_fields_under_transforms() {
+    let figure = union(vec![
+        noise(0.5),
+        circle(0.0, 0.0, 1.0).freeze().drag(1.0, 0.0),
+    ])
+    .scale(2.0, 2.0)
+    .translate(5.0, 0.0);
+    let command = compile(&figure);
+
+    let matrix = Matrix::create_scale(2.0, 2.0).post_translate(vec2(5.0, 0.0));
+    let circle = Shape::Terminal(Terminal::Circle(Circle {
+        x: 0.0,
+        y: 0.0,
+        r: 1.0,
+    }));
+    let field = |id: u32| Shape::Terminal(Terminal::Field(id.into()));
+    let expected = Command::Serially(vec![
+        Command::Simplex(
+            0.into(),
+            Simplex {
+                cutoff: 0.5,
+                matrix,
+            },
+        ),
+        Command::Define(
+            1.into(),
+            Value::BasicShape(Shape::Transform(Box::new(circle), matrix)),
+        ),
+        Command::Freeze {
+            target: 1.into(),
+            id: 2.into(),
+        },
+        Command::Define(
+            3.into(),
+            Value::BasicShape(Shape::Transform(Box::new(field(2)), matrix)),
+        ),
+        Command::Drag {
+            target: 3.into(),
+            id: 4.into(),
+            dx: 2.0,
+            dy: 0.0,
+        },
+        Command::Define(
+            5.into(),
+            Value::BasicShape(Shape::Transform(
+                Box::new(Shape::Transform(
+                    Box::new(Shape::Union(vec![field(0), field(4)])),
+                    Matrix::create_scale(2.0, 2.0),
+                )),
+                Matrix::create_translation(5.0, 0.0),
+            )),
+        ),
+        Command::Export(5.into()),
+    ]);
+    assert_eq!(command, expected);
+}
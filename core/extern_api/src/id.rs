@@ -0,0 +1,115 @@
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+/// Names a field.  The OCaml frontend numbers its fields, so an id can be
+/// written as either a number or a string, and numbers are written back out
+/// as numbers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Id(String);
+
+/// Separates the namespace of an included program from its own ids.
+const NAMESPACE_SEPARATOR: char = '.';
+
+/// Puts `name` (an id, or the namespace of a nested include) under
+/// `namespace`.
+pub(crate) fn scoped_name(namespace: &str, name: &str) -> String {
+    format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name)
+}
+
+impl Id {
+    pub fn new<S: Into<String>>(name: S) -> Id {
+        Id(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The id that this one is given when its program is included under
+    /// `namespace`.
+    pub fn scoped(&self, namespace: &str) -> Id {
+        Id(scoped_name(namespace, &self.0))
+    }
+
+    fn number(&self) -> Option<u32> {
+        match self.0.parse::<u32>() {
+            // Only ids that print the same way round trip as numbers.
+            Ok(n) if n.to_string() == self.0 => Some(n),
+            _ => None,
+        }
+    }
+}
+
+impl From<u32> for Id {
+    fn from(n: u32) -> Id {
+        Id(n.to_string())
+    }
+}
+
+impl<'a> From<&'a str> for Id {
+    fn from(name: &'a str) -> Id {
+        Id::new(name)
+    }
+}
+
+impl From<String> for Id {
+    fn from(name: String) -> Id {
+        Id(name)
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Id {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.number() {
+            Some(n) => serializer.serialize_u32(n),
+            None => serializer.serialize_str(&self.0),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Id, D::Error> {
+        struct IdVisitor;
+        impl<'de> Visitor<'de> for IdVisitor {
+            type Value = Id;
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number or a name")
+            }
+
+            fn visit_u64<E: de::Error>(self, n: u64) -> Result<Id, E> {
+                Ok(Id(n.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, n: i64) -> Result<Id, E> {
+                if n < 0 {
+                    return Err(E::invalid_value(de::Unexpected::Signed(n), &self));
+                }
+                Ok(Id(n.to_string()))
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Id, E> {
+                if name.is_empty() {
+                    return Err(E::invalid_value(de::Unexpected::Str(name), &self));
+                }
+                Ok(Id::new(name))
+            }
+        }
+        deserializer.deserialize_any(IdVisitor)
+    }
+}
+
+#[test]
+fn numbers_stay_numbers() {
+    let ids: Vec<Id> = serde_json::from_str(r#"[3, "gear", "07"]"#).unwrap();
+    assert_eq!(ids, vec![Id::from(3), Id::from("gear"), Id::from("07")]);
+    assert_eq!(serde_json::to_string(&ids).unwrap(), r#"[3,"gear","07"]"#);
+    assert!(serde_json::from_str::<Id>(r#""""#).is_err());
+    assert!(serde_json::from_str::<Id>("-1").is_err());
+}
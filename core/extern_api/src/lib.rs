@@ -2,103 +2,666 @@
 extern crate serde_derive;
 extern crate euclid;
 extern crate serde;
+extern crate serde_json;
 
+pub mod bbox;
+pub mod builder;
+mod id;
+pub mod params;
+pub mod sexp;
+pub mod simplify;
+
+use euclid::num::{One, Zero};
 use euclid::{Point2D, Transform2D};
-use serde::Deserializer;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 
-pub type Id = u32;
+pub use crate::id::Id;
 pub type Matrix = Transform2D<f32>;
 pub type Point = Point2D<f32>;
 
-#[derive(Deserialize)]
-struct PointDef {
-    x: f32,
-    y: f32,
+/// The numbers that shapes, and the commands that move them, are made of:
+/// `f32` in a `Program`, and `params::Float` in a template, where any of
+/// them can be a parameter.
+pub trait Scalar: Copy + PartialEq + fmt::Debug + From<f32> + One + Zero {
+    /// The number, or `None` if it isn't known until parameters are filled
+    /// in.
+    fn value(self) -> Option<f32>;
+}
+
+impl Scalar for f32 {
+    fn value(self) -> Option<f32> {
+        Some(self)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PointDef<S> {
+    x: S,
+    y: S,
 }
 
-#[derive(Deserialize)]
-#[serde(remote = "Matrix")]
-struct MatrixDef {
-    #[serde(getter = "garbage")]
-    m11: f32,
-    m12: f32,
-    m21: f32,
-    m22: f32,
-    m31: f32,
-    m32: f32,
+#[derive(Serialize, Deserialize)]
+struct MatrixDef<S> {
+    m11: S,
+    m12: S,
+    m21: S,
+    m22: S,
+    m31: S,
+    m32: S,
+}
+
+// Matrices are written out field by field rather than with euclid's own
+// (tuple based) serde impls, to match the layout the OCaml side uses.
+mod matrix_def {
+    use super::{MatrixDef, Transform2D};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T, S>(matrix: &Transform2D<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Copy + Serialize,
+        S: Serializer,
+    {
+        MatrixDef::from(*matrix).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Transform2D<T>, D::Error>
+    where
+        T: Copy + Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        MatrixDef::deserialize(deserializer).map(MatrixDef::into_matrix)
+    }
 }
 
 // Provide a conversion to construct the remote type.
-impl From<PointDef> for Point {
-    fn from(def: PointDef) -> Point {
+impl<S: Copy> From<PointDef<S>> for Point2D<S> {
+    fn from(def: PointDef<S>) -> Point2D<S> {
         ::euclid::point2(def.x, def.y)
     }
 }
 
-impl From<MatrixDef> for Matrix {
-    fn from(def: MatrixDef) -> Matrix {
-        Transform2D::row_major(def.m11, def.m12, def.m21, def.m22, def.m31, def.m32)
+impl<S: Copy> From<Transform2D<S>> for MatrixDef<S> {
+    fn from(m: Transform2D<S>) -> MatrixDef<S> {
+        MatrixDef {
+            m11: m.m11,
+            m12: m.m12,
+            m21: m.m21,
+            m22: m.m22,
+            m31: m.m31,
+            m32: m.m32,
+        }
     }
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Circle {
-    pub x: f32,
-    pub y: f32,
-    pub r: f32,
+impl<S: Copy> MatrixDef<S> {
+    fn into_matrix(self) -> Transform2D<S> {
+        Transform2D::row_major(self.m11, self.m12, self.m21, self.m22, self.m31, self.m32)
+    }
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Rect {
-    pub x: f32,
-    pub y: f32,
-    pub w: f32,
-    pub h: f32,
+fn map_matrix<S, T, E, F>(m: &Transform2D<S>, f: &mut F) -> Result<Transform2D<T>, E>
+where
+    S: Copy,
+    T: Copy,
+    F: FnMut(S) -> Result<T, E>,
+{
+    Ok(Transform2D::row_major(
+        f(m.m11)?,
+        f(m.m12)?,
+        f(m.m21)?,
+        f(m.m22)?,
+        f(m.m31)?,
+        f(m.m32)?,
+    ))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Circle<S: Scalar = f32> {
+    pub x: S,
+    pub y: S,
+    pub r: S,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Polygon {
-    #[serde(deserialize_with = "transcode_point")]
-    pub points: Vec<Point>,
-    #[serde(with = "MatrixDef")]
-    pub matrix: Matrix,
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Rect<S: Scalar = f32> {
+    pub x: S,
+    pub y: S,
+    pub w: S,
+    pub h: S,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Simplex {
-    pub cutoff: f32,
-    #[serde(with = "MatrixDef")]
-    pub matrix: Matrix,
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(bound(
+    serialize = "S: Scalar + Serialize",
+    deserialize = "S: Scalar + Deserialize<'de>"
+))]
+pub struct Polygon<S: Scalar = f32> {
+    #[serde(
+        serialize_with = "serialize_points",
+        deserialize_with = "transcode_point"
+    )]
+    pub points: Vec<Point2D<S>>,
+    #[serde(with = "matrix_def")]
+    pub matrix: Transform2D<S>,
+}
+
+impl<S: Scalar> Polygon<S> {
+    pub fn try_map<T, E, F>(&self, f: &mut F) -> Result<Polygon<T>, E>
+    where
+        T: Scalar,
+        F: FnMut(S) -> Result<T, E>,
+    {
+        Ok(Polygon {
+            points: self
+                .points
+                .iter()
+                .map(|p| Ok(::euclid::point2(f(p.x)?, f(p.y)?)))
+                .collect::<Result<_, E>>()?,
+            matrix: map_matrix(&self.matrix, f)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Simplex<S: Scalar = f32> {
+    pub cutoff: S,
+    #[serde(with = "matrix_def")]
+    pub matrix: Transform2D<S>,
+}
+
+impl<S: Scalar> Simplex<S> {
+    pub fn try_map<T, E, F>(&self, f: &mut F) -> Result<Simplex<T>, E>
+    where
+        T: Scalar,
+        F: FnMut(S) -> Result<T, E>,
+    {
+        Ok(Simplex {
+            cutoff: f(self.cutoff)?,
+            matrix: map_matrix(&self.matrix, f)?,
+        })
+    }
 }
 
-#[derive(Deserialize, Debug)]
-pub enum Terminal {
-    Circle(Circle),
-    Rect(Rect),
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Terminal<S: Scalar = f32> {
+    Circle(Circle<S>),
+    Rect(Rect<S>),
     Field(Id),
 }
 
-#[derive(Deserialize, Debug)]
-pub enum Shape {
-    Terminal(Terminal),
-    Not(Box<Shape>),
-    Union(Vec<Shape>),
-    Intersection(Vec<Shape>),
-    Modulate(Box<Shape>, f32),
-    Transform(Box<Shape>, #[serde(with = "MatrixDef")] Matrix),
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Shape<S: Scalar = f32> {
+    Terminal(Terminal<S>),
+    Not(Box<Shape<S>>),
+    Union(Vec<Shape<S>>),
+    Intersection(Vec<Shape<S>>),
+    Modulate(Box<Shape<S>>, S),
+    Transform(Box<Shape<S>>, #[serde(with = "matrix_def")] Transform2D<S>),
+    RepeatGrid {
+        target: Box<Shape<S>>,
+        spacing_x: S,
+        spacing_y: S,
+        count_x: u32,
+        count_y: u32,
+    },
+    RepeatPolar {
+        target: Box<Shape<S>>,
+        count: u32,
+        x: S,
+        y: S,
+    },
+    Mirror {
+        target: Box<Shape<S>>,
+        x: S,
+        y: S,
+        nx: S,
+        ny: S,
+    },
+    Symmetry {
+        target: Box<Shape<S>>,
+        count: u32,
+        x: S,
+        y: S,
+    },
+    Warp(Box<Shape<S>>, Warp<S>),
+    Displace {
+        target: Box<Shape<S>>,
+        by: Box<Shape<S>>,
+        amount: S,
+    },
+    Morph(Box<Shape<S>>, Box<Shape<S>>, S),
+}
+
+impl<S: Scalar> Shape<S> {
+    pub fn children(&self) -> Vec<&Shape<S>> {
+        match self {
+            Shape::Terminal(_) => vec![],
+            Shape::Union(shapes) | Shape::Intersection(shapes) => shapes.iter().collect(),
+            Shape::Not(target)
+            | Shape::Modulate(target, _)
+            | Shape::Transform(target, _)
+            | Shape::Warp(target, _)
+            | Shape::RepeatGrid { target, .. }
+            | Shape::RepeatPolar { target, .. }
+            | Shape::Mirror { target, .. }
+            | Shape::Symmetry { target, .. } => vec![&**target],
+            Shape::Displace { target, by, .. } => vec![&**target, &**by],
+            Shape::Morph(a, b, _) => vec![&**a, &**b],
+        }
+    }
+
+    /// The ids of every field that the shape reads from, in the order that
+    /// they appear.
+    pub fn fields(&self) -> Vec<Id> {
+        fn collect<S: Scalar>(shape: &Shape<S>, out: &mut Vec<Id>) {
+            if let Shape::Terminal(Terminal::Field(id)) = shape {
+                out.push(id.clone());
+            }
+            for child in shape.children() {
+                collect(child, out);
+            }
+        }
+        let mut out = vec![];
+        collect(self, &mut out);
+        out
+    }
+
+    /// The same shape with each of its numbers replaced by `f` of it, or the
+    /// first error that `f` returns.
+    pub fn try_map<T, E, F>(&self, f: &mut F) -> Result<Shape<T>, E>
+    where
+        T: Scalar,
+        F: FnMut(S) -> Result<T, E>,
+    {
+        let all = |shapes: &[Shape<S>], f: &mut F| {
+            shapes
+                .iter()
+                .map(|s| s.try_map(f))
+                .collect::<Result<Vec<_>, E>>()
+        };
+        let one = |shape: &Shape<S>, f: &mut F| shape.try_map(f).map(Box::new);
+        Ok(match self {
+            Shape::Terminal(terminal) => Shape::Terminal(terminal.try_map(f)?),
+            Shape::Not(target) => Shape::Not(one(target, f)?),
+            Shape::Union(shapes) => Shape::Union(all(shapes, f)?),
+            Shape::Intersection(shapes) => Shape::Intersection(all(shapes, f)?),
+            Shape::Modulate(target, by) => Shape::Modulate(one(target, f)?, f(*by)?),
+            Shape::Transform(target, matrix) => {
+                Shape::Transform(one(target, f)?, map_matrix(matrix, f)?)
+            }
+            Shape::RepeatGrid {
+                target,
+                spacing_x,
+                spacing_y,
+                count_x,
+                count_y,
+            } => Shape::RepeatGrid {
+                target: one(target, f)?,
+                spacing_x: f(*spacing_x)?,
+                spacing_y: f(*spacing_y)?,
+                count_x: *count_x,
+                count_y: *count_y,
+            },
+            Shape::RepeatPolar {
+                target,
+                count,
+                x,
+                y,
+            } => Shape::RepeatPolar {
+                target: one(target, f)?,
+                count: *count,
+                x: f(*x)?,
+                y: f(*y)?,
+            },
+            Shape::Mirror {
+                target,
+                x,
+                y,
+                nx,
+                ny,
+            } => Shape::Mirror {
+                target: one(target, f)?,
+                x: f(*x)?,
+                y: f(*y)?,
+                nx: f(*nx)?,
+                ny: f(*ny)?,
+            },
+            Shape::Symmetry {
+                target,
+                count,
+                x,
+                y,
+            } => Shape::Symmetry {
+                target: one(target, f)?,
+                count: *count,
+                x: f(*x)?,
+                y: f(*y)?,
+            },
+            Shape::Warp(target, warp) => Shape::Warp(one(target, f)?, warp.try_map(f)?),
+            Shape::Displace { target, by, amount } => Shape::Displace {
+                target: one(target, f)?,
+                by: one(by, f)?,
+                amount: f(*amount)?,
+            },
+            Shape::Morph(a, b, t) => Shape::Morph(one(a, f)?, one(b, f)?, f(*t)?),
+        })
+    }
+
+    fn scope(&mut self, namespace: &str) {
+        match self {
+            Shape::Terminal(Terminal::Field(id)) => *id = id.scoped(namespace),
+            Shape::Terminal(_) => {}
+            Shape::Union(shapes) | Shape::Intersection(shapes) => {
+                for shape in shapes {
+                    shape.scope(namespace);
+                }
+            }
+            Shape::Not(target)
+            | Shape::Modulate(target, _)
+            | Shape::Transform(target, _)
+            | Shape::Warp(target, _)
+            | Shape::RepeatGrid { target, .. }
+            | Shape::RepeatPolar { target, .. }
+            | Shape::Mirror { target, .. }
+            | Shape::Symmetry { target, .. } => target.scope(namespace),
+            Shape::Displace { target, by, .. } => {
+                target.scope(namespace);
+                by.scope(namespace);
+            }
+            Shape::Morph(a, b, _) => {
+                a.scope(namespace);
+                b.scope(namespace);
+            }
+        }
+    }
+}
+
+impl Shape {
+    /// The unit normal of a `Mirror` with the normal (`nx`, `ny`), or `None`
+    /// if it has no direction, in which case the mirror leaves its target as
+    /// it is.
+    pub fn mirror_normal(nx: f32, ny: f32) -> Option<(f32, f32)> {
+        let len = (nx * nx + ny * ny).sqrt();
+        if len > 0.0 && len.is_finite() {
+            Some((nx / len, ny / len))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the shape is moved by a matrix that can't be inverted.
+    /// Shapes are drawn by mapping each point back through their matrices,
+    /// so those can't be drawn.
+    pub fn has_singular_matrix(&self) -> bool {
+        match self {
+            Shape::Transform(_, matrix) if matrix.inverse().is_none() => true,
+            _ => self.children().iter().any(|c| c.has_singular_matrix()),
+        }
+    }
+}
+
+impl<S: Scalar> Terminal<S> {
+    pub fn try_map<T, E, F>(&self, f: &mut F) -> Result<Terminal<T>, E>
+    where
+        T: Scalar,
+        F: FnMut(S) -> Result<T, E>,
+    {
+        Ok(match self {
+            Terminal::Circle(c) => Terminal::Circle(Circle {
+                x: f(c.x)?,
+                y: f(c.y)?,
+                r: f(c.r)?,
+            }),
+            Terminal::Rect(r) => Terminal::Rect(Rect {
+                x: f(r.x)?,
+                y: f(r.y)?,
+                w: f(r.w)?,
+                h: f(r.h)?,
+            }),
+            Terminal::Field(id) => Terminal::Field(id.clone()),
+        })
+    }
+}
+
+/// A non-affine deformation of space around (`x`, `y`).  Warps stretch
+/// distances, so each one is only guaranteed to be accurate within its
+/// `radius`, which is used to bound how much it can stretch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Warp<S: Scalar = f32> {
+    /// Rotates by `rate` radians per unit of distance from the center.
+    Twist { x: S, y: S, rate: S, radius: S },
+    /// Rotates by `rate` radians per unit of distance along the x axis.
+    Bend { x: S, y: S, rate: S, radius: S },
+    /// Grows the height by `rate` per unit of distance along the x axis.
+    /// Where that would shrink it below a hundredth of its height, it's
+    /// kept at a hundredth.
+    Taper { x: S, y: S, rate: S, radius: S },
+}
+
+impl<S: Scalar> Warp<S> {
+    pub fn try_map<T, E, F>(&self, f: &mut F) -> Result<Warp<T>, E>
+    where
+        T: Scalar,
+        F: FnMut(S) -> Result<T, E>,
+    {
+        Ok(match *self {
+            Warp::Twist { x, y, rate, radius } => Warp::Twist {
+                x: f(x)?,
+                y: f(y)?,
+                rate: f(rate)?,
+                radius: f(radius)?,
+            },
+            Warp::Bend { x, y, rate, radius } => Warp::Bend {
+                x: f(x)?,
+                y: f(y)?,
+                rate: f(rate)?,
+                radius: f(radius)?,
+            },
+            Warp::Taper { x, y, rate, radius } => Warp::Taper {
+                x: f(x)?,
+                y: f(y)?,
+                rate: f(rate)?,
+                radius: f(radius)?,
+            },
+        })
+    }
+}
+
+impl Warp {
+    /// An upper bound on how much the warp stretches distances within its
+    /// radius.  Dividing a warped field by this keeps it from overestimating
+    /// the distance to the surface.
+    pub fn lipschitz(&self) -> f32 {
+        match *self {
+            Warp::Twist { rate, radius, .. } | Warp::Bend { rate, radius, .. } => {
+                1.0 + rate.abs() * radius
+            }
+            Warp::Taper { rate, radius, .. } => {
+                let narrowest = (1.0 - rate.abs() * radius).max(0.01);
+                (1.0 / narrowest).max(1.0) + rate.abs() * radius / (narrowest * narrowest)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Value<S: Scalar = f32> {
+    BasicShape(Shape<S>),
+    Polygon(Polygon<S>),
+}
+
+/// How the surface of an exported field is traced into paths.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Contouring {
+    /// Marching squares, which puts every point on the edges of the grid
+    /// and so rounds off corners.
+    #[default]
+    Marching,
+    /// Dual contouring, which puts a point inside each cell where the
+    /// surfaces through its edges meet, and so keeps corners sharp.
+    Dual,
+}
+
+/// The algorithm that paths are simplified with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SimplifyMethod {
+    /// Keeps every point that's further than the tolerance from the line
+    /// between the points that are kept around it.
+    #[default]
+    DouglasPeucker,
+    /// Removes the points that make the smallest triangles with their
+    /// neighbours, until none are smaller than the tolerance.
+    VisvalingamWhyatt,
+}
+
+/// How joined paths are simplified.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SimplifyOptions<S: Scalar = f32> {
+    #[serde(default)]
+    pub method: SimplifyMethod,
+    /// In pixels for `DouglasPeucker`, and square pixels for
+    /// `VisvalingamWhyatt`.
+    pub tolerance: S,
+    /// Keeps any point whose removal would make a path cross itself or
+    /// another path.
+    #[serde(default)]
+    pub preserve_topology: bool,
+}
+
+/// How the lines that trace a field are joined into paths.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct StitchOptions<S: Scalar = f32> {
+    /// Joins lines by the grid edge that their ends lie on, which is exact.
+    /// Otherwise the lines are joined wherever their ends are within
+    /// `epsilon` of each other, by whichever of the stages below are
+    /// enabled.  Dual contouring always joins its own lines.
+    pub by_edge: bool,
+    /// How close (in pixels) the ends of two lines need to be to be joined.
+    pub epsilon: S,
+    /// Sorts the lines before they're joined, so that the same field is
+    /// always joined into the same paths.
+    pub sort: bool,
+    pub remove_zero_area_loops: bool,
+    pub prune: bool,
+    pub connect_obvious: bool,
+    pub graph_stitch: bool,
+    /// Only continues a path into a line that starts where it ends, which is
+    /// right when every line runs the same way around the shape (as they do
+    /// from marching squares).
+    pub oriented: bool,
+    /// Simplifies the joined paths, whichever way they were joined.
+    pub simplify: Option<SimplifyOptions<S>>,
 }
 
-#[derive(Deserialize, Debug)]
-pub enum Value {
-    BasicShape(Shape),
-    Polygon(Polygon),
+impl<S: Scalar> Default for StitchOptions<S> {
+    fn default() -> StitchOptions<S> {
+        StitchOptions {
+            by_edge: true,
+            epsilon: S::from(0.001),
+            sort: true,
+            remove_zero_area_loops: true,
+            prune: true,
+            connect_obvious: true,
+            graph_stitch: true,
+            oriented: true,
+            simplify: None,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-pub enum Command {
-    Concurrently(Vec<Command>),
-    Serially(Vec<Command>),
-    Define(Id, Value),
+/// How the inside of an exported shape is shaded, for plotters that can
+/// only draw lines.  Distances are in world units, and angles in radians
+/// from the x axis.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Infill<S: Scalar = f32> {
+    /// Parallel lines `spacing` apart.
+    Hatch { angle: S, spacing: S },
+    /// Hatching at `angle`, and again at right angles to it.
+    CrossHatch { angle: S, spacing: S },
+    /// The outline, inset by `spacing` again and again until nothing is left.
+    /// Each ring is traced from the field modulated by another `-spacing`,
+    /// so the rings are only evenly spaced if the field is a distance field
+    /// (as `Freeze` makes).
+    Concentric { spacing: S },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(bound(deserialize = "S: Scalar + Deserialize<'de>"))]
+pub struct ExportOptions<S: Scalar = f32> {
+    #[serde(default)]
+    pub contouring: Contouring,
+    /// Moves each point of the paths onto the exact surface of the shape,
+    /// rather than where it's interpolated to between pixels.  Only fields
+    /// that were defined by a shape can be refined.
+    #[serde(default)]
+    pub refine: bool,
+    /// Joins saddle cells in marching squares (two opposite corners inside)
+    /// by sampling the shape in the middle of the cell, rather than by
+    /// interpolating between its corners.  Like `refine`, this only applies
+    /// to fields that were defined by a shape.
+    #[serde(default)]
+    pub sample_centers: bool,
+    #[serde(default)]
+    pub stitch: StitchOptions<S>,
+    /// Lines to add inside of the shape.
+    #[serde(default)]
+    pub infill: Option<Infill<S>>,
+}
+
+impl<S: Scalar> ExportOptions<S> {
+    pub fn try_map<T, E, F>(&self, f: &mut F) -> Result<ExportOptions<T>, E>
+    where
+        T: Scalar,
+        F: FnMut(S) -> Result<T, E>,
+    {
+        let stitch = &self.stitch;
+        Ok(ExportOptions {
+            contouring: self.contouring,
+            refine: self.refine,
+            sample_centers: self.sample_centers,
+            stitch: StitchOptions {
+                by_edge: stitch.by_edge,
+                epsilon: f(stitch.epsilon)?,
+                sort: stitch.sort,
+                remove_zero_area_loops: stitch.remove_zero_area_loops,
+                prune: stitch.prune,
+                connect_obvious: stitch.connect_obvious,
+                graph_stitch: stitch.graph_stitch,
+                oriented: stitch.oriented,
+                simplify: match stitch.simplify {
+                    Some(ref simplify) => Some(SimplifyOptions {
+                        method: simplify.method,
+                        tolerance: f(simplify.tolerance)?,
+                        preserve_topology: simplify.preserve_topology,
+                    }),
+                    None => None,
+                },
+            },
+            infill: match self.infill {
+                Some(Infill::Hatch { angle, spacing }) => Some(Infill::Hatch {
+                    angle: f(angle)?,
+                    spacing: f(spacing)?,
+                }),
+                Some(Infill::CrossHatch { angle, spacing }) => Some(Infill::CrossHatch {
+                    angle: f(angle)?,
+                    spacing: f(spacing)?,
+                }),
+                Some(Infill::Concentric { spacing }) => Some(Infill::Concentric {
+                    spacing: f(spacing)?,
+                }),
+                None => None,
+            },
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Command<S: Scalar = f32> {
+    Concurrently(Vec<Command<S>>),
+    Serially(Vec<Command<S>>),
+    Define(Id, Value<S>),
     Freeze {
         target: Id,
         id: Id,
@@ -106,14 +669,219 @@ pub enum Command {
     Drag {
         target: Id,
         id: Id,
-        dx: f32,
-        dy: f32,
+        dx: S,
+        dy: S,
     },
     Export(Id),
-    Simplex(Id, Simplex),
+    /// Like `Export`, but with options for how the field is traced.
+    ExportWith(Id, ExportOptions<S>),
+    Simplex(Id, Simplex<S>),
+    /// Splices in the commands of the program at `path`, with every id that
+    /// it uses put under `namespace`.  Includes are resolved when a program
+    /// is loaded, relative to the file that it was loaded from.
+    Include {
+        path: String,
+        namespace: String,
+    },
+}
+
+impl Command {
+    /// Builds a program that exports `steps` shapes morphing from `a` to `b`.
+    /// `a` and `b` are defined as `first_id` and `first_id + 1`, and the
+    /// exports use the ids after them, in order.
+    pub fn morph_sweep(a: Shape, b: Shape, steps: u32, first_id: u32) -> Command {
+        let (a_id, b_id) = (Id::from(first_id), Id::from(first_id + 1));
+        let mut commands = vec![
+            Command::Define(a_id.clone(), Value::BasicShape(a)),
+            Command::Define(b_id.clone(), Value::BasicShape(b)),
+        ];
+        for i in 0..steps {
+            let id = Id::from(first_id + 2 + i);
+            let t = if steps > 1 {
+                i as f32 / (steps - 1) as f32
+            } else {
+                0.0
+            };
+            let morph = Shape::Morph(
+                Box::new(Shape::Terminal(Terminal::Field(a_id.clone()))),
+                Box::new(Shape::Terminal(Terminal::Field(b_id.clone()))),
+                t,
+            );
+            commands.push(Command::Define(id.clone(), Value::BasicShape(morph)));
+            commands.push(Command::Export(id));
+        }
+        Command::Serially(commands)
+    }
+
+    /// The first id that's defined by a shape, polygon, or noise with a
+    /// matrix that can't be inverted, if any.
+    pub fn singular_matrix(&self) -> Option<Id> {
+        match self {
+            Command::Concurrently(commands) | Command::Serially(commands) => {
+                commands.iter().find_map(|c| c.singular_matrix())
+            }
+            Command::Define(id, Value::BasicShape(shape)) if shape.has_singular_matrix() => {
+                Some(id.clone())
+            }
+            Command::Define(id, Value::Polygon(Polygon { matrix, .. }))
+            | Command::Simplex(id, Simplex { matrix, .. })
+                if matrix.inverse().is_none() =>
+            {
+                Some(id.clone())
+            }
+            _ => None,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+impl<S: Scalar> Command<S> {
+    /// The same command with each of the numbers in it replaced by `f` of
+    /// it, or the first error that `f` returns.
+    pub fn try_map<T, E, F>(&self, f: &mut F) -> Result<Command<T>, E>
+    where
+        T: Scalar,
+        F: FnMut(S) -> Result<T, E>,
+    {
+        Ok(match self {
+            Command::Concurrently(commands) => Command::Concurrently(
+                commands
+                    .iter()
+                    .map(|c| c.try_map(f))
+                    .collect::<Result<_, E>>()?,
+            ),
+            Command::Serially(commands) => Command::Serially(
+                commands
+                    .iter()
+                    .map(|c| c.try_map(f))
+                    .collect::<Result<_, E>>()?,
+            ),
+            Command::Define(id, Value::BasicShape(shape)) => {
+                Command::Define(id.clone(), Value::BasicShape(shape.try_map(f)?))
+            }
+            Command::Define(id, Value::Polygon(polygon)) => {
+                Command::Define(id.clone(), Value::Polygon(polygon.try_map(f)?))
+            }
+            Command::Freeze { target, id } => Command::Freeze {
+                target: target.clone(),
+                id: id.clone(),
+            },
+            Command::Drag { target, id, dx, dy } => Command::Drag {
+                target: target.clone(),
+                id: id.clone(),
+                dx: f(*dx)?,
+                dy: f(*dy)?,
+            },
+            Command::Export(id) => Command::Export(id.clone()),
+            Command::ExportWith(id, options) => {
+                Command::ExportWith(id.clone(), options.try_map(f)?)
+            }
+            Command::Simplex(id, simplex) => Command::Simplex(id.clone(), simplex.try_map(f)?),
+            Command::Include { path, namespace } => Command::Include {
+                path: path.clone(),
+                namespace: namespace.clone(),
+            },
+        })
+    }
+
+    /// The command with every id that it defines or reads from put under
+    /// `namespace`, as it is when its program is included.
+    pub fn scoped(&self, namespace: &str) -> Command<S> {
+        let mut command = self.clone();
+        command.scope(namespace);
+        command
+    }
+
+    fn scope(&mut self, namespace: &str) {
+        match self {
+            Command::Concurrently(commands) | Command::Serially(commands) => {
+                for command in commands {
+                    command.scope(namespace);
+                }
+            }
+            Command::Define(id, value) => {
+                *id = id.scoped(namespace);
+                if let Value::BasicShape(shape) = value {
+                    shape.scope(namespace);
+                }
+            }
+            Command::Freeze { target, id } | Command::Drag { target, id, .. } => {
+                *target = target.scoped(namespace);
+                *id = id.scoped(namespace);
+            }
+            Command::Export(id) | Command::ExportWith(id, _) | Command::Simplex(id, _) => {
+                *id = id.scoped(namespace)
+            }
+            Command::Include {
+                namespace: inner, ..
+            } => *inner = id::scoped_name(namespace, inner),
+        }
+    }
+
+    /// Every id that the command defines, in order, including any that are
+    /// defined more than once.
+    pub fn defined_ids(&self) -> Vec<Id> {
+        fn collect<S: Scalar>(command: &Command<S>, out: &mut Vec<Id>) {
+            match command {
+                Command::Concurrently(commands) | Command::Serially(commands) => {
+                    for command in commands {
+                        collect(command, out);
+                    }
+                }
+                Command::Define(id, _)
+                | Command::Freeze { id, .. }
+                | Command::Drag { id, .. }
+                | Command::Simplex(id, _) => out.push(id.clone()),
+                Command::Export(_) | Command::ExportWith(..) | Command::Include { .. } => {}
+            }
+        }
+        let mut out = vec![];
+        collect(self, &mut out);
+        out
+    }
+
+    /// The first id that is defined twice, if any.  Fields can't be
+    /// redefined, since that would make which definition a read sees depend
+    /// on the order that concurrent commands run in.
+    pub fn duplicate_id(&self) -> Option<Id> {
+        let mut seen = ::std::collections::HashSet::new();
+        self.defined_ids()
+            .into_iter()
+            .find(|id| !seen.insert(id.clone()))
+    }
+
+    /// The first id that is read before anything defines it, if any.
+    /// Commands run in the order that they're listed, concurrent or not.
+    /// Includes should be resolved first, since the ids that they define
+    /// aren't known until they are.
+    pub fn undefined_id(&self) -> Option<Id> {
+        fn check<S: Scalar>(
+            command: &Command<S>,
+            defined: &mut ::std::collections::HashSet<Id>,
+        ) -> Option<Id> {
+            let reads = match command {
+                Command::Concurrently(commands) | Command::Serially(commands) => {
+                    return commands.iter().find_map(|c| check(c, defined));
+                }
+                Command::Define(_, Value::BasicShape(shape)) => shape.fields(),
+                Command::Freeze { target, .. } | Command::Drag { target, .. } => {
+                    vec![target.clone()]
+                }
+                Command::Export(id) | Command::ExportWith(id, _) => vec![id.clone()],
+                Command::Define(_, Value::Polygon(_))
+                | Command::Simplex(..)
+                | Command::Include { .. } => vec![],
+            };
+            if let Some(id) = reads.into_iter().find(|id| !defined.contains(id)) {
+                return Some(id);
+            }
+            defined.extend(command.defined_ids());
+            None
+        }
+        check(self, &mut ::std::collections::HashSet::new())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub struct Bbox {
     pub x: f32,
     pub y: f32,
@@ -121,28 +889,84 @@ pub struct Bbox {
     pub h: f32,
 }
 
-fn transcode_point<'de, D>(deserializer: D) -> Result<Vec<Point>, D::Error>
+/// The region of world space that a program is rendered over, and how many
+/// pixels are used per world unit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Viewport {
+    pub bbox: Bbox,
+    pub resolution: f32,
+}
+
+impl Viewport {
+    /// A viewport covering `[0, width] x [0, height]` at one pixel per unit.
+    pub fn from_dimensions(width: f32, height: f32) -> Viewport {
+        Viewport {
+            bbox: Bbox {
+                x: 0.0,
+                y: 0.0,
+                w: width,
+                h: height,
+            },
+            resolution: 1.0,
+        }
+    }
+
+    /// The size of the fields that the viewport is rendered into.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (
+            (self.bbox.w * self.resolution).ceil() as u32,
+            (self.bbox.h * self.resolution).ceil() as u32,
+        )
+    }
+
+    pub fn world_to_pixel(&self) -> Matrix {
+        Matrix::create_translation(-self.bbox.x, -self.bbox.y)
+            .post_scale(self.resolution, self.resolution)
+    }
+
+    pub fn pixel_to_world(&self) -> Matrix {
+        Matrix::create_scale(1.0 / self.resolution, 1.0 / self.resolution)
+            .post_translate(::euclid::vec2(self.bbox.x, self.bbox.y))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Program<S: Scalar = f32> {
+    pub command: Command<S>,
+    pub viewport: Viewport,
+}
+
+fn serialize_points<T, S>(points: &[Point2D<T>], serializer: S) -> Result<S::Ok, S::Error>
 where
+    T: Copy + Serialize,
+    S: Serializer,
+{
+    serializer.collect_seq(points.iter().map(|p| PointDef { x: p.x, y: p.y }))
+}
+
+fn transcode_point<'de, T, D>(deserializer: D) -> Result<Vec<Point2D<T>>, D::Error>
+where
+    T: Copy + Deserialize<'de>,
     D: Deserializer<'de>,
 {
     use serde::de::{SeqAccess, Visitor};
     use std::fmt;
     use std::marker::PhantomData;
 
-    struct PointVisitor(PhantomData<fn() -> Vec<Point>>);
-    impl<'de> Visitor<'de> for PointVisitor {
-        type Value = Vec<Point>;
+    struct PointVisitor<T>(PhantomData<fn() -> Vec<Point2D<T>>>);
+    impl<'de, T: Copy + Deserialize<'de>> Visitor<'de> for PointVisitor<T> {
+        type Value = Vec<Point2D<T>>;
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
             formatter.write_str("a list of points")
         }
 
-        fn visit_seq<S>(self, mut seq: S) -> Result<Vec<Point>, S::Error>
+        fn visit_seq<S>(self, mut seq: S) -> Result<Vec<Point2D<T>>, S::Error>
         where
             S: SeqAccess<'de>,
         {
             let mut out = vec![];
             while let Some(value) = seq.next_element()? {
-                let v: PointDef = value;
+                let v: PointDef<T> = value;
                 out.push(v.into());
             }
 
@@ -153,3 +977,159 @@ where
     let visitor = PointVisitor(PhantomData);
     deserializer.deserialize_seq(visitor)
 }
+
+#[test]
+fn scopes_included_commands() {
+    let field = |id: &str| -> Box<Shape> { Box::new(Shape::Terminal(Terminal::Field(id.into()))) };
+    let command = Command::Serially(vec![
+        Command::Define("gear".into(), Value::BasicShape(Shape::Not(field("hole")))),
+        Command::Freeze {
+            target: "gear".into(),
+            id: 1.into(),
+        },
+        Command::Include {
+            path: "tooth.shape".into(),
+            namespace: "tooth".into(),
+        },
+        Command::Export(1.into()),
+    ]);
+    let expected = Command::Serially(vec![
+        Command::Define(
+            "a.gear".into(),
+            Value::BasicShape(Shape::Not(field("a.hole"))),
+        ),
+        Command::Freeze {
+            target: "a.gear".into(),
+            id: "a.1".into(),
+        },
+        Command::Include {
+            path: "tooth.shape".into(),
+            namespace: "a.tooth".into(),
+        },
+        Command::Export("a.1".into()),
+    ]);
+    assert_eq!(command.scoped("a"), expected);
+    assert_eq!(command.duplicate_id(), None);
+}
+
+#[test]
+fn finds_duplicate_ids() {
+    let define = |id: &str| -> Command {
+        Command::Simplex(
+            id.into(),
+            Simplex {
+                cutoff: 0.0,
+                matrix: Matrix::identity(),
+            },
+        )
+    };
+    let command = Command::Serially(vec![
+        define("a"),
+        Command::Concurrently(vec![define("b"), define("c")]),
+        Command::Freeze {
+            target: "b".into(),
+            id: "c".into(),
+        },
+    ]);
+    assert_eq!(command.defined_ids().len(), 4);
+    assert_eq!(command.duplicate_id(), Some("c".into()));
+}
+
+#[test]
+fn finds_ids_that_are_read_before_they_are_defined() {
+    let circle = Shape::Terminal(Terminal::Circle(Circle {
+        x: 0.0,
+        y: 0.0,
+        r: 1.0,
+    }));
+    let define =
+        |id: &str, shape: &Shape| Command::Define(id.into(), Value::BasicShape(shape.clone()));
+    let reads_a = Shape::Not(Box::new(Shape::Terminal(Terminal::Field("a".into()))));
+
+    let command = Command::Serially(vec![
+        define("a", &circle),
+        Command::Concurrently(vec![define("b", &reads_a), Command::Export("b".into())]),
+    ]);
+    assert_eq!(command.undefined_id(), None);
+
+    let command = Command::Serially(vec![define("b", &reads_a), define("a", &circle)]);
+    assert_eq!(command.undefined_id(), Some("a".into()));
+    let command: Command = Command::Freeze {
+        target: "a".into(),
+        id: "a".into(),
+    };
+    assert_eq!(command.undefined_id(), Some("a".into()));
+    let command: Command = Command::Export("x".into());
+    assert_eq!(command.undefined_id(), Some("x".into()));
+}
+
+#[test]
+fn export_options_default_to_marching() {
+    let parse = |json: &str| serde_json::from_str::<Command>(json).unwrap();
+    assert_eq!(
+        parse(r#"{"ExportWith": [2, {}]}"#),
+        Command::ExportWith(2.into(), ExportOptions::default())
+    );
+    assert_eq!(
+        parse(r#"{"ExportWith": ["gear", {"contouring": "Dual"}]}"#),
+        Command::ExportWith(
+            "gear".into(),
+            ExportOptions {
+                contouring: Contouring::Dual,
+                refine: false,
+                sample_centers: false,
+                stitch: StitchOptions::default(),
+                infill: None,
+            }
+        )
+    );
+    assert_eq!(
+        parse(r#"{"ExportWith": ["gear", {"refine": true}]}"#),
+        Command::ExportWith(
+            "gear".into(),
+            ExportOptions {
+                contouring: Contouring::Marching,
+                refine: true,
+                sample_centers: false,
+                stitch: StitchOptions::default(),
+                infill: None,
+            }
+        )
+    );
+}
+
+#[test]
+fn stitch_options_fill_in_their_defaults() {
+    let options: ExportOptions = serde_json::from_str(
+        r#"{"stitch": {"by_edge": false, "epsilon": 0.01, "simplify": {"tolerance": 0.5}}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        options.stitch,
+        StitchOptions {
+            by_edge: false,
+            epsilon: 0.01,
+            simplify: Some(SimplifyOptions {
+                method: SimplifyMethod::DouglasPeucker,
+                tolerance: 0.5,
+                preserve_topology: false,
+            }),
+            ..StitchOptions::default()
+        }
+    );
+}
+
+#[test]
+fn infill_is_tagged_by_kind() {
+    let options: ExportOptions =
+        serde_json::from_str(r#"{"infill": {"CrossHatch": {"angle": 0.5, "spacing": 2}}}"#)
+            .unwrap();
+    assert_eq!(
+        options.infill,
+        Some(Infill::CrossHatch {
+            angle: 0.5,
+            spacing: 2.0,
+        })
+    );
+    assert_eq!(<ExportOptions>::default().infill, None);
+}
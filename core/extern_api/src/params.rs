@@ -0,0 +1,590 @@
+//! Programs with named numeric parameters.
+//!
+//! Anywhere that a command has a number (a radius, an offset, a matrix
+//! entry, a point of a polygon, the vector of a drag, the cutoff of noise, or
+//! an export option such as the spacing of infill), a template can name a
+//! parameter instead.  Only the viewport is fixed.  Every parameter gets a
+//! default value in the template's `params`:
+//!
+//! ```json
+//! {
+//!   "params": {"radius": 5},
+//!   "viewport": {...},
+//!   "command": {"Define": [0, {"BasicShape": {"Terminal": {"Circle":
+//!     {"x": 10, "y": 10, "r": {"Param": "radius"}}}}}]}
+//! }
+//! ```
+//!
+//! MessagePack templates are laid out the same way, by position.  In the
+//! s-expression layout a parameter is written `(Param radius)`, and the
+//! defaults follow the size of the program as a list of `(name value)`
+//! pairs.
+//!
+//! `Template::resolve` fills the parameters in, overriding whichever
+//! defaults it is given values for, and produces an ordinary `Program`.
+//! Shapes can also be compiled with their parameters left in, so that
+//! changing a value only means running them again.
+
+use crate::{Command, Id, Program, Scalar, Viewport};
+use euclid::num::{One, Zero};
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Values for parameters, by name.  Ordered, so that equal maps serialize
+/// (and hash) the same.
+pub type Params = BTreeMap<String, f32>;
+
+const REFERENCE_KEY: &str = "Param";
+
+/// The name of a parameter.  Names are interned, so that numbers that refer
+/// to parameters are as cheap to copy as any other.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Name(&'static str);
+
+impl Name {
+    pub fn new(name: &str) -> Name {
+        // Only ever grows by the number of distinct names that are used.
+        static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+        let mut names = NAMES.lock().unwrap_or_else(|e| e.into_inner());
+        match names.get(name) {
+            Some(interned) => Name(interned),
+            None => {
+                let interned: &'static str = Box::leak(name.to_string().into_boxed_str());
+                names.insert(interned);
+                Name(interned)
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A number in a template: either known up front, or the value of a
+/// parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Float {
+    Value(f32),
+    Param(Name),
+}
+
+impl Float {
+    pub fn resolve(self, values: &Params) -> Result<f32, ParamError> {
+        match self {
+            Float::Value(v) => Ok(v),
+            Float::Param(name) => values
+                .get(name.as_str())
+                .cloned()
+                .ok_or_else(|| ParamError::Unknown(name.to_string())),
+        }
+    }
+}
+
+impl From<f32> for Float {
+    fn from(v: f32) -> Float {
+        Float::Value(v)
+    }
+}
+
+impl One for Float {
+    fn one() -> Float {
+        Float::Value(1.0)
+    }
+}
+
+impl Zero for Float {
+    fn zero() -> Float {
+        Float::Value(0.0)
+    }
+}
+
+impl Scalar for Float {
+    fn value(self) -> Option<f32> {
+        match self {
+            Float::Value(v) => Some(v),
+            Float::Param(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamError {
+    /// A value was given for, or a reference was made to, a parameter
+    /// without a default.
+    Unknown(String),
+    /// The parameters make a matrix in the definition of this id that can't
+    /// be inverted, such as a scale by zero.
+    Singular(Id),
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamError::Unknown(name) => write!(f, "unknown parameter {:?}", name),
+            ParamError::Singular(id) => {
+                write!(f, "the definition of {} has a matrix with no inverse", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+/// A program whose numbers can be parameters.  The defaults come last, so
+/// that MessagePack templates without any can leave them off.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Template {
+    pub command: Command<Float>,
+    pub viewport: Viewport,
+    /// Every parameter that the template takes, with its default value.
+    #[serde(rename = "params", default, skip_serializing_if = "Params::is_empty")]
+    pub defaults: Params,
+}
+
+impl Template {
+    /// A template without any parameters.
+    pub fn from_program(program: &Program) -> Template {
+        let command = program
+            .command
+            .try_map(&mut |v| Ok::<_, Infallible>(Float::Value(v)))
+            .unwrap_or_else(|never| match never {});
+        Template {
+            command,
+            viewport: program.viewport.clone(),
+            defaults: Params::new(),
+        }
+    }
+
+    /// The value of every parameter: the defaults, overridden by `params`.
+    pub fn values(&self, params: &Params) -> Result<Params, ParamError> {
+        if let Some(name) = params.keys().find(|k| !self.defaults.contains_key(*k)) {
+            return Err(ParamError::Unknown(name.clone()));
+        }
+        let mut values = self.defaults.clone();
+        values.extend(params.iter().map(|(k, v)| (k.clone(), *v)));
+        Ok(values)
+    }
+
+    /// The program with every parameter filled in.  Fails if a parameter
+    /// is unknown, or if the values make a matrix that can't be inverted.
+    pub fn resolve(&self, params: &Params) -> Result<Program, ParamError> {
+        let values = self.values(params)?;
+        let command = self.command.try_map(&mut |f| f.resolve(&values))?;
+        if let Some(id) = command.singular_matrix() {
+            return Err(ParamError::Singular(id));
+        }
+        Ok(Program {
+            command,
+            viewport: self.viewport.clone(),
+        })
+    }
+}
+
+impl Serialize for Float {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Float::Value(v) => serializer.serialize_f32(*v),
+            Float::Param(name) => {
+                serializer.serialize_newtype_variant("Float", 0, REFERENCE_KEY, name.as_str())
+            }
+        }
+    }
+}
+
+// A parameter is read from whatever its format makes of a newtype variant:
+// `{"Param": name}` in json, `[0, [name]]` in MessagePack, and
+// `(Param name)` in s-expressions.
+impl<'de> Deserialize<'de> for Float {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Float, D::Error> {
+        deserializer.deserialize_any(FloatVisitor)
+    }
+}
+
+// Derived impls need every element of a struct that is written as a
+// sequence, so this is written out to let the defaults be left off.
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Template, D::Error> {
+        deserializer.deserialize_struct(
+            "Template",
+            &["command", "viewport", "params"],
+            TemplateVisitor,
+        )
+    }
+}
+
+struct TemplateVisitor;
+
+impl<'de> Visitor<'de> for TemplateVisitor {
+    type Value = Template;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct Template")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Template, A::Error> {
+        let command = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let viewport = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let defaults = seq.next_element()?.unwrap_or_default();
+        Ok(Template {
+            command,
+            viewport,
+            defaults,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Template, A::Error> {
+        let (mut command, mut viewport, mut defaults) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "command" => command = Some(map.next_value()?),
+                "viewport" => viewport = Some(map.next_value()?),
+                "params" => defaults = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Template {
+            command: command.ok_or_else(|| de::Error::missing_field("command"))?,
+            viewport: viewport.ok_or_else(|| de::Error::missing_field("viewport"))?,
+            defaults: defaults.unwrap_or_default(),
+        })
+    }
+}
+
+struct FloatVisitor;
+
+impl<'de> Visitor<'de> for FloatVisitor {
+    type Value = Float;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a number or a parameter")
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Float, E> {
+        Ok(Float::Value(v as f32))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Float, E> {
+        Ok(Float::Value(v as f32))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Float, E> {
+        Ok(Float::Value(v as f32))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Float, A::Error> {
+        let _: Tag = map
+            .next_key()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let name: ParamName = map.next_value()?;
+        if map.next_key::<Tag>()?.is_some() {
+            return Err(de::Error::invalid_length(2, &self));
+        }
+        Ok(Float::Param(name.0))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Float, A::Error> {
+        let _: Tag = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let name: ParamName = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        if seq.next_element::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(3, &self));
+        }
+        Ok(Float::Param(name.0))
+    }
+}
+
+// `Param`, or its index in formats that write variants by position.
+struct Tag;
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tag, D::Error> {
+        struct TagVisitor;
+        impl<'de> Visitor<'de> for TagVisitor {
+            type Value = Tag;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{:?}", REFERENCE_KEY)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Tag, E> {
+                match v {
+                    0 => Ok(Tag),
+                    _ => Err(E::invalid_value(de::Unexpected::Unsigned(v), &self)),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Tag, E> {
+                match v {
+                    REFERENCE_KEY => Ok(Tag),
+                    _ => Err(E::unknown_variant(v, &[REFERENCE_KEY])),
+                }
+            }
+        }
+        deserializer.deserialize_any(TagVisitor)
+    }
+}
+
+// A name, or a list of just a name, as MessagePack writes the arguments of
+// a variant.
+struct ParamName(Name);
+
+impl<'de> Deserialize<'de> for ParamName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ParamName, D::Error> {
+        struct NameVisitor;
+        impl<'de> Visitor<'de> for NameVisitor {
+            type Value = ParamName;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("the name of a parameter")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<ParamName, E> {
+                Ok(ParamName(Name::new(v)))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ParamName, A::Error> {
+                let name: String = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                if seq.next_element::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(2, &self));
+                }
+                Ok(ParamName(Name::new(&name)))
+            }
+        }
+        deserializer.deserialize_any(NameVisitor)
+    }
+}
+
+#[cfg(test)]
+const RING: &str = r#"{
+    "params": {"outer": 10, "inner": 5, "dx": 0},
+    "viewport": {"bbox": {"x": 0, "y": 0, "w": 40, "h": 40}, "resolution": 1},
+    "command": {"Serially": [
+        {"Define": [0, {"BasicShape": {"Intersection": [
+            {"Terminal": {"Circle": {"x": 20, "y": 20, "r": {"Param": "outer"}}}},
+            {"Not": {"Terminal": {"Circle": {"x": 20, "y": 20, "r": {"Param": "inner"}}}}}
+        ]}}]},
+        {"Define": [1, {"BasicShape": {"Transform": [
+            {"Terminal": {"Field": 0}},
+            {"m11": 1, "m12": 0, "m21": 0, "m22": 1, "m31": {"Param": "dx"}, "m32": 0}
+        ]}}]},
+        {"Drag": {"target": 1, "id": 2, "dx": {"Param": "dx"}, "dy": 0}},
+        {"Export": 2}
+    ]}
+}"#;
+
+#[test]
+fn fills_in_defaults_and_overrides() {
+    use crate::*;
+
+    let template: Template = serde_json::from_str(RING).unwrap();
+    assert_eq!(template.defaults.len(), 3);
+
+    let circle = |program: &Program| match &program.command {
+        Command::Serially(commands) => match &commands[0] {
+            Command::Define(_, Value::BasicShape(Shape::Intersection(shapes))) => shapes[0].clone(),
+            other => panic!("unexpected command {:?}", other),
+        },
+        other => panic!("unexpected command {:?}", other),
+    };
+    let outer = |r| {
+        Shape::Terminal(Terminal::Circle(Circle {
+            x: 20.0,
+            y: 20.0,
+            r,
+        }))
+    };
+
+    let program = template.resolve(&Params::new()).unwrap();
+    assert_eq!(circle(&program), outer(10.0));
+
+    let mut params = Params::new();
+    params.insert("outer".into(), 15.0);
+    params.insert("dx".into(), 3.0);
+    let program = template.resolve(&params).unwrap();
+    assert_eq!(circle(&program), outer(15.0));
+    match &program.command {
+        Command::Serially(commands) => {
+            assert_eq!(
+                commands[1],
+                Command::Define(
+                    1.into(),
+                    Value::BasicShape(Shape::Transform(
+                        Box::new(Shape::Terminal(Terminal::Field(0.into()))),
+                        Matrix::create_translation(3.0, 0.0)
+                    ))
+                )
+            );
+            assert_eq!(
+                commands[2],
+                Command::Drag {
+                    target: 1.into(),
+                    id: 2.into(),
+                    dx: 3.0,
+                    dy: 0.0
+                }
+            );
+        }
+        other => panic!("unexpected command {:?}", other),
+    }
+}
+
+#[test]
+fn polygons_noise_and_export_options_take_parameters() {
+    use crate::*;
+
+    let template: Template = serde_json::from_str(
+        r#"{
+        "params": {"w": 4, "cutoff": 0.5, "spacing": 2},
+        "viewport": {"bbox": {"x": 0, "y": 0, "w": 10, "h": 10}, "resolution": 1},
+        "command": {"Serially": [
+            {"Define": [0, {"Polygon": {
+                "points": [{"x": 0, "y": 0}, {"x": {"Param": "w"}, "y": 0}, {"x": 0, "y": 3}],
+                "matrix": {"m11": {"Param": "w"}, "m12": 0, "m21": 0, "m22": 1, "m31": 0, "m32": 0}
+            }}]},
+            {"Simplex": [1, {
+                "cutoff": {"Param": "cutoff"},
+                "matrix": {"m11": 1, "m12": 0, "m21": 0, "m22": 1, "m31": 0, "m32": 0}
+            }]},
+            {"ExportWith": [0, {
+                "stitch": {"epsilon": {"Param": "cutoff"}},
+                "infill": {"Hatch": {"angle": 0, "spacing": {"Param": "spacing"}}}
+            }]}
+        ]}
+    }"#,
+    )
+    .unwrap();
+
+    let mut params = Params::new();
+    params.insert("w".into(), 6.0);
+    params.insert("spacing".into(), 1.5);
+    let program = template.resolve(&params).unwrap();
+    let commands = match program.command {
+        Command::Serially(commands) => commands,
+        other => panic!("unexpected command {:?}", other),
+    };
+    assert_eq!(
+        commands[0],
+        Command::Define(
+            0.into(),
+            Value::Polygon(Polygon {
+                points: vec![
+                    euclid::point2(0.0, 0.0),
+                    euclid::point2(6.0, 0.0),
+                    euclid::point2(0.0, 3.0),
+                ],
+                matrix: Matrix::create_scale(6.0, 1.0),
+            })
+        )
+    );
+    assert_eq!(
+        commands[1],
+        Command::Simplex(
+            1.into(),
+            Simplex {
+                cutoff: 0.5,
+                matrix: Matrix::identity(),
+            }
+        )
+    );
+    assert_eq!(
+        commands[2],
+        Command::ExportWith(
+            0.into(),
+            ExportOptions {
+                stitch: StitchOptions {
+                    epsilon: 0.5,
+                    ..StitchOptions::default()
+                },
+                infill: Some(Infill::Hatch {
+                    angle: 0.0,
+                    spacing: 1.5,
+                }),
+                ..ExportOptions::default()
+            }
+        )
+    );
+
+    let sexp = crate::sexp::to_sexp(&template).unwrap();
+    assert_eq!(crate::sexp::from_sexp::<Template>(&sexp).unwrap(), template);
+}
+
+#[test]
+fn rejects_unknown_parameters() {
+    let template: Template = serde_json::from_str(RING).unwrap();
+    let mut params = Params::new();
+    params.insert("outr".into(), 15.0);
+    match template.resolve(&params) {
+        Err(ParamError::Unknown(name)) => assert_eq!(name, "outr"),
+        other => panic!("expected an unknown parameter, got {:?}", other),
+    }
+
+    let missing = RING.replace(r#""inner": 5, "#, "");
+    let template: Template = serde_json::from_str(&missing).unwrap();
+    match template.resolve(&Params::new()) {
+        Err(ParamError::Unknown(name)) => assert_eq!(name, "inner"),
+        other => panic!("expected an unknown parameter, got {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_parameters_that_make_a_matrix_singular() {
+    let template: Template = serde_json::from_str(&RING.replace(
+        r#""m11": 1, "m12": 0"#,
+        r#""m11": {"Param": "dx"}, "m12": 0"#,
+    ))
+    .unwrap();
+    let mut params = Params::new();
+    params.insert("dx".into(), 2.0);
+    assert!(template.resolve(&params).is_ok());
+    assert_eq!(
+        template.resolve(&Params::new()),
+        Err(ParamError::Singular(1.into()))
+    );
+}
+
+#[test]
+fn round_trips_through_serde() {
+    let template: Template = serde_json::from_str(RING).unwrap();
+    let text = serde_json::to_string(&template).unwrap();
+    assert_eq!(serde_json::from_str::<Template>(&text).unwrap(), template);
+
+    let sexp = crate::sexp::to_sexp(&template).unwrap();
+    assert_eq!(crate::sexp::from_sexp::<Template>(&sexp).unwrap(), template);
+}
+
+#[test]
+fn parameters_are_written_as_variants() {
+    let r = Float::Param(Name::new("r"));
+    assert_eq!(serde_json::to_string(&r).unwrap(), r#"{"Param":"r"}"#);
+    assert_eq!(crate::sexp::to_string(&r).unwrap(), "(Param r)");
+    assert_eq!(serde_json::to_string(&Float::Value(2.5)).unwrap(), "2.5");
+    assert_eq!(Name::new("r"), Name::new(&"rr"[1..]));
+}
@@ -0,0 +1,742 @@
+//! Reads and writes values in the s-expression layout produced by OCaml's
+//! `ppx_sexp_conv`, which is what `.shape` programs are written in.
+//!
+//! * structs are lists of `(field value)` pairs
+//! * enum variants are `(Name args...)`, with the fields of struct variants
+//!   inlined as `(field value)` pairs
+//! * `None` is `()` and `Some(x)` is `(x)`
+//! * tuples and sequences are `(a b c)`
+
+use serde::de::{self, Deserialize, Expected, Visitor};
+use serde::ser::{self, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+pub fn to_sexp<T: Serialize + ?Sized>(value: &T) -> Result<Sexp, Error> {
+    value.serialize(Serializer)
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    to_sexp(value).map(|sexp| sexp.to_string())
+}
+
+/// Reads a value back out of the layout that `to_sexp` writes.  Unlike
+/// `snoot`, this can read types that decide what they are by looking at the
+/// input, such as `Id`s and template numbers.
+pub fn from_sexp<'a, T: Deserialize<'a>>(sexp: &'a Sexp) -> Result<T, Error> {
+    T::deserialize(sexp)
+}
+
+impl fmt::Display for Sexp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sexp::Atom(atom) => {
+                let needs_quotes = atom.is_empty()
+                    || atom
+                        .chars()
+                        .any(|c| c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == ';');
+                if needs_quotes {
+                    write!(f, "{:?}", atom)
+                } else {
+                    f.write_str(atom)
+                }
+            }
+            Sexp::List(items) => {
+                f.write_str("(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+fn atom<T: ToString>(value: T) -> Sexp {
+    Sexp::Atom(value.to_string())
+}
+
+fn field(name: &str, value: Sexp) -> Sexp {
+    Sexp::List(vec![atom(name), value])
+}
+
+struct Serializer;
+
+struct SeqSerializer {
+    items: Vec<Sexp>,
+}
+
+struct MapSerializer {
+    items: Vec<Sexp>,
+    key: Option<Sexp>,
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Sexp;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = SeqSerializer;
+    type SerializeStructVariant = SeqSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_i16(self, v: i16) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_char(self, v: char) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_str(self, v: &str) -> Result<Sexp, Error> {
+        Ok(atom(v))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Sexp, Error> {
+        Ok(Sexp::List(v.iter().map(atom).collect()))
+    }
+    fn serialize_none(self) -> Result<Sexp, Error> {
+        Ok(Sexp::List(vec![]))
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Sexp, Error> {
+        Ok(Sexp::List(vec![to_sexp(value)?]))
+    }
+    fn serialize_unit(self) -> Result<Sexp, Error> {
+        Ok(Sexp::List(vec![]))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Sexp, Error> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Sexp, Error> {
+        Ok(atom(variant))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Sexp, Error> {
+        to_sexp(value)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Sexp, Error> {
+        Ok(Sexp::List(vec![atom(variant), to_sexp(value)?]))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: vec![atom(variant)],
+        })
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            items: vec![],
+            key: None,
+        })
+    }
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: vec![atom(variant)],
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Sexp;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(to_sexp(value)?);
+        Ok(())
+    }
+    fn end(self) -> Result<Sexp, Error> {
+        Ok(Sexp::List(self.items))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Sexp;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Sexp, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Sexp;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Sexp, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Sexp;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Sexp, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeStruct for SeqSerializer {
+    type Ok = Sexp;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.items.push(field(key, to_sexp(value)?));
+        Ok(())
+    }
+    fn end(self) -> Result<Sexp, Error> {
+        Ok(Sexp::List(self.items))
+    }
+}
+
+impl ser::SerializeStructVariant for SeqSerializer {
+    type Ok = Sexp;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+    fn end(self) -> Result<Sexp, Error> {
+        ser::SerializeStruct::end(self)
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Sexp;
+    type Error = Error;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(to_sexp(key)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error("map value without a key".into()))?;
+        self.items.push(Sexp::List(vec![key, to_sexp(value)?]));
+        Ok(())
+    }
+    fn end(self) -> Result<Sexp, Error> {
+        Ok(Sexp::List(self.items))
+    }
+}
+
+impl Sexp {
+    fn unexpected(&self) -> de::Unexpected<'_> {
+        match self {
+            Sexp::Atom(atom) => de::Unexpected::Str(atom),
+            Sexp::List(_) => de::Unexpected::Seq,
+        }
+    }
+
+    fn atom<E: Expected>(&self, expected: &E) -> Result<&str, Error> {
+        match self {
+            Sexp::Atom(atom) => Ok(atom),
+            Sexp::List(_) => Err(de::Error::invalid_type(self.unexpected(), expected)),
+        }
+    }
+
+    fn list<E: Expected>(&self, expected: &E) -> Result<&[Sexp], Error> {
+        match self {
+            Sexp::List(items) => Ok(items),
+            Sexp::Atom(_) => Err(de::Error::invalid_type(self.unexpected(), expected)),
+        }
+    }
+
+    fn parse<T: ::std::str::FromStr, E: Expected>(&self, expected: &E) -> Result<T, Error> {
+        let atom = self.atom(expected)?;
+        atom.parse()
+            .map_err(|_| de::Error::invalid_value(self.unexpected(), expected))
+    }
+}
+
+// Only atoms that start like a number are read as one, so that names such
+// as `inf` stay names.
+fn looks_numeric(atom: &str) -> bool {
+    match atom.chars().next() {
+        Some(c) => c.is_ascii_digit() || c == '-' || c == '+' || c == '.',
+        None => false,
+    }
+}
+
+macro_rules! deserialize_number {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                let v = self.parse(&visitor)?;
+                visitor.$visit(v)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for &'de Sexp {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Sexp::Atom(atom) if looks_numeric(atom) => {
+                if let Ok(v) = atom.parse::<u64>() {
+                    visitor.visit_u64(v)
+                } else if let Ok(v) = atom.parse::<i64>() {
+                    visitor.visit_i64(v)
+                } else if let Ok(v) = atom.parse::<f64>() {
+                    visitor.visit_f64(v)
+                } else {
+                    visitor.visit_borrowed_str(atom)
+                }
+            }
+            Sexp::Atom(atom) => visitor.visit_borrowed_str(atom),
+            Sexp::List(items) => visitor.visit_seq(SeqAccess(items.iter())),
+        }
+    }
+
+    deserialize_number! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let atom = self.atom(&visitor)?;
+        visitor.visit_borrowed_str(atom)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.list(&visitor)? {
+            [] => visitor.visit_none(),
+            [value] => visitor.visit_some(value),
+            _ => Err(de::Error::invalid_length(2, &visitor)),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.list(&visitor)? {
+            [] => visitor.visit_unit(),
+            items => Err(de::Error::invalid_length(items.len(), &visitor)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let items = self.list(&visitor)?;
+        visitor.visit_seq(SeqAccess(items.iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let pairs = self.list(&visitor)?;
+        visitor.visit_map(MapAccess {
+            pairs: pairs.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Sexp::Atom(variant) => visitor.visit_enum(EnumAccess(variant, &[])),
+            Sexp::List(items) => match items.split_first() {
+                Some((Sexp::Atom(variant), args)) => visitor.visit_enum(EnumAccess(variant, args)),
+                _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+            },
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+struct SeqAccess<'de>(::std::slice::Iter<'de, Sexp>);
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.0.next().map(|item| seed.deserialize(item)).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+// Structs and maps are lists of `(key value)` pairs.
+struct MapAccess<'de> {
+    pairs: ::std::slice::Iter<'de, Sexp>,
+    value: Option<&'de Sexp>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let pair = match self.pairs.next() {
+            Some(pair) => pair,
+            None => return Ok(None),
+        };
+        match pair {
+            Sexp::List(items) if items.len() == 2 => {
+                self.value = Some(&items[1]);
+                seed.deserialize(&items[0]).map(Some)
+            }
+            _ => Err(Error(format!(
+                "expected a (key value) pair, found {}",
+                pair
+            ))),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(Error("map value without a key".into())),
+        }
+    }
+}
+
+// A variant and the arguments that follow its name.
+struct EnumAccess<'de>(&'de str, &'de [Sexp]);
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = Error;
+    type Variant = VariantAccess<'de>;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantAccess<'de>), Error> {
+        let variant = seed.deserialize(de::value::BorrowedStrDeserializer::<Error>::new(self.0))?;
+        Ok((variant, VariantAccess(self.1)))
+    }
+}
+
+struct VariantAccess<'de>(&'de [Sexp]);
+
+impl<'de> de::VariantAccess<'de> for VariantAccess<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.0 {
+            [] => Ok(()),
+            args => Err(de::Error::invalid_length(args.len(), &"a unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.0 {
+            [value] => seed.deserialize(value),
+            args => Err(de::Error::invalid_length(args.len(), &"a newtype variant")),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(SeqAccess(self.0.iter()))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_map(MapAccess {
+            pairs: self.0.iter(),
+            value: None,
+        })
+    }
+}
+
+#[cfg(test)]
+#[derive(Serialize)]
+struct Record {
+    #[serde(rename = "two words")]
+    two_words: u32,
+    #[serde(rename = "(paren")]
+    paren: u32,
+    plain: Option<&'static str>,
+}
+
+#[cfg(test)]
+#[derive(Serialize)]
+enum Variants {
+    Unit,
+    Tuple(u32, f32),
+    Struct { a: u32, b: Option<u32> },
+}
+
+#[test]
+fn options_are_empty_or_single_item_lists() {
+    assert_eq!(to_string(&None::<u32>).unwrap(), "()");
+    assert_eq!(to_string(&Some(1)).unwrap(), "(1)");
+    assert_eq!(to_string(&Some(None::<u32>)).unwrap(), "(())");
+    assert_eq!(to_string(&Some(vec![1, 2])).unwrap(), "((1 2))");
+}
+
+#[test]
+fn tuples_and_variants_are_flat_lists() {
+    assert_eq!(to_string(&(1, "a", 2.5)).unwrap(), "(1 a 2.5)");
+    assert_eq!(to_string(&()).unwrap(), "()");
+    assert_eq!(to_string(&Variants::Unit).unwrap(), "Unit");
+    assert_eq!(to_string(&Variants::Tuple(1, 2.0)).unwrap(), "(Tuple 1 2)");
+    assert_eq!(
+        to_string(&Variants::Struct { a: 1, b: None }).unwrap(),
+        "(Struct (a 1) (b ()))"
+    );
+    assert_eq!(
+        to_string(&crate::Terminal::Circle(crate::Circle {
+            x: 1.0,
+            y: 2.0,
+            r: 0.5,
+        }))
+        .unwrap(),
+        "(Circle ((x 1) (y 2) (r 0.5)))"
+    );
+}
+
+#[test]
+fn atoms_that_would_not_read_back_are_quoted() {
+    let record = Record {
+        two_words: 1,
+        paren: 2,
+        plain: Some("say \"hi\"; bye"),
+    };
+    assert_eq!(
+        to_string(&record).unwrap(),
+        r#"(("two words" 1) ("(paren" 2) (plain ("say \"hi\"; bye")))"#
+    );
+    assert_eq!(to_string("").unwrap(), r#""""#);
+    assert_eq!(to_string("plain").unwrap(), "plain");
+}
+
+#[test]
+fn reads_back_what_it_writes() {
+    use crate::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Variants {
+        Unit,
+        Tuple(u32, f32),
+        Struct { a: u32, b: Option<u32> },
+    }
+
+    let values = vec![
+        Variants::Unit,
+        Variants::Tuple(1, -2.5),
+        Variants::Struct { a: 1, b: None },
+        Variants::Struct { a: 2, b: Some(3) },
+    ];
+    let sexp = to_sexp(&values).unwrap();
+    assert_eq!(from_sexp::<Vec<Variants>>(&sexp).unwrap(), values);
+
+    let command = Command::Serially(vec![
+        Command::Define(
+            "gear".into(),
+            Value::BasicShape(Shape::Transform(
+                Box::new(Shape::Terminal(Terminal::Field(0.into()))),
+                Matrix::create_translation(1.0, 2.0),
+            )),
+        ),
+        Command::Drag {
+            target: "gear".into(),
+            id: 1.into(),
+            dx: 0.5,
+            dy: 0.0,
+        },
+        Command::ExportWith(1.into(), ExportOptions::default()),
+    ]);
+    let sexp = to_sexp(&Some((command.clone(), (10.0, 20.0)))).unwrap();
+    assert_eq!(
+        from_sexp::<Option<(Command, (f32, f32))>>(&sexp).unwrap(),
+        Some((command, (10.0, 20.0)))
+    );
+}
@@ -0,0 +1,268 @@
+//! Removes the parts of a shape that can't change it, ported from the OCaml
+//! frontend's `Simplify` module.
+//!
+//! A `Shape` has no way of saying "nothing" or "everything", so a shape that
+//! simplifies all the way down to one of them is returned as a `Simplified`
+//! instead.  Anything that `shape_bounds` proves to be empty or full is
+//! pruned too, such as the intersection of two shapes that don't overlap.
+
+use crate::bbox::{shape_bounds, Bounding};
+use crate::{Id, Shape};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Simplified {
+    Nothing,
+    Everything,
+    Shape(Shape),
+}
+
+impl Simplified {
+    fn map<F: FnOnce(Shape) -> Shape>(self, f: F) -> Simplified {
+        match self {
+            Simplified::Shape(shape) => Simplified::Shape(f(shape)),
+            other => other,
+        }
+    }
+
+    // Used where a constant can't be kept as a constant, because whatever
+    // it is combined with doesn't pass it through.
+    fn or(self, original: &Shape) -> Shape {
+        match self {
+            Simplified::Shape(shape) => shape,
+            _ => original.clone(),
+        }
+    }
+}
+
+/// Simplifies `shape`, looking up the bounds of the fields that it reads
+/// from with `fields`.
+pub fn simplify<F: Fn(&Id) -> Bounding>(shape: &Shape, fields: &F) -> Simplified {
+    let simplified = match shape {
+        Shape::Terminal(_) => Simplified::Shape(shape.clone()),
+        Shape::Not(target) => match simplify(target, fields) {
+            Simplified::Nothing => Simplified::Everything,
+            Simplified::Everything => Simplified::Nothing,
+            Simplified::Shape(Shape::Not(inner)) => Simplified::Shape(*inner),
+            Simplified::Shape(target) => Simplified::Shape(Shape::Not(Box::new(target))),
+        },
+        Shape::Union(shapes) => simplify_all(shapes, true, fields),
+        Shape::Intersection(shapes) => simplify_all(shapes, false, fields),
+        Shape::Modulate(target, by) => simplify(target, fields).map(|target| match target {
+            Shape::Modulate(inner, inner_by) => Shape::Modulate(inner, inner_by + by),
+            target => Shape::Modulate(Box::new(target), *by),
+        }),
+        Shape::Transform(target, matrix) => simplify(target, fields).map(|target| match target {
+            Shape::Transform(inner, inner_matrix) => {
+                Shape::Transform(inner, inner_matrix.post_mul(matrix))
+            }
+            target => Shape::Transform(Box::new(target), *matrix),
+        }),
+        Shape::RepeatGrid {
+            target,
+            count_x,
+            count_y,
+            ..
+        } if *count_x <= 1 && *count_y <= 1 => simplify(target, fields),
+        Shape::RepeatGrid {
+            target,
+            spacing_x,
+            spacing_y,
+            count_x,
+            count_y,
+        } => simplify(target, fields).map(|target| Shape::RepeatGrid {
+            target: Box::new(target),
+            spacing_x: *spacing_x,
+            spacing_y: *spacing_y,
+            count_x: *count_x,
+            count_y: *count_y,
+        }),
+        Shape::RepeatPolar {
+            target,
+            count,
+            x,
+            y,
+        } => simplify(target, fields).map(|target| Shape::RepeatPolar {
+            target: Box::new(target),
+            count: *count,
+            x: *x,
+            y: *y,
+        }),
+        Shape::Mirror {
+            target,
+            x,
+            y,
+            nx,
+            ny,
+        } => simplify(target, fields).map(|target| Shape::Mirror {
+            target: Box::new(target),
+            x: *x,
+            y: *y,
+            nx: *nx,
+            ny: *ny,
+        }),
+        Shape::Symmetry {
+            target,
+            count,
+            x,
+            y,
+        } => simplify(target, fields).map(|target| Shape::Symmetry {
+            target: Box::new(target),
+            count: *count,
+            x: *x,
+            y: *y,
+        }),
+        Shape::Warp(target, warp) => {
+            simplify(target, fields).map(|target| Shape::Warp(Box::new(target), warp.clone()))
+        }
+        Shape::Displace { target, amount, .. } if *amount == 0.0 => simplify(target, fields),
+        Shape::Displace { target, by, amount } => {
+            let by = simplify(by, fields).or(by);
+            simplify(target, fields).map(|target| Shape::Displace {
+                target: Box::new(target),
+                by: Box::new(by),
+                amount: *amount,
+            })
+        }
+        Shape::Morph(a, _, t) if *t == 0.0 => simplify(a, fields),
+        Shape::Morph(_, b, t) if *t == 1.0 => simplify(b, fields),
+        Shape::Morph(a, b, t) => match (simplify(a, fields), simplify(b, fields)) {
+            (Simplified::Nothing, Simplified::Nothing) => Simplified::Nothing,
+            (Simplified::Everything, Simplified::Everything) => Simplified::Everything,
+            (sa, sb) => Simplified::Shape(Shape::Morph(Box::new(sa.or(a)), Box::new(sb.or(b)), *t)),
+        },
+    };
+
+    match simplified {
+        Simplified::Shape(shape) => {
+            let bounds = shape_bounds(&shape, fields);
+            if bounds.is_empty() {
+                Simplified::Nothing
+            } else if bounds.is_full() {
+                Simplified::Everything
+            } else {
+                Simplified::Shape(shape)
+            }
+        }
+        other => other,
+    }
+}
+
+// Flattens nested unions (or intersections), drops the shapes that can't
+// change the result, and stops early at any shape that decides it.
+fn simplify_all<F: Fn(&Id) -> Bounding>(shapes: &[Shape], union: bool, fields: &F) -> Simplified {
+    let (identity, absorbing) = if union {
+        (Simplified::Nothing, Simplified::Everything)
+    } else {
+        (Simplified::Everything, Simplified::Nothing)
+    };
+
+    let mut out = vec![];
+    for shape in shapes {
+        match simplify(shape, fields) {
+            Simplified::Shape(Shape::Union(inner)) if union => out.extend(inner),
+            Simplified::Shape(Shape::Intersection(inner)) if !union => out.extend(inner),
+            Simplified::Shape(shape) => out.push(shape),
+            constant => {
+                if constant == absorbing {
+                    return absorbing;
+                }
+            }
+        }
+    }
+
+    match out.len() {
+        0 => identity,
+        1 => Simplified::Shape(out.pop().unwrap()),
+        _ if union => Simplified::Shape(Shape::Union(out)),
+        _ => Simplified::Shape(Shape::Intersection(out)),
+    }
+}
+
+#[cfg(test)]
+fn simplified(figure: crate::builder::Figure) -> Simplified {
+    use crate::{Command, Value};
+    match crate::builder::compile(&figure) {
+        Command::Serially(commands) => match &commands[0] {
+            Command::Define(_, Value::BasicShape(shape)) => {
+                simplify(shape, &|_| Bounding::unknown())
+            }
+            other => panic!("expected a shape, got {:?}", other),
+        },
+        other => panic!("expected a list of commands, got {:?}", other),
+    }
+}
+
+#[test]
+fn removes_empty_shapes() {
+    use crate::builder::*;
+    let a = circle(0.0, 0.0, 1.0);
+
+    assert_eq!(simplified(circle(0.0, 0.0, 0.0)), Simplified::Nothing);
+    assert_eq!(
+        simplified(not(rect(0.0, 0.0, 0.0, 5.0))),
+        Simplified::Everything
+    );
+    assert_eq!(
+        simplified(union(vec![a.clone(), circle(5.0, 5.0, -1.0)])),
+        simplified(a.clone())
+    );
+    assert_eq!(
+        simplified(intersection(vec![a.clone(), not(circle(5.0, 5.0, 0.0))])),
+        simplified(a.clone())
+    );
+    assert_eq!(
+        simplified(union(vec![a.clone(), not(circle(5.0, 5.0, 0.0))])),
+        Simplified::Everything
+    );
+    assert_eq!(simplified(union(vec![])), Simplified::Nothing);
+
+    // The circles don't overlap, so their intersection is empty.
+    let disjoint = intersection(vec![a.clone(), circle(10.0, 0.0, 1.0)]);
+    assert_eq!(
+        simplified(disjoint.translate(3.0, 0.0)),
+        Simplified::Nothing
+    );
+}
+
+#[test]
+fn flattens_nested_shapes() {
+    use crate::builder::*;
+    use crate::Matrix;
+    let (a, b, c) = (
+        circle(0.0, 0.0, 1.0),
+        circle(1.0, 0.0, 1.0),
+        circle(2.0, 0.0, 1.0),
+    );
+
+    assert_eq!(simplified(not(not(a.clone()))), simplified(a.clone()));
+    assert_eq!(
+        simplified(union(vec![a.clone(), union(vec![b.clone(), c.clone()])])),
+        simplified(union(vec![a.clone(), b.clone(), c.clone()]))
+    );
+    assert_eq!(
+        simplified(a.clone().modulate(1.0).modulate(2.0)),
+        simplified(a.clone().modulate(3.0))
+    );
+
+    let matrix = Matrix::create_scale(2.0, 2.0).post_translate(euclid::vec2(1.0, 0.0));
+    assert_eq!(
+        simplified(a.clone().scale(2.0, 2.0).translate(1.0, 0.0)),
+        simplified(a.clone().transform(matrix))
+    );
+}
+
+#[test]
+fn prunes_fields_with_known_bounds() {
+    use crate::{Shape, Terminal};
+    let field = |id: u32| Shape::Terminal(Terminal::Field(id.into()));
+    let bounds = |id: &Id| match id.as_str() {
+        "0" => Bounding::empty(),
+        _ => Bounding::unknown(),
+    };
+    let shape = Shape::Union(vec![field(0), field(1)]);
+    assert_eq!(simplify(&shape, &bounds), Simplified::Shape(field(1)));
+    assert_eq!(
+        simplify(&Shape::Not(Box::new(field(0))), &bounds),
+        Simplified::Everything
+    );
+}
@@ -5,7 +5,7 @@ use typed_arena::Arena;
 
 pub fn compile<'a, F>(shape: &Shape, arena: &'a Arena<Ast<'a>>, find_buffer: &F) -> Ast<'a>
 where
-    F: Fn(Id) -> FieldBuffer,
+    F: Fn(&Id) -> FieldBuffer,
 {
     match shape {
         Shape::Terminal(Terminal::Circle(c)) => {
@@ -16,10 +16,10 @@ where
             let dy2 = Ast::Square(arena.alloc(dy));
             let dx2_plus_dy2 = Ast::Add(arena.alloc_extend(vec![dx2, dy2]));
             let sqrt = Ast::Sqrt(arena.alloc(dx2_plus_dy2));
-            Ast::Sub(arena.alloc(Ast::Constant(c.r)), arena.alloc(sqrt))
+            Ast::Sub(arena.alloc(sqrt), arena.alloc(Ast::Constant(c.r)))
         }
         Shape::Terminal(Terminal::Field(id)) => {
-            let buffer = find_buffer(*id);
+            let buffer = find_buffer(id);
             Ast::Buffer(buffer)
         }
         Shape::Terminal(Terminal::Rect(rect)) => {
@@ -59,5 +59,14 @@ where
                 matrix: matrix.inverse().unwrap().to_3d(),
             }
         }
+        // The rest of the combinators are only lowered by `implicit`'s
+        // compiler.
+        Shape::RepeatGrid { .. }
+        | Shape::RepeatPolar { .. }
+        | Shape::Mirror { .. }
+        | Shape::Symmetry { .. }
+        | Shape::Warp(..)
+        | Shape::Displace { .. }
+        | Shape::Morph(..) => unimplemented!(),
     }
 }
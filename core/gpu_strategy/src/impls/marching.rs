@@ -1,6 +1,9 @@
 use crate::opencl::{FieldBuffer, LineBuffer, OpenClContext};
 
-const PROGRAM: &'static str = include_str!("../shaders/marching.c");
+const PROGRAM: &'static str = concat!(
+    include_str!("../shaders/saddle.c"),
+    include_str!("../shaders/marching.c")
+);
 
 pub fn run_marching(input: &mut FieldBuffer, ctx: &OpenClContext) -> (LineBuffer, u32) {
     let _guard = ::flame::start_guard("opencl marching [run_marching]");
@@ -15,7 +15,7 @@ pub fn exec_shape<F>(
     buffer_find: F,
 ) -> FieldBuffer
 where
-    F: Fn(Id) -> FieldBuffer,
+    F: Fn(&Id) -> FieldBuffer,
 {
     let arena = ::typed_arena::Arena::new();
     let output = crate::compiler::compile(&shape, &arena, &buffer_find);
@@ -46,8 +46,8 @@ fn run_shape_helper(
 ) -> FieldBuffer {
     use debug_helpers::*;
 
-    let mut buffer = exec_shape(ctx, provider.duplicate(), shape, width, height, |i| {
-        fields[i as usize].clone()
+    let mut buffer = exec_shape(ctx, provider.duplicate(), shape, width, height, |id| {
+        fields[id.as_str().parse::<usize>().unwrap()].clone()
     });
 
     let w_color = provider.png_writer("out.color.png");
@@ -179,7 +179,7 @@ fn exec_field(provider: Provider) {
 
     let circle_field = run_shape_helper(&ctx, circle, 22, 22, provider.subdir("inner"), &[]);
 
-    let shape = Shape::Terminal(Terminal::Field(0));
+    let shape = Shape::Terminal(Terminal::Field(0.into()));
 
     run_shape_helper(&ctx, shape, 22, 22, provider, &[circle_field]);
 }
@@ -204,8 +204,8 @@ fn exec_field_intersection(provider: Provider) {
     let circle_field_2 = run_shape_helper(&ctx, circle_2, 22, 22, provider.subdir("c2"), &[]);
 
     let shape = Shape::Intersection(vec![
-        Shape::Terminal(Terminal::Field(0)),
-        Shape::Terminal(Terminal::Field(1)),
+        Shape::Terminal(Terminal::Field(0.into())),
+        Shape::Terminal(Terminal::Field(1.into())),
     ]);
 
     run_shape_helper(
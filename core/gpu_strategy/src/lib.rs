@@ -57,7 +57,7 @@ impl strategy::Strategy for GpuStrategy {
 
     fn shape<F>(&self, shape: Shape, width: u32, height: u32, buffer_find: F) -> gpu_interp::Buffer
     where
-        F: Fn(Id) -> gpu_interp::Buffer,
+        F: Fn(&Id) -> gpu_interp::Buffer,
     {
         unimplemented!()
     }
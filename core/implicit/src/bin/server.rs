@@ -1,56 +1,474 @@
+extern crate euclid;
 extern crate expectation;
 extern crate expectation_shared;
 extern crate extern_api;
 extern crate flame;
 extern crate implicit;
+extern crate line_stitch;
 extern crate serde;
-extern crate snoot;
+extern crate serde_json;
+extern crate structopt;
 
+use euclid::point2;
 use expectation::Provider;
 use expectation_shared::filesystem::RealFileSystem;
-use extern_api::*;
-use implicit::inspector::Inspector;
-use snoot::serde_serialization::{deserialize, DeserializeResult};
-use std::io::Read;
-use std::io::{stdin, stdout};
+use extern_api::params::Params;
+use extern_api::Viewport;
+use implicit::daemon::{self, ExportedPaths};
+use implicit::exec::{Cache, Export};
+use implicit::inspector::{BoxedInspector, Inspector};
+use implicit::opencl::{all_devices, device_name, OpenClContext};
+use implicit::program::{self, Format};
+use implicit::watch::{self, Event};
+use line_stitch::order_for_travel;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{stdin, stdout, Read, Write};
+use std::os::unix::net::UnixListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "server", about = "Renders implicit surface programs.")]
+struct Options {
+    /// The program to render.  Read from stdin if omitted.  With --watch,
+    /// any number of programs and directories of `.shape` programs.
+    #[structopt(parse(from_os_str))]
+    inputs: Vec<PathBuf>,
+
+    /// Where to write the exported paths.  Written to stdout if omitted.
+    /// With --watch, the directory to write outputs into, which defaults to
+    /// the directory that each program is in.
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// The format of the program: sexp, json, or msgpack.  Detected from the
+    /// input if omitted.
+    #[structopt(short = "f", long = "format")]
+    format: Option<Format>,
+
+    /// The format of the output: svg, png, txt, or json.
+    #[structopt(long = "output-format", default_value = "svg")]
+    output_format: OutputFormat,
+
+    /// Draw svg output with lines, arcs, and curves that are fitted to the
+    /// exported paths to within this distance (in world units), instead of
+    /// with a line between every pair of points.
+    #[structopt(long = "curve-tolerance")]
+    curve_tolerance: Option<f32>,
+
+    /// Order the paths of each export so that a plotter moves as little as
+    /// possible between them, and print how far it travels before and after.
+    /// Svg output is written in that order, with holes drawn along with the
+    /// outlines that they're in.
+    #[structopt(long = "order-travel")]
+    order_travel: bool,
+
+    /// Pixels per world unit, overriding the program's viewport.
+    #[structopt(short = "r", long = "resolution")]
+    resolution: Option<f32>,
+
+    /// Replace the program's viewport with one that fits around everything
+    /// that it exports.
+    #[structopt(long = "fit")]
+    fit: bool,
+
+    /// Set a parameter of the program, as NAME=VALUE.  Can be given any
+    /// number of times.
+    #[structopt(
+        short = "p",
+        long = "param",
+        raw(number_of_values = "1"),
+        parse(try_from_str = "parse_param")
+    )]
+    params: Vec<(String, f32)>,
+
+    /// Write the intermediate fields and paths into this directory.
+    #[structopt(long = "inspect", parse(from_os_str))]
+    inspect: Option<PathBuf>,
+
+    /// Keep computed fields in this directory, so that later runs can reuse
+    /// them.
+    #[structopt(long = "cache", parse(from_os_str))]
+    cache: Option<PathBuf>,
+
+    /// Write a flame graph of the run to this path.
+    #[structopt(long = "profile", parse(from_os_str))]
+    profile: Option<PathBuf>,
+
+    /// The index of the OpenCL device to run on (see --list-devices).
+    #[structopt(short = "d", long = "device")]
+    device: Option<usize>,
+
+    /// Print the available OpenCL devices and exit.
+    #[structopt(long = "list-devices")]
+    list_devices: bool,
+
+    /// Re-render the inputs whenever they change, until interrupted.
+    #[structopt(long = "watch")]
+    watch: bool,
+
+    /// Keep running and answer length-prefixed requests on stdin until it is
+    /// closed.  See `implicit::daemon` for the protocol.
+    #[structopt(long = "daemon")]
+    daemon: bool,
+
+    /// With --daemon, listen on this unix socket instead of stdin.
+    #[structopt(long = "socket", parse(from_os_str))]
+    socket: Option<PathBuf>,
+
+    /// Only print errors.
+    #[structopt(short = "q", long = "quiet")]
+    quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OutputFormat {
+    Svg,
+    Png,
+    Txt,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "svg" => Ok(OutputFormat::Svg),
+            "png" => Ok(OutputFormat::Png),
+            "txt" => Ok(OutputFormat::Txt),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!(
+                "unknown output format {:?}, expected one of svg, png, txt, or json",
+                other
+            )),
+        }
+    }
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Svg => "svg",
+            OutputFormat::Png => "png",
+            OutputFormat::Txt => "txt",
+            OutputFormat::Json => "json",
+        }
+    }
+}
 
 fn main() {
-    let mut out = String::new();
-    let stdin = stdin();
-    stdin.lock().read_to_string(&mut out).unwrap();
-
-    let sexprs = snoot::simple_parse(out, &[], Some("<stdin>"));
-    sexprs.diagnostics.assert_empty();
-    assert!(sexprs.roots.len() == 1);
-
-    let program = sexprs.roots.into_iter().next().unwrap();
-    let (command, (w, h)) = match deserialize::<Option<(Command, (f32, f32))>>(&program) {
-        DeserializeResult::AllGood(None) => panic!("deserialized into none"),
-        DeserializeResult::AllGood(Some(v)) => v,
-        DeserializeResult::CouldntRecover(bag) | DeserializeResult::CouldRecover(_, bag) => {
-            bag.assert_empty();
-            panic!()
+    let options = Options::from_args();
+    if let Err(e) = run(options) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(options: Options) -> Result<(), Box<Error>> {
+    let devices = all_devices();
+    if options.list_devices {
+        for (i, (_, device)) in devices.iter().enumerate() {
+            println!("{}: {}", i, device_name(device));
         }
-    };
+        return Ok(());
+    }
+
+    let index = options.device.unwrap_or(0);
+    let (platform, device) = devices
+        .into_iter()
+        .nth(index)
+        .ok_or_else(|| format!("no OpenCL device with index {} (try --list-devices)", index))?;
+    if !options.quiet {
+        eprintln!("running on {}", device_name(&device));
+    }
+    let ctx = OpenClContext::new(platform, device);
 
-    let output = implicit::exec::exec(
-        command,
-        Provider::new(
-            Box::new(RealFileSystem {
-                root: "./main_out/actual".into(),
-            }),
-            Box::new(RealFileSystem {
-                root: "./main_out/expected".into(),
-            }),
+    let inspector: BoxedInspector = match options.inspect {
+        Some(ref dir) => Provider::new(
+            Box::new(RealFileSystem { root: dir.clone() }),
+            Box::new(RealFileSystem { root: dir.clone() }),
         )
         .duplicate(),
-        //Box::new(()),
-        w.ceil() as u32,
-        h.ceil() as u32,
-    );
-    let output: Vec<_> = output.into_iter().flat_map(|(_, v)| v).collect();
-    implicit::debug::svg_path_segments(stdout(), &output).unwrap();
-
-    flame::dump_html(std::fs::File::create("perf.html").unwrap()).unwrap();
-    flame::dump_text_to_writer(::std::io::stderr()).unwrap();
+        None => Box::new(()),
+    };
+
+    if options.daemon {
+        return run_daemon(&options, &ctx, &*inspector);
+    }
+
+    if options.watch {
+        return run_watch(&options, &ctx, &*inspector);
+    }
+    if options.inputs.len() > 1 {
+        return Err("only one program can be rendered at a time without --watch".into());
+    }
+
+    let mut input = vec![];
+    // Programs are included relative to the program that includes them, or
+    // the working directory for programs read from stdin.
+    let dir = options
+        .inputs
+        .first()
+        .and_then(|path| path.parent())
+        .unwrap_or_else(|| Path::new("."));
+    match options.inputs.first() {
+        Some(path) => {
+            File::open(path)
+                .and_then(|mut f| f.read_to_end(&mut input))
+                .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        }
+        None => {
+            stdin().lock().read_to_end(&mut input)?;
+        }
+    }
+
+    let mut cache = new_cache(&options)?;
+    let (exports, viewport) = render(&options, &ctx, &input, dir, inspector, &mut cache)?;
+
+    match options.output {
+        Some(ref path) => {
+            let file = File::create(path)
+                .map_err(|e| format!("couldn't create {}: {}", path.display(), e))?;
+            write_exports(file, &exports, &viewport, &options)?;
+        }
+        None => write_exports(stdout(), &exports, &viewport, &options)?,
+    }
+
+    if let Some(ref path) = options.profile {
+        flame::dump_html(File::create(path)?)?;
+        if !options.quiet {
+            flame::dump_text_to_writer(::std::io::stderr())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn render(
+    options: &Options,
+    ctx: &OpenClContext,
+    input: &[u8],
+    dir: &Path,
+    inspector: BoxedInspector,
+    cache: &mut Cache,
+) -> Result<(Vec<Export>, Viewport), Box<Error>> {
+    let params: Params = options.params.iter().cloned().collect();
+    let mut template = program::parse_template(input, options.format)?;
+    program::resolve_includes(&mut template.command, dir)?;
+    if let Some(resolution) = options.resolution {
+        template.viewport.resolution = resolution;
+    }
+    if options.fit {
+        let program = template.resolve(&params)?;
+        template.viewport = Viewport::fit(&program.command, template.viewport.resolution)
+            .ok_or("can't fit a viewport around exports that are empty or unbounded")?;
+    }
+    let viewport = template.viewport.clone();
+    let mut exports = implicit::exec::exec_template(ctx, &template, &params, inspector, cache)?;
+    if options.order_travel {
+        order_exports(&mut exports, &viewport, options.quiet);
+    }
+    Ok((exports, viewport))
+}
+
+/// The pen starts at the corner of the viewport, and each export starts
+/// wherever the one before it left the pen.
+fn order_exports(exports: &mut [Export], viewport: &Viewport, quiet: bool) {
+    let mut pen = point2(viewport.bbox.x, viewport.bbox.y);
+    for export in exports {
+        let lines = ::std::mem::replace(&mut export.lines, vec![]);
+        let (lines, travel) = order_for_travel(lines, pen);
+        if !quiet {
+            eprintln!(
+                "export {}: pen-up travel {:.3} -> {:.3}",
+                export.id, travel.before, travel.after
+            );
+        }
+        if let Some(last) = lines.last() {
+            pen = if last.closed {
+                last.path[0]
+            } else {
+                last.path[last.path.len() - 1]
+            };
+        }
+        export.lines = lines;
+    }
+}
+
+fn parse_param(s: &str) -> Result<(String, f32), String> {
+    let mut parts = s.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => value
+            .trim()
+            .parse()
+            .map(|value| (name.trim().to_string(), value))
+            .map_err(|e| format!("invalid value for {}: {}", name, e)),
+        _ => Err(format!("expected NAME=VALUE, got {:?}", s)),
+    }
+}
+
+fn new_cache(options: &Options) -> Result<Cache, Box<Error>> {
+    match options.cache {
+        Some(ref dir) => Ok(Cache::with_directory(dir.clone())
+            .map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?),
+        None => Ok(Cache::new()),
+    }
+}
+
+fn run_watch(
+    options: &Options,
+    ctx: &OpenClContext,
+    inspector: &Inspector,
+) -> Result<(), Box<Error>> {
+    if options.inputs.is_empty() {
+        return Err("--watch needs at least one program or directory to watch".into());
+    }
+    if let Some(ref dir) = options.output {
+        fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?;
+    }
+
+    // Each program gets its own cache, so that only the parts of a program
+    // that changed since it was last saved are recomputed.
+    let mut caches = HashMap::new();
+    watch::watch(&options.inputs, Duration::from_millis(100), |event| {
+        let path = match event {
+            Event::Changed(path) => path,
+            Event::Error(e) => return eprintln!("error: {}", e),
+        };
+        // A bad program shouldn't stop the rest from being watched, so both
+        // errors and panics are reported and then forgotten.
+        let rendered = panic::catch_unwind(AssertUnwindSafe(|| {
+            render_file(options, ctx, inspector, &mut caches, path)
+        }));
+        match rendered {
+            Ok(Ok(output)) => {
+                if !options.quiet {
+                    eprintln!("{} -> {}", path.display(), output.display());
+                }
+            }
+            Ok(Err(e)) => eprintln!("error: {}: {}", path.display(), e),
+            Err(_) => eprintln!("error: {}: rendering panicked", path.display()),
+        }
+    })?;
+    Ok(())
+}
+
+fn render_file(
+    options: &Options,
+    ctx: &OpenClContext,
+    inspector: &Inspector,
+    caches: &mut HashMap<PathBuf, Cache>,
+    path: &Path,
+) -> Result<PathBuf, Box<Error>> {
+    let input = fs::read(path)?;
+    if !caches.contains_key(path) {
+        caches.insert(path.to_path_buf(), new_cache(options)?);
+    }
+    let stem = path.file_stem().ok_or("the program has no file name")?;
+    let (exports, viewport) = render(
+        options,
+        ctx,
+        &input,
+        path.parent().unwrap_or_else(|| Path::new(".")),
+        inspector.specialize(&stem.to_string_lossy()),
+        caches.get_mut(path).unwrap(),
+    )?;
+
+    let extension = options.output_format.extension();
+    let mut file_name = stem.to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    let output = match options.output {
+        Some(ref dir) => dir.join(file_name),
+        None => path.with_file_name(file_name),
+    };
+    if output == path {
+        return Err("the output would overwrite the program".into());
+    }
+    // Written to the side first so that viewers never see half of a file.
+    let partial = output.with_extension(format!("{}.partial", extension));
+    write_exports(File::create(&partial)?, &exports, &viewport, options)?;
+    fs::rename(&partial, &output)?;
+    Ok(output)
+}
+
+fn run_daemon(
+    options: &Options,
+    ctx: &OpenClContext,
+    inspector: &Inspector,
+) -> Result<(), Box<Error>> {
+    let mut cache = new_cache(options)?;
+    match options.socket {
+        Some(ref path) => {
+            let listener = UnixListener::bind(path)
+                .map_err(|e| format!("couldn't listen on {}: {}", path.display(), e))?;
+            if !options.quiet {
+                eprintln!("listening on {}", path.display());
+            }
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let reader = stream.try_clone()?;
+                // A client hanging up shouldn't take the daemon down with it.
+                if let Err(e) = daemon::serve(
+                    ctx,
+                    inspector,
+                    options.resolution,
+                    &mut cache,
+                    reader,
+                    stream,
+                ) {
+                    eprintln!("error: {}", e);
+                }
+            }
+        }
+        None => {
+            let input = stdin();
+            let output = stdout();
+            daemon::serve(
+                ctx,
+                inspector,
+                options.resolution,
+                &mut cache,
+                input.lock(),
+                output.lock(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn write_exports<W: Write>(
+    mut out: W,
+    exports: &[Export],
+    viewport: &Viewport,
+    options: &Options,
+) -> Result<(), Box<Error>> {
+    match options.output_format {
+        OutputFormat::Svg => implicit::debug::svg_exports(
+            out,
+            exports,
+            options.curve_tolerance,
+            options.order_travel,
+        )?,
+        OutputFormat::Png => {
+            let lines: Vec<_> = exports.iter().flat_map(|e| e.lines.clone()).collect();
+            implicit::debug::png_path_segments(out, &lines, viewport)?;
+        }
+        OutputFormat::Txt => {
+            for export in exports {
+                writeln!(out, "export {}", export.id)?;
+                implicit::debug::print_path_segments(&mut out, &export.lines);
+            }
+        }
+        OutputFormat::Json => {
+            let exports: Vec<_> = exports.iter().map(ExportedPaths::from).collect();
+            serde_json::to_writer(out, &exports)?;
+        }
+    }
+    Ok(())
 }
@@ -19,7 +19,7 @@ fn sphere<'a>(x: f32, y: f32, z: f32, r: f32, arena: &'a Arena<Ast<'a>>) -> Ast<
 
     let dx2_plus_dy2_plus_dz2 = Ast::Add(arena.alloc_extend(vec![dx2, dy2, dz2]));
     let sqrt = Ast::Sqrt(arena.alloc(dx2_plus_dy2_plus_dz2));
-    let sub = Ast::Sub(arena.alloc(Ast::Constant(r)), arena.alloc(sqrt));
+    let sub = Ast::Sub(arena.alloc(sqrt), arena.alloc(Ast::Constant(r)));
     sub
 }
 
@@ -45,8 +45,8 @@ fn torus<'a>(_x: f32, _y: f32, _z: f32, r: f32, a: f32, arena: &'a Arena<Ast<'a>
         Ast::Add(arena.alloc_extend(vec![x2, y2])),
     ]));
 
-    Ast::Sub(arena.alloc(lhs), arena.alloc(rhs))
-    //Ast::Neg(arena.alloc(Ast::Sub(arena.alloc(lhs), arena.alloc(rhs))))
+    Ast::Sub(arena.alloc(rhs), arena.alloc(lhs))
+    //Ast::Neg(arena.alloc(Ast::Sub(arena.alloc(rhs), arena.alloc(lhs))))
 }
 
 fn main() {
@@ -1,63 +1,380 @@
-use extern_api::{Id, Shape, Terminal};
-use gpu_interp::Ast;
+use euclid::num::{One, Zero};
+use extern_api::params::{Float, Params};
+use extern_api::{Id, Matrix, Scalar, Shape, Terminal, Warp};
+use gpu_interp::gpu::bytecode::{compile_with_inputs, CompilationResult};
+use gpu_interp::{Ast, Number, Operand};
 use opencl::FieldBuffer;
+use std::cell::RefCell;
 use typed_arena::Arena;
 
 pub fn compile<'a, F>(shape: &Shape, arena: &'a Arena<Ast<'a>>, find_buffer: &F) -> Ast<'a>
 where
-    F: Fn(Id) -> FieldBuffer,
+    F: Fn(&Id) -> FieldBuffer,
 {
+    lower(shape, &Constants, arena, find_buffer)
+}
+
+/// Where the numbers in a compiled shape come from.  Each one is `f` of some
+/// of the numbers in the shape, which is either worked out while the shape
+/// is compiled, or left until the compiled shape is run.
+pub trait Numbers<S: Scalar> {
+    type Number: Number;
+    fn number(&self, f: fn(&[f32]) -> f32, args: &[S]) -> Self::Number;
+}
+
+/// Works every number out while the shape is compiled.
+pub struct Constants;
+
+impl Numbers<f32> for Constants {
+    type Number = f32;
+    fn number(&self, f: fn(&[f32]) -> f32, args: &[f32]) -> f32 {
+        f(args)
+    }
+}
+
+type Input = (fn(&[f32]) -> f32, Vec<Float>);
+
+/// Leaves the numbers that read parameters as inputs.
+#[derive(Default)]
+pub struct Inputs {
+    inputs: RefCell<Vec<Input>>,
+}
+
+impl Numbers<Float> for Inputs {
+    type Number = Operand;
+    fn number(&self, f: fn(&[f32]) -> f32, args: &[Float]) -> Operand {
+        match args.iter().map(|a| a.value()).collect::<Option<Vec<_>>>() {
+            Some(values) => Operand::Constant(f(&values)),
+            None => {
+                let mut inputs = self.inputs.borrow_mut();
+                inputs.push((f, args.to_vec()));
+                Operand::Input(inputs.len() as u32 - 1)
+            }
+        }
+    }
+}
+
+/// A shape that was compiled with its parameters left as inputs, so that it
+/// can be run with new values for them without being compiled again.
+#[derive(Clone)]
+pub struct CompiledShape {
+    program: CompilationResult,
+    inputs: Vec<Input>,
+}
+
+impl CompiledShape {
+    pub fn new<F>(shape: &Shape<Float>, find_buffer: &F) -> CompiledShape
+    where
+        F: Fn(&Id) -> FieldBuffer,
+    {
+        let arena = Arena::new();
+        let numbers = Inputs::default();
+        let ast = lower(shape, &numbers, &arena, find_buffer);
+        let inputs = numbers.inputs.into_inner();
+        CompiledShape {
+            program: compile_with_inputs(&ast, inputs.len() as u32),
+            inputs,
+        }
+    }
+
+    /// The program, with its inputs worked out from `values`.  Panics
+    /// unless `values` has a value for every parameter that the shape reads,
+    /// as `Template::values` does for a template that resolves.  Resolving
+    /// also checks that the values leave every matrix invertible.
+    pub fn bind(&self, values: &Params) -> CompilationResult {
+        let inputs: Vec<f32> = self
+            .inputs
+            .iter()
+            .map(|(f, args)| {
+                let args: Vec<f32> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Float::Value(v) => *v,
+                        Float::Param(name) => values[name.as_str()],
+                    })
+                    .collect();
+                f(&args)
+            })
+            .collect();
+        let mut program = self.program.clone();
+        program.set_inputs(&inputs);
+        program
+    }
+}
+
+fn value(args: &[f32]) -> f32 {
+    args[0]
+}
+
+// An entry of the inverse of the matrix with the entries `m`.  Programs
+// with singular matrices are rejected when their templates are resolved.
+fn inverse(m: &[f32]) -> Matrix {
+    Matrix::row_major(m[0], m[1], m[2], m[3], m[4], m[5])
+        .inverse()
+        .expect("singular matrix")
+}
+
+// A mirror whose normal has no direction gets a zero normal, which leaves
+// its target as it is.
+fn unit_normal(n: &[f32]) -> (f32, f32) {
+    Shape::mirror_normal(n[0], n[1]).unwrap_or((0.0, 0.0))
+}
+
+pub fn lower<'a, S, N, F>(
+    shape: &Shape<S>,
+    numbers: &N,
+    arena: &'a Arena<Ast<'a, N::Number>>,
+    find_buffer: &F,
+) -> Ast<'a, N::Number>
+where
+    S: Scalar,
+    N: Numbers<S>,
+    F: Fn(&Id) -> FieldBuffer,
+{
+    let n = |v: S| numbers.number(value, &[v]);
+    let point = |x: S, y: S| (n(x), n(y));
     match shape {
         Shape::Terminal(Terminal::Circle(c)) => {
-            let dx = Ast::Sub(arena.alloc(Ast::X), arena.alloc(Ast::Constant(c.x)));
-            let dy = Ast::Sub(arena.alloc(Ast::Y), arena.alloc(Ast::Constant(c.y)));
+            let dx = Ast::Sub(arena.alloc(Ast::X), arena.alloc(Ast::Constant(n(c.x))));
+            let dy = Ast::Sub(arena.alloc(Ast::Y), arena.alloc(Ast::Constant(n(c.y))));
             // TODO: don't duplicate this entire branch.
             let dx2 = Ast::Square(arena.alloc(dx));
             let dy2 = Ast::Square(arena.alloc(dy));
             let dx2_plus_dy2 = Ast::Add(arena.alloc_extend(vec![dx2, dy2]));
             let sqrt = Ast::Sqrt(arena.alloc(dx2_plus_dy2));
-            Ast::Sub(arena.alloc(Ast::Constant(c.r)), arena.alloc(sqrt))
+            Ast::Sub(arena.alloc(sqrt), arena.alloc(Ast::Constant(n(c.r))))
         }
         Shape::Terminal(Terminal::Field(id)) => {
-            let buffer = find_buffer(*id);
+            let buffer = find_buffer(id);
             Ast::Buffer(buffer)
         }
         Shape::Terminal(Terminal::Rect(rect)) => {
             let ::extern_api::Rect { x, y, w, h } = *rect;
-            let top = (x, y, x + w, y);
-            let right = (x + w, y, x + w, y + h);
-            let bot = (x + w, y + h, x, y + h);
-            let left = (x, y + h, x, y);
+            let (x, y, xw, yh) = (
+                n(x),
+                n(y),
+                numbers.number(|a| a[0] + a[1], &[x, w]),
+                numbers.number(|a| a[0] + a[1], &[y, h]),
+            );
+            let top = (x, y, xw, y);
+            let right = (xw, y, xw, yh);
+            let bot = (xw, yh, x, yh);
+            let left = (x, yh, x, y);
             Ast::DistToPoly(vec![top, right, bot, left])
         }
         Shape::Not(target) => {
-            let child = compile(target, arena, find_buffer);
+            let child = lower(target, numbers, arena, find_buffer);
             Ast::Neg(arena.alloc(child))
         }
         Shape::Union(shapes) => {
             let children = shapes
                 .into_iter()
-                .map(|s| compile(s, arena, find_buffer))
+                .map(|s| lower(s, numbers, arena, find_buffer))
                 .collect::<Vec<_>>();
             Ast::Min(arena.alloc_extend(children))
         }
         Shape::Intersection(shapes) => {
             let children = shapes
                 .into_iter()
-                .map(|s| compile(s, arena, find_buffer))
+                .map(|s| lower(s, numbers, arena, find_buffer))
                 .collect::<Vec<_>>();
             Ast::Max(arena.alloc_extend(children))
         }
         Shape::Modulate(target, how_much) => {
-            let child = compile(target, arena, find_buffer);
-            Ast::Add(arena.alloc_extend(vec![child, Ast::Constant(-*how_much)].into_iter()))
+            let child = lower(target, numbers, arena, find_buffer);
+            let by = Ast::Constant(numbers.number(|a| -a[0], &[*how_much]));
+            Ast::Add(arena.alloc_extend(vec![child, by].into_iter()))
         }
         Shape::Transform(target, matrix) => {
-            let child = compile(target, arena, find_buffer);
+            let child = lower(target, numbers, arena, find_buffer);
+            let m = [
+                matrix.m11, matrix.m12, matrix.m21, matrix.m22, matrix.m31, matrix.m32,
+            ];
+            let (zero, one) = (N::Number::zero(), N::Number::one());
+            // The inverse, as a 3d matrix.
+            let matrix = ::euclid::Transform3D::row_major(
+                numbers.number(|m| inverse(m).m11, &m),
+                numbers.number(|m| inverse(m).m12, &m),
+                zero,
+                zero,
+                numbers.number(|m| inverse(m).m21, &m),
+                numbers.number(|m| inverse(m).m22, &m),
+                zero,
+                zero,
+                zero,
+                zero,
+                one,
+                zero,
+                numbers.number(|m| inverse(m).m31, &m),
+                numbers.number(|m| inverse(m).m32, &m),
+                zero,
+                one,
+            );
             Ast::Transform {
                 target: arena.alloc(child),
-                matrix: matrix.inverse().unwrap().to_3d(),
+                matrix,
+            }
+        }
+        Shape::RepeatGrid {
+            target,
+            spacing_x,
+            spacing_y,
+            count_x,
+            count_y,
+        } => {
+            let child = lower(target, numbers, arena, find_buffer);
+            Ast::RepeatGrid {
+                target: arena.alloc(child),
+                spacing: point(*spacing_x, *spacing_y),
+                count: (*count_x, *count_y),
+            }
+        }
+        Shape::RepeatPolar {
+            target,
+            count,
+            x,
+            y,
+        } => {
+            let child = lower(target, numbers, arena, find_buffer);
+            Ast::RepeatPolar {
+                target: arena.alloc(child),
+                count: *count,
+                center: point(*x, *y),
             }
         }
+        Shape::Mirror {
+            target,
+            x,
+            y,
+            nx,
+            ny,
+        } => {
+            let child = lower(target, numbers, arena, find_buffer);
+            let known = match (nx.value(), ny.value()) {
+                (Some(nx), Some(ny)) => Some(Shape::mirror_normal(nx, ny)),
+                _ => None,
+            };
+            match known {
+                Some(None) => child,
+                _ => Ast::Mirror {
+                    target: arena.alloc(child),
+                    point: point(*x, *y),
+                    normal: (
+                        numbers.number(|n| unit_normal(n).0, &[*nx, *ny]),
+                        numbers.number(|n| unit_normal(n).1, &[*nx, *ny]),
+                    ),
+                },
+            }
+        }
+        Shape::Symmetry {
+            target,
+            count,
+            x,
+            y,
+        } => {
+            let child = lower(target, numbers, arena, find_buffer);
+            Ast::Symmetry {
+                target: arena.alloc(child),
+                count: *count,
+                center: point(*x, *y),
+            }
+        }
+        Shape::Warp(target, warp) => {
+            let child = lower(target, numbers, arena, find_buffer);
+            let (warped, correction) = match *warp {
+                Warp::Twist { x, y, rate, radius } => (
+                    Ast::Twist {
+                        target: arena.alloc(child),
+                        center: point(x, y),
+                        rate: n(rate),
+                    },
+                    numbers.number(|a| 1.0 / twist_lipschitz(a), &[rate, radius]),
+                ),
+                Warp::Bend { x, y, rate, radius } => (
+                    Ast::Bend {
+                        target: arena.alloc(child),
+                        center: point(x, y),
+                        rate: n(rate),
+                    },
+                    numbers.number(|a| 1.0 / twist_lipschitz(a), &[rate, radius]),
+                ),
+                Warp::Taper { x, y, rate, radius } => (
+                    Ast::Taper {
+                        target: arena.alloc(child),
+                        center: point(x, y),
+                        rate: n(rate),
+                    },
+                    numbers.number(|a| 1.0 / taper_lipschitz(a), &[rate, radius]),
+                ),
+            };
+            Ast::Mul(arena.alloc_extend(vec![warped, Ast::Constant(correction)]))
+        }
+        Shape::Displace { target, by, amount } => {
+            // Assumes that `by` doesn't change faster than a distance field
+            // does, so the sum can change by at most `1 + |amount|`.
+            let child = lower(target, numbers, arena, find_buffer);
+            let by = lower(by, numbers, arena, find_buffer);
+            let offset = Ast::Mul(arena.alloc_extend(vec![by, Ast::Constant(n(*amount))]));
+            let displaced = Ast::Add(arena.alloc_extend(vec![child, offset]));
+            let correction = numbers.number(|a| 1.0 / (1.0 + a[0].abs()), &[*amount]);
+            Ast::Mul(arena.alloc_extend(vec![displaced, Ast::Constant(correction)]))
+        }
+        Shape::Morph(a, b, t) => {
+            let a = lower(a, numbers, arena, find_buffer);
+            let b = lower(b, numbers, arena, find_buffer);
+            let a_weight = numbers.number(|a| 1.0 - a[0], &[*t]);
+            let a = Ast::Mul(arena.alloc_extend(vec![a, Ast::Constant(a_weight)]));
+            let b = Ast::Mul(arena.alloc_extend(vec![b, Ast::Constant(n(*t))]));
+            Ast::Add(arena.alloc_extend(vec![a, b]))
+        }
     }
 }
+
+// The `Warp::lipschitz` of a twist (or a bend) and a taper with the rate and
+// radius `a`.
+fn twist_lipschitz(a: &[f32]) -> f32 {
+    let twist = Warp::Twist {
+        x: 0.0,
+        y: 0.0,
+        rate: a[0],
+        radius: a[1],
+    };
+    twist.lipschitz()
+}
+
+fn taper_lipschitz(a: &[f32]) -> f32 {
+    let taper = Warp::Taper {
+        x: 0.0,
+        y: 0.0,
+        rate: a[0],
+        radius: a[1],
+    };
+    taper.lipschitz()
+}
+
+#[test]
+fn parameters_become_inputs() {
+    use extern_api::params::Name;
+    use extern_api::Circle;
+
+    let r = Float::Param(Name::new("r"));
+    let shape = Shape::Modulate(
+        Box::new(Shape::Terminal(Terminal::Circle(Circle {
+            x: Float::Value(3.0),
+            y: Float::Value(3.0),
+            r,
+        }))),
+        r,
+    );
+    let compiled = CompiledShape::new(&shape, &|_: &Id| unreachable!());
+    assert_eq!(compiled.inputs.len(), 2);
+
+    let mut values = Params::new();
+    values.insert("r".into(), 2.0);
+    let program = compiled.bind(&values);
+    assert_eq!(&program.constants[..3], &[2.0, -2.0, 3.0]);
+    values.insert("r".into(), 5.0);
+    let rebound = compiled.bind(&values);
+    assert_eq!(&rebound.constants[..3], &[5.0, -5.0, 3.0]);
+    assert_eq!(rebound.code, program.code);
+}
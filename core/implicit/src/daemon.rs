@@ -0,0 +1,306 @@
+//! The protocol that `server --daemon` speaks.
+//!
+//! Requests and responses are sent as frames: a little-endian `u32` length
+//! followed by that many bytes of payload.  A request is a `Request` encoded
+//! as json or MessagePack, and every request gets back exactly one
+//! `Response`, encoded as json.  Requests are answered in the order they
+//! arrive, and the OpenCL context (along with its compiled kernels) is kept
+//! alive between them.  Programs are included relative to the daemon's
+//! working directory.
+
+use exec::{exec_template, Cache, Export};
+use extern_api::params::{Params, Template};
+use extern_api::Id;
+use inspector::Inspector;
+use opencl::OpenClContext;
+use program::Format;
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+/// The longest payload that a request can have.  A frame's length is read
+/// before any of it is, so without a limit a bad header could ask for up to
+/// 4 GB at once.
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Request {
+    /// Chosen by the client, and echoed back in the response.
+    pub id: u64,
+    /// Overrides the defaults of the program's parameters.
+    #[serde(default)]
+    pub params: Params,
+    pub program: Template,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Response {
+    /// `None` if the request couldn't be parsed.
+    pub id: Option<u64>,
+    pub exports: Vec<ExportedPaths>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportedPaths {
+    pub id: Id,
+    pub paths: Vec<Path>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Path {
+    pub closed: bool,
+    pub points: Vec<(f32, f32)>,
+}
+
+impl<'a> From<&'a Export> for ExportedPaths {
+    fn from(export: &'a Export) -> ExportedPaths {
+        ExportedPaths {
+            id: export.id.clone(),
+            paths: export
+                .lines
+                .iter()
+                .map(|segment| Path {
+                    closed: segment.closed,
+                    points: segment.path.iter().map(|p| (p.x, p.y)).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Reads the next frame, or `None` if the input ended cleanly between frames.
+/// Frames longer than `MAX_FRAME_LEN` are `InvalidData` errors, and nothing
+/// after their header is read.
+pub fn read_frame<R: Read>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; 4];
+    let mut filled = 0;
+    while filled < header.len() {
+        match input.read(&mut header[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => filled += n,
+        }
+    }
+    let len = header
+        .iter()
+        .rev()
+        .fold(0usize, |len, &b| (len << 8) | b as usize);
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes is longer than the limit of {}",
+                len, MAX_FRAME_LEN
+            ),
+        ));
+    }
+
+    let mut payload = vec![0; len];
+    input.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+pub fn write_frame<W: Write>(output: &mut W, payload: &[u8]) -> io::Result<()> {
+    let len = payload.len() as u32;
+    output.write_all(&[
+        len as u8,
+        (len >> 8) as u8,
+        (len >> 16) as u8,
+        (len >> 24) as u8,
+    ])?;
+    output.write_all(payload)?;
+    output.flush()
+}
+
+pub fn parse_request(payload: &[u8]) -> Result<Request, String> {
+    match Format::detect(payload) {
+        Format::Json => ::serde_json::from_slice(payload).map_err(|e| e.to_string()),
+        Format::MessagePack => {
+            // Written by position, as `[id, program]` or, to override
+            // parameters, `[id, program, params]`.
+            let (id, program, params) = ::rmp_serde::from_slice(payload)
+                .or_else(|_| {
+                    ::rmp_serde::from_slice(payload)
+                        .map(|(id, program): (u64, Template)| (id, program, Params::new()))
+                })
+                .map_err(|e| e.to_string())?;
+            Ok(Request {
+                id,
+                params,
+                program,
+            })
+        }
+        Format::Sexp => Err("requests must be json or MessagePack".into()),
+    }
+}
+
+/// Answers requests from `input` until it is closed.  `resolution`
+/// overrides the resolution of every program, and `cache` is shared between
+/// all of the requests.  A request whose program panics while it runs gets
+/// an error, and the requests after it are still answered.  A frame that's
+/// too long gets an error too, but since the rest of the input can't be
+/// split into frames after it, nothing more is read.
+pub fn serve<R: Read, W: Write>(
+    ctx: &OpenClContext,
+    inspector: &Inspector,
+    resolution: Option<f32>,
+    cache: &mut Cache,
+    mut input: R,
+    mut output: W,
+) -> io::Result<()> {
+    loop {
+        let payload = match read_frame(&mut input) {
+            Ok(Some(payload)) => payload,
+            Ok(None) => break,
+            Err(error) => {
+                if error.kind() == io::ErrorKind::InvalidData {
+                    write_response(
+                        &mut output,
+                        &Response {
+                            error: Some(error.to_string()),
+                            ..Response::default()
+                        },
+                    )?;
+                }
+                return Err(error);
+            }
+        };
+        let response = match parse_request(&payload) {
+            Ok(Request {
+                id,
+                params,
+                mut program,
+            }) => {
+                if let Some(resolution) = resolution {
+                    program.viewport.resolution = resolution;
+                }
+                let inspector = inspector.specialize(&format!("request_{}", id));
+                // The cache only holds results that were finished, so it's
+                // still good to use after a panic.
+                let exports = panic::catch_unwind(AssertUnwindSafe(|| {
+                    exec_template(ctx, &program, &params, inspector, cache)
+                }));
+                match exports {
+                    Ok(Ok(exports)) => Response {
+                        id: Some(id),
+                        exports: exports.iter().map(ExportedPaths::from).collect(),
+                        error: None,
+                    },
+                    Ok(Err(error)) => Response {
+                        id: Some(id),
+                        error: Some(error.to_string()),
+                        ..Response::default()
+                    },
+                    Err(_) => Response {
+                        id: Some(id),
+                        error: Some("rendering panicked".into()),
+                        ..Response::default()
+                    },
+                }
+            }
+            Err(error) => Response {
+                error: Some(error),
+                ..Response::default()
+            },
+        };
+        write_response(&mut output, &response)?;
+    }
+    Ok(())
+}
+
+fn write_response<W: Write>(output: &mut W, response: &Response) -> io::Result<()> {
+    let payload =
+        ::serde_json::to_vec(response).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    write_frame(output, &payload)
+}
+
+#[test]
+fn frames_round_trip() {
+    let mut buffer = vec![];
+    write_frame(&mut buffer, b"hello").unwrap();
+    write_frame(&mut buffer, &[7; 300]).unwrap();
+    assert_eq!(&buffer[..4], &[5, 0, 0, 0]);
+
+    let mut input = &buffer[..];
+    assert_eq!(read_frame(&mut input).unwrap(), Some(b"hello".to_vec()));
+    assert_eq!(read_frame(&mut input).unwrap(), Some(vec![7; 300]));
+    assert_eq!(read_frame(&mut input).unwrap(), None);
+}
+
+#[test]
+fn frames_over_the_limit_are_rejected_before_their_payload() {
+    let len = MAX_FRAME_LEN as u32 + 1;
+    let header = [
+        len as u8,
+        (len >> 8) as u8,
+        (len >> 16) as u8,
+        (len >> 24) as u8,
+    ];
+    let mut input = &header[..];
+    let error = read_frame(&mut input).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let mut input = &[0xff; 4][..];
+    assert_eq!(
+        read_frame(&mut input).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn requests_can_override_parameters() {
+    let request = br#"{"id": 3, "params": {"r": 4}, "program": {
+        "params": {"r": 1},
+        "viewport": {"bbox": {"x": 0, "y": 0, "w": 10, "h": 10}, "resolution": 1},
+        "command": {"Define": [0, {"BasicShape": {"Modulate": [
+            {"Terminal": {"Field": 1}}, {"Param": "r"}
+        ]}}]}
+    }}"#;
+    let Request {
+        id,
+        params,
+        program,
+    } = parse_request(request).unwrap();
+    assert_eq!(id, 3);
+
+    use extern_api::{Command, Shape, Terminal, Value};
+    let field = Box::new(Shape::Terminal(Terminal::Field(1.into())));
+    assert_eq!(
+        program.resolve(&params).unwrap().command,
+        Command::Define(0.into(), Value::BasicShape(Shape::Modulate(field, 4.0)))
+    );
+}
+
+#[test]
+fn messagepack_requests_can_take_parameters() {
+    use extern_api::params::{Float, Name};
+    use extern_api::{Command, Shape, Terminal, Value, Viewport};
+
+    let radius = Float::Param(Name::new("r"));
+    let circle = Terminal::Circle(::extern_api::Circle {
+        x: 0.0.into(),
+        y: 0.0.into(),
+        r: radius,
+    });
+    let program = Template {
+        command: Command::Define(0.into(), Value::BasicShape(Shape::Terminal(circle))),
+        viewport: Viewport::from_dimensions(10.0, 10.0),
+        defaults: vec![("r".to_string(), 1.0)].into_iter().collect(),
+    };
+    let bytes = ::rmp_serde::to_vec(&(3, &program)).unwrap();
+    let request = parse_request(&bytes).unwrap();
+    assert_eq!(request.program, program);
+    assert!(request.params.is_empty());
+
+    let params: Params = vec![("r".to_string(), 4.0)].into_iter().collect();
+    let bytes = ::rmp_serde::to_vec(&(3, &program, &params)).unwrap();
+    assert_eq!(parse_request(&bytes).unwrap().params, params);
+}
+
+#[test]
+fn truncated_frames_are_errors() {
+    let mut input = &[5u8, 0, 0, 0, 1, 2][..];
+    assert!(read_frame(&mut input).is_err());
+    let mut input = &[5u8, 0][..];
+    assert!(read_frame(&mut input).is_err());
+}
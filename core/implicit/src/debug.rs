@@ -1,12 +1,18 @@
 #![cfg_attr(not(test), allow(dead_code))]
 
-use euclid::TypedPoint2D;
+use euclid::{TypedPoint2D, UnknownUnit};
+use exec::Export;
+use extern_api::Viewport;
 use geometry::PathSegment;
 use image::{DynamicImage, ImageBuffer, ImageRgb8, Rgb, PNG};
+use line_stitch::{containment_tree, fit_curves, Curve, CurvePath};
 
 use opencl::FieldBuffer;
 use std::f32::{INFINITY, NEG_INFINITY};
-use std::io::{Result as IoResult, Write};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+use vectorphile::backend::{Command, DrawBackend, DrawOptions};
+use vectorphile::svg::SvgBackend;
+use vectorphile::Canvas;
 
 #[derive(Copy, Clone)]
 pub enum ColorMode {
@@ -67,25 +73,184 @@ fn save_image<W: Write>(samples: &[f32], width: usize, mut writer: W, color_mode
 }
 
 pub fn svg_path_segments<W: Write>(out: W, extracted: &[PathSegment]) -> IoResult<()> {
-    use vectorphile::{backend::*, svg::*, *};
-    let svgb = SvgBackend::new(out)?;
-    let mut canvas = Canvas::new(svgb);
-
-    let mut additive: Vec<Vec<_>> = vec![];
-    let mut subtractive: Vec<Vec<_>> = vec![];
-    let mut non_filled: Vec<Vec<_>> = vec![];
-    for segment in extracted.iter().cloned() {
-        if !segment.closed {
-            non_filled.push(segment.into_iter().collect());
-        } else if is_clockwise(&segment.path) {
-            additive.push(segment.into_iter().collect());
+    let mut canvas = Canvas::new(SvgBackend::new(out)?);
+    draw_path_segments(&mut canvas, extracted)?;
+    canvas.close()
+}
+
+/// Draws each export in a group of its own, with the export's id as the
+/// group's id.  With a `curve_tolerance`, the paths are drawn as the lines,
+/// arcs, and curves that fit them to within that distance, rather than
+/// point by point.  With `keep_order`, the paths are written in the order
+/// that they're in, from the point that each one starts at, so that a
+/// plotter draws them that way too.
+pub fn svg_exports<W: Write>(
+    out: W,
+    exports: &[Export],
+    curve_tolerance: Option<f32>,
+    keep_order: bool,
+) -> IoResult<()> {
+    let mut canvas = Canvas::new(SvgBackend::new(out)?);
+    for export in exports {
+        canvas.apply(Command::StartGroup {
+            id: export.id.to_string(),
+        })?;
+        match (curve_tolerance, keep_order) {
+            (Some(tolerance), true) => {
+                draw_in_order(&mut canvas, &export.lines, |canvas, path| {
+                    write_curve_path(canvas, &fit_curves(path, tolerance))
+                })?
+            }
+            (None, true) => draw_in_order(&mut canvas, &export.lines, write_points)?,
+            (Some(tolerance), false) => draw_curves(&mut canvas, &export.lines, tolerance)?,
+            (None, false) => draw_path_segments(&mut canvas, &export.lines)?,
+        }
+        canvas.apply(Command::EndGroup)?;
+    }
+    canvas.close()
+}
+
+// A hole has to be in the same shape as its outline to be cut out of it, so
+// each outline is drawn along with the holes directly inside of it, and the
+// holes aren't drawn again where they come up in the order.
+fn draw_in_order<W: Write, F>(
+    canvas: &mut Canvas<SvgBackend<W>>,
+    extracted: &[PathSegment],
+    write: F,
+) -> IoResult<()>
+where
+    F: Fn(&mut Canvas<SvgBackend<W>>, &PathSegment) -> IoResult<()>,
+{
+    let tree = containment_tree(extracted.to_vec());
+    let mut is_hole = vec![false; extracted.len()];
+    let mut holes: Vec<Vec<usize>> = vec![vec![]; extracted.len()];
+    for contour in tree.contours() {
+        if contour.is_hole() {
+            is_hole[contour.index] = true;
         } else {
-            subtractive.push(segment.into_iter().collect());
+            holes[contour.index] = contour.children.iter().map(|c| c.index).collect();
+            holes[contour.index].sort();
+        }
+    }
+
+    for (i, segment) in extracted.iter().enumerate() {
+        if !segment.closed {
+            canvas.apply(Command::StartShape(DrawOptions::default()))?;
+            write(canvas, segment)?;
+            canvas.apply(Command::CloseShape)?;
+        } else if !is_hole[i] {
+            canvas.apply(Command::StartShape(DrawOptions::filled((0, 0, 0))))?;
+            write(canvas, segment)?;
+            for &hole in &holes[i] {
+                write(canvas, &extracted[hole])?;
+            }
+            canvas.apply(Command::EndShape)?;
         }
     }
+    Ok(())
+}
+
+// Closed paths end with the point that they started from.
+fn write_points<W: Write>(
+    canvas: &mut Canvas<SvgBackend<W>>,
+    segment: &PathSegment,
+) -> IoResult<()> {
+    let end = if segment.closed {
+        segment.path.first().cloned()
+    } else {
+        None
+    };
+    for (i, point) in segment.path.iter().chain(&end).enumerate() {
+        canvas.apply(if i == 0 {
+            Command::MoveTo {
+                x: point.x as f64,
+                y: point.y as f64,
+            }
+        } else {
+            Command::LineTo {
+                x: point.x as f64,
+                y: point.y as f64,
+            }
+        })?;
+    }
+    Ok(())
+}
+
+// Closed paths are filled with the even-odd rule, so unlike with
+// `draw_holy_polygon`, it doesn't matter which way they wind.
+fn draw_curves<W: Write>(
+    canvas: &mut Canvas<SvgBackend<W>>,
+    extracted: &[PathSegment],
+    tolerance: f32,
+) -> IoResult<()> {
+    let (closed, open): (Vec<_>, Vec<_>) = extracted
+        .iter()
+        .map(|segment| fit_curves(segment, tolerance))
+        .partition(|path| path.closed);
+
+    canvas.apply(Command::StartShape(DrawOptions::filled((0, 0, 0))))?;
+    for path in &closed {
+        write_curve_path(canvas, path)?;
+    }
+    canvas.apply(Command::EndShape)?;
+    for path in &open {
+        canvas.apply(Command::StartShape(DrawOptions::default()))?;
+        write_curve_path(canvas, path)?;
+        canvas.apply(Command::CloseShape)?;
+    }
+    Ok(())
+}
+
+fn write_curve_path<W: Write>(
+    canvas: &mut Canvas<SvgBackend<W>>,
+    path: &CurvePath<UnknownUnit>,
+) -> IoResult<()> {
+    canvas.apply(Command::MoveTo {
+        x: path.start.x as f64,
+        y: path.start.y as f64,
+    })?;
+    for curve in &path.curves {
+        canvas.apply(match *curve {
+            Curve::Line(to) => Command::LineTo {
+                x: to.x as f64,
+                y: to.y as f64,
+            },
+            Curve::Cubic(c1, c2, to) => Command::CubicCurveTo {
+                cx1: c1.x as f64,
+                cy1: c1.y as f64,
+                cx2: c2.x as f64,
+                cy2: c2.y as f64,
+                x: to.x as f64,
+                y: to.y as f64,
+            },
+            Curve::Arc {
+                radius, sweep, to, ..
+            } => Command::ArcTo {
+                rx: radius as f64,
+                ry: radius as f64,
+                rotation: 0.0,
+                large_arc: sweep.abs() > ::std::f32::consts::PI,
+                sweep: sweep > 0.0,
+                x: to.x as f64,
+                y: to.y as f64,
+            },
+        })?;
+    }
+    Ok(())
+}
+
+fn draw_path_segments<W: Write>(
+    canvas: &mut Canvas<SvgBackend<W>>,
+    extracted: &[PathSegment],
+) -> IoResult<()> {
+    // Whether a path is an outline or a hole depends on how many paths it's
+    // inside of, not on which way it happens to wind.
+    let tree = containment_tree(extracted.to_vec());
+    let additive: Vec<Vec<_>> = tree.outlines().map(|p| p.path.to_vec()).collect();
+    let subtractive: Vec<Vec<_>> = tree.holes().map(|p| p.path.to_vec()).collect();
 
     canvas.draw_holy_polygon(additive, subtractive, DrawOptions::filled((0, 0, 0)))?;
-    for segment in non_filled {
+    for segment in tree.open {
         let mut is_first = true;
         canvas.apply(Command::StartShape(DrawOptions::default()))?;
         for point in segment {
@@ -104,7 +269,69 @@ pub fn svg_path_segments<W: Write>(out: W, extracted: &[PathSegment]) -> IoResul
         }
         canvas.apply(Command::CloseShape)?;
     }
-    canvas.close()
+    Ok(())
+}
+
+/// Rasterizes the paths over `viewport`, filling closed paths with the
+/// even-odd rule and drawing open paths one pixel wide.
+pub fn png_path_segments<W: Write>(
+    mut out: W,
+    extracted: &[PathSegment],
+    viewport: &Viewport,
+) -> IoResult<()> {
+    let _guard = ::flame::start_guard("png_path_segments");
+    let (width, height) = viewport.dimensions();
+    let to_pixel = viewport.world_to_pixel();
+    let mut buf = ImageBuffer::from_pixel(width, height, Rgb([255u8, 255, 255]));
+
+    let mut edges = vec![];
+    for segment in extracted.iter().filter(|s| s.closed) {
+        let points: Vec<_> = segment
+            .path
+            .iter()
+            .map(|p| to_pixel.transform_point(p))
+            .collect();
+        for (i, &a) in points.iter().enumerate() {
+            edges.push((a, points[(i + 1) % points.len()]));
+        }
+    }
+
+    let mut crossings = vec![];
+    for y in 0..height {
+        let center = y as f32 + 0.5;
+        crossings.clear();
+        for &(a, b) in &edges {
+            if (a.y <= center) != (b.y <= center) {
+                crossings.push(a.x + (center - a.y) / (b.y - a.y) * (b.x - a.x));
+            }
+        }
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        for span in crossings.chunks(2).filter(|span| span.len() == 2) {
+            let start = (span[0] - 0.5).ceil().max(0.0) as u32;
+            let end = (span[1] - 0.5).ceil().min(width as f32).max(0.0) as u32;
+            for x in start..end {
+                buf.put_pixel(x, y, Rgb([0, 0, 0]));
+            }
+        }
+    }
+
+    for segment in extracted.iter().filter(|s| !s.closed) {
+        for line in segment.path.windows(2) {
+            let a = to_pixel.transform_point(&line[0]);
+            let b = to_pixel.transform_point(&line[1]);
+            let steps = ((b - a).length() * 2.0).ceil().max(1.0) as u32;
+            for step in 0..steps + 1 {
+                let p = a.lerp(b, step as f32 / steps as f32);
+                if p.x >= 0.0 && p.y >= 0.0 && p.x < width as f32 && p.y < height as f32 {
+                    buf.put_pixel(p.x as u32, p.y as u32, Rgb([0, 0, 0]));
+                }
+            }
+        }
+    }
+
+    let d: DynamicImage = ImageRgb8(buf);
+    d.write_to(&mut out, PNG)
+        .map_err(|e| IoError::new(ErrorKind::Other, e.to_string()))
 }
 
 pub fn is_clockwise<K>(pts: &[TypedPoint2D<f32, K>]) -> bool {
@@ -134,3 +361,64 @@ pub fn print_path_segments<W: Write>(mut out: W, extracted: &[PathSegment]) {
         }
     }
 }
+
+#[test]
+fn ordered_svgs_keep_the_order_and_draw_holes_with_their_outlines() {
+    use euclid::point2;
+
+    fn square(x: f32, size: f32) -> PathSegment {
+        let mut segment = PathSegment::new(vec![
+            point2(x, 0.0),
+            point2(x, size),
+            point2(x + size, size),
+            point2(x + size, 0.0),
+        ]);
+        segment.closed = true;
+        segment
+    }
+    let mut hole = square(22.0, 2.0);
+    hole.path.iter_mut().for_each(|p| p.y += 2.0);
+    let export = Export {
+        id: "ordered".into(),
+        lines: vec![
+            square(50.0, 1.0),
+            hole,
+            PathSegment::new(vec![point2(30.0, 0.0), point2(40.0, 0.0)]),
+            square(20.0, 6.0),
+        ],
+    };
+
+    let mut out = vec![];
+    svg_exports(&mut out, &[export], None, true).unwrap();
+    let svg = String::from_utf8(out).unwrap();
+    let shapes: Vec<Vec<_>> = svg
+        .split("<path")
+        .skip(1)
+        .map(|shape| shape.lines().filter(|l| l.starts_with('M')).collect())
+        .collect();
+    assert_eq!(
+        shapes,
+        vec![
+            vec!["M50.00000,0.00000 "],
+            vec!["M30.00000,0.00000 "],
+            vec!["M20.00000,0.00000 ", "M22.00000,2.00000 "],
+        ]
+    );
+}
+
+#[test]
+fn pngs_can_be_drawn_from_points_that_are_not_numbers() {
+    use euclid::point2;
+    use std::f32::NAN;
+
+    let mut segment = PathSegment::new(vec![
+        point2(1.0, 1.0),
+        point2(NAN, 5.0),
+        point2(8.0, 8.0),
+        point2(8.0, 1.0),
+    ]);
+    segment.closed = true;
+    let mut out = vec![];
+    png_path_segments(&mut out, &[segment], &Viewport::from_dimensions(10.0, 10.0)).unwrap();
+    assert!(!out.is_empty());
+}
@@ -0,0 +1,387 @@
+use euclid::{point2, vec2};
+use geometry::{PathSegment, Point, Vector};
+use line_stitch::connect_indexed;
+
+const NORTH: usize = 0;
+const EAST: usize = 1;
+const SOUTH: usize = 2;
+const WEST: usize = 3;
+
+/// The lines through a cell for each case, as (from, to) pairs of its sides,
+/// the same as in `shaders/marching_edges.c`.
+const LINES: [&[(usize, usize)]; 16] = [
+    &[],
+    &[(WEST, SOUTH)],
+    &[(SOUTH, EAST)],
+    &[(WEST, EAST)],
+    &[(EAST, NORTH)],
+    &[(EAST, NORTH), (WEST, SOUTH)],
+    &[(SOUTH, NORTH)],
+    &[(WEST, NORTH)],
+    &[(NORTH, WEST)],
+    &[(NORTH, SOUTH)],
+    &[(SOUTH, EAST), (NORTH, WEST)],
+    &[(NORTH, EAST)],
+    &[(EAST, WEST)],
+    &[(EAST, SOUTH)],
+    &[(SOUTH, WEST)],
+    &[],
+];
+
+/// The lines through the saddle cases when the middle of the cell is inside.
+const JOINED_LINES: [&[(usize, usize)]; 2] = [
+    &[(WEST, NORTH), (EAST, SOUTH)],
+    &[(NORTH, EAST), (SOUTH, WEST)],
+];
+
+/// Normals that are closer together than this (as a ratio of the eigenvalues
+/// of the QEF) are treated as one, so flat and gently curved surfaces put
+/// their vertices at the middle of their crossings instead of chasing noise.
+const FEATURE_RATIO: f32 = 0.1;
+
+/// What's known about a surface beyond the samples of its grid.
+pub trait Surface {
+    /// The gradient of the field at `p`, or `None` where it isn't known.
+    fn gradient(&self, p: Point) -> Option<Vector>;
+
+    /// Where the surface crosses the grid edge from `a` to `b`, given the
+    /// `estimate` that's interpolated from the samples at either end.
+    fn crossing(&self, _a: Point, _b: Point, estimate: Point) -> Point {
+        estimate
+    }
+
+    /// Moves `p`, a point near the surface, onto it.
+    fn snap(&self, p: Point) -> Point {
+        p
+    }
+}
+
+impl<F> Surface for F
+where
+    F: Fn(Point) -> Option<Vector>,
+{
+    fn gradient(&self, p: Point) -> Option<Vector> {
+        self(p)
+    }
+}
+
+/// The samples of a field, as they're laid out in a `FieldBuffer`.
+pub struct Grid<'a> {
+    values: &'a [f32],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Grid<'a> {
+    pub fn new(values: &'a [f32], width: usize, height: usize) -> Grid<'a> {
+        assert_eq!(values.len(), width * height);
+        Grid {
+            values,
+            width,
+            height,
+        }
+    }
+
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.values[x + y * self.width]
+    }
+
+    fn inside(&self, x: usize, y: usize) -> bool {
+        self.at(x, y) <= 0.0
+    }
+
+    /// The gradient at a sample, by central differences (or one sided ones
+    /// along the border).
+    fn sample_gradient(&self, x: usize, y: usize) -> Option<Vector> {
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(self.width - 1));
+        let (y0, y1) = (y.saturating_sub(1), (y + 1).min(self.height - 1));
+        let dx = (self.at(x1, y) - self.at(x0, y)) / (x1 - x0).max(1) as f32;
+        let dy = (self.at(x, y1) - self.at(x, y0)) / (y1 - y0).max(1) as f32;
+        if dx.is_finite() && dy.is_finite() {
+            Some(vec2(dx, dy))
+        } else {
+            None
+        }
+    }
+
+    /// The gradient of the field at `p`, interpolated from the finite
+    /// differences at the samples around it.  `None` where the field isn't
+    /// finite.
+    pub fn gradient(&self, p: Point) -> Option<Vector> {
+        let x = (p.x.floor().max(0.0) as usize).min(self.width.saturating_sub(2));
+        let y = (p.y.floor().max(0.0) as usize).min(self.height.saturating_sub(2));
+        let (tx, ty) = (p.x - x as f32, p.y - y as f32);
+        let x1 = (x + 1).min(self.width - 1);
+        let y1 = (y + 1).min(self.height - 1);
+        let top = self
+            .sample_gradient(x, y)?
+            .lerp(self.sample_gradient(x1, y)?, tx);
+        let bottom = self
+            .sample_gradient(x, y1)?
+            .lerp(self.sample_gradient(x1, y1)?, tx);
+        Some(top.lerp(bottom, ty))
+    }
+
+    /// Where the surface crosses from sample `a` to sample `b`, which are on
+    /// opposite sides of it.
+    fn crossing(&self, a: (usize, usize), b: (usize, usize)) -> Point {
+        let (fa, fb) = (self.at(a.0, a.1), self.at(b.0, b.1));
+        let t = match (fa.is_infinite(), fb.is_infinite()) {
+            (true, true) => 0.5,
+            (true, false) => 1.0,
+            (false, true) => 0.0,
+            (false, false) => fa / (fa - fb),
+        };
+        let a = point2(a.0 as f32, a.1 as f32);
+        let b = point2(b.0 as f32, b.1 as f32);
+        a.lerp(b, t)
+    }
+
+    fn lines(&self, x: usize, y: usize) -> &'static [(usize, usize)] {
+        let which = (self.inside(x, y) as usize) << 3
+            | (self.inside(x + 1, y) as usize) << 2
+            | (self.inside(x + 1, y + 1) as usize) << 1
+            | (self.inside(x, y + 1) as usize);
+        let center =
+            (self.at(x, y) + self.at(x + 1, y) + self.at(x + 1, y + 1) + self.at(x, y + 1)) * 0.25;
+        match which {
+            5 if center <= 0.0 => JOINED_LINES[0],
+            10 if center <= 0.0 => JOINED_LINES[1],
+            _ => LINES[which],
+        }
+    }
+
+    /// The id of the grid edge on `side` of the cell at `(x, y)`, numbered
+    /// the same way as in `run_marching_edges`.
+    fn edge(&self, x: usize, y: usize, side: usize) -> usize {
+        let pos = x + y * self.width;
+        match side {
+            NORTH => pos * 2,
+            EAST => (pos + 1) * 2 + 1,
+            SOUTH => (pos + self.width) * 2,
+            _ => pos * 2 + 1,
+        }
+    }
+
+    /// The samples at either end of the grid edge on `side` of a cell.
+    fn edge_ends(x: usize, y: usize, side: usize) -> ((usize, usize), (usize, usize)) {
+        match side {
+            NORTH => ((x, y), (x + 1, y)),
+            EAST => ((x + 1, y), (x + 1, y + 1)),
+            SOUTH => ((x, y + 1), (x + 1, y + 1)),
+            _ => ((x, y), (x, y + 1)),
+        }
+    }
+
+    /// The cell on the other side of `side`, if it's in the grid.
+    fn neighbor(&self, x: usize, y: usize, side: usize) -> Option<(usize, usize)> {
+        let (cells_x, cells_y) = (self.width - 1, self.height - 1);
+        match side {
+            NORTH if y > 0 => Some((x, y - 1)),
+            EAST if x + 1 < cells_x => Some((x + 1, y)),
+            SOUTH if y + 1 < cells_y => Some((x, y + 1)),
+            WEST if x > 0 => Some((x - 1, y)),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Surface for Grid<'a> {
+    fn gradient(&self, p: Point) -> Option<Vector> {
+        Grid::gradient(self, p)
+    }
+}
+
+fn opposite(side: usize) -> usize {
+    (side + 2) % 4
+}
+
+/// Minimizes the squared distance to the lines through each crossing along
+/// its surface.  The result is solved for relative to the middle of the
+/// crossings, and directions that the normals don't pin down are left there.
+fn feature_point(crossings: &[(Point, Option<Vector>)]) -> Point {
+    let mut mass = vec2(0.0, 0.0);
+    for &(p, _) in crossings {
+        mass += p.to_vector();
+    }
+    let mass = (mass / crossings.len() as f32).to_point();
+
+    let (mut a11, mut a12, mut a22, mut b1, mut b2) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for &(p, normal) in crossings {
+        let n = match normal {
+            Some(n) if n.square_length() > 0.0 => n / n.length(),
+            _ => continue,
+        };
+        let d = n.dot(p - mass);
+        a11 += n.x * n.x;
+        a12 += n.x * n.y;
+        a22 += n.y * n.y;
+        b1 += n.x * d;
+        b2 += n.y * d;
+    }
+
+    // The eigenvalues and eigenvectors of the symmetric matrix
+    // [[a11, a12], [a12, a22]].
+    let half_trace = (a11 + a22) / 2.0;
+    let det = a11 * a22 - a12 * a12;
+    let disc = (half_trace * half_trace - det).max(0.0).sqrt();
+    let (l1, l2) = (half_trace + disc, half_trace - disc);
+    if l1 <= 0.0 {
+        return mass;
+    }
+    let e1 = if a12.abs() > 1e-6 {
+        let e = vec2(l1 - a22, a12);
+        e / e.length()
+    } else if a11 >= a22 {
+        vec2(1.0, 0.0)
+    } else {
+        vec2(0.0, 1.0)
+    };
+    let e2 = vec2(-e1.y, e1.x);
+    let b = vec2(b1, b2);
+
+    let mut offset = e1 * (e1.dot(b) / l1);
+    if l2 > l1 * FEATURE_RATIO {
+        offset += e2 * (e2.dot(b) / l2);
+    }
+    mass + offset
+}
+
+/// Traces the surface of `grid` with dual contouring.  Instead of putting
+/// the points of a path on the edges of the grid as marching squares does,
+/// each cell gets one point (two, for saddles) at the feature inside it,
+/// where the surfaces through its crossings meet, so corners stay sharp.
+///
+/// `surface` gives the normal of the surface at each crossing, and can move
+/// the crossings themselves: pass the grid itself for finite differences
+/// over it, or something exact if more is known about the field.  Paths wind
+/// the same way as `extract_lines`, and open ones end where the surface
+/// leaves the grid.
+pub fn dual_contour<S>(grid: &Grid, surface: &S) -> Vec<PathSegment>
+where
+    S: Surface,
+{
+    let _guard = ::flame::start_guard("dual_contour");
+    if grid.width < 2 || grid.height < 2 {
+        return vec![];
+    }
+
+    // Points are named by the cell that they're in (and which of its lines
+    // they're on), or, past those, by the grid edge that a path leaves on.
+    let cells = (grid.width - 1) * (grid.height - 1);
+    let vertex = |x: usize, y: usize, line: usize| (x + y * (grid.width - 1)) * 2 + line;
+    let border = |x: usize, y: usize, side: usize| cells * 2 + grid.edge(x, y, side);
+
+    let mut points = vec![point2(f32::NAN, f32::NAN); cells * 2 + grid.width * grid.height * 2];
+    let mut lines = vec![];
+    for y in 0..grid.height - 1 {
+        for x in 0..grid.width - 1 {
+            for (i, &(from, to)) in grid.lines(x, y).iter().enumerate() {
+                let crossings: Vec<_> = [from, to]
+                    .iter()
+                    .map(|&side| {
+                        let (a, b) = Grid::edge_ends(x, y, side);
+                        let estimate = grid.crossing(a, b);
+                        let (a, b) = (
+                            point2(a.0 as f32, a.1 as f32),
+                            point2(b.0 as f32, b.1 as f32),
+                        );
+                        let p = surface.crossing(a, b, estimate);
+                        (p, surface.gradient(p))
+                    })
+                    .collect();
+                let here = vertex(x, y, i);
+                let p = feature_point(&crossings);
+                let p = point2(
+                    p.x.max(x as f32).min(x as f32 + 1.0),
+                    p.y.max(y as f32).min(y as f32 + 1.0),
+                );
+                points[here] = surface.snap(p);
+
+                match grid.neighbor(x, y, to) {
+                    Some((nx, ny)) => {
+                        let next = grid
+                            .lines(nx, ny)
+                            .iter()
+                            .position(|&(next_from, _)| next_from == opposite(to))
+                            .expect("the surface crosses into the next cell");
+                        lines.push((here, vertex(nx, ny, next)));
+                    }
+                    None => {
+                        let end = border(x, y, to);
+                        points[end] = crossings[1].0;
+                        lines.push((here, end));
+                    }
+                }
+                if grid.neighbor(x, y, from).is_none() {
+                    let start = border(x, y, from);
+                    points[start] = crossings[0].0;
+                    lines.push((start, here));
+                }
+            }
+        }
+    }
+
+    connect_indexed(&lines, |id| points[id])
+}
+
+#[test]
+fn corners_stay_sharp() {
+    // A square from 2.3 to 7.6, which puts its corners inside of cells.
+    let (width, height) = (10, 10);
+    let mut values = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            let (x, y) = (x as f32, y as f32);
+            values.push((2.3 - x).max(x - 7.6).max(2.3 - y).max(y - 7.6));
+        }
+    }
+    let grid = Grid::new(&values, width, height);
+    let paths = dual_contour(&grid, &grid);
+
+    assert_eq!(paths.len(), 1);
+    assert!(paths[0].closed);
+    let near = |corner: Point| paths[0].path.iter().any(|p| (*p - corner).length() < 0.25);
+    // The gradients are blurred across the corners, so they aren't found
+    // exactly, but marching squares would cut (2.3, 2.3) off by 0.7.
+    assert!(near(point2(2.3, 2.3)));
+    assert!(near(point2(7.6, 2.3)));
+    assert!(near(point2(7.6, 7.6)));
+    assert!(near(point2(2.3, 7.6)));
+    // Points along the sides stay on them.
+    for p in &paths[0].path {
+        let off = (p.x - 2.3)
+            .abs()
+            .min((p.x - 7.6).abs())
+            .min((p.y - 2.3).abs())
+            .min((p.y - 7.6).abs());
+        assert!(off < 0.15, "{:?} is off of the square", p);
+    }
+}
+
+#[test]
+fn exact_gradients_find_the_corner() {
+    let (width, height) = (6, 6);
+    let mut values = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            values.push((2.5 - x as f32).max(2.5 - y as f32));
+        }
+    }
+    let grid = Grid::new(&values, width, height);
+    // The quadrant past (2.5, 2.5), whose normal points away from whichever
+    // edge is nearer.
+    let paths = dual_contour(&grid, &|p: Point| {
+        Some(if 2.5 - p.x > 2.5 - p.y {
+            vec2(-1.0, 0.0)
+        } else {
+            vec2(0.0, -1.0)
+        })
+    });
+
+    assert_eq!(paths.len(), 1);
+    assert!(!paths[0].closed);
+    assert!(paths[0].path.contains(&point2(2.5, 2.5)));
+    assert_eq!(paths[0].path.first(), Some(&point2(2.5, 5.0)));
+    assert_eq!(paths[0].path.last(), Some(&point2(5.0, 2.5)));
+}
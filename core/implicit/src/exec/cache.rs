@@ -0,0 +1,213 @@
+use compiler::CompiledShape;
+use extern_api::Viewport;
+use fnv::{FnvHashMap, FnvHashSet, FnvHasher};
+use geometry::PathSegment;
+use opencl::FieldBuffer;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+/// Names the result of a command by everything that went into computing it:
+/// the kind of command, its arguments, the viewport, and the keys of the
+/// fields that it reads from.  Two commands with the same key produce the
+/// same result, no matter which id the result is stored under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key(pub u64);
+
+/// Goes into every key, so that fields written to a cache directory by a
+/// build that computes them differently are never read back.  Bump the
+/// number after the crate version whenever the compiler, the kernels, or the
+/// buffer format change what a command produces.
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+1");
+
+impl Key {
+    pub fn of<T: Serialize>(kind: &str, value: &T, inputs: &[Key], viewport: &Viewport) -> Key {
+        let mut hasher = FnvHasher::default();
+        hasher.write(VERSION.as_bytes());
+        hasher.write(kind.as_bytes());
+        hasher.write(&::rmp_serde::to_vec(value).unwrap());
+        hasher.write(&::rmp_serde::to_vec(viewport).unwrap());
+        for input in inputs {
+            hasher.write_u64(input.0);
+        }
+        Key(hasher.finish())
+    }
+}
+
+/// The fields and exported lines computed by earlier runs.  Passing the same
+/// cache to each run of a program that is being edited means that only the
+/// commands downstream of an edit are recomputed.
+#[derive(Default)]
+pub struct Cache {
+    fields: FnvHashMap<Key, FieldBuffer>,
+    lines: FnvHashMap<Key, Vec<PathSegment>>,
+    compiled: FnvHashMap<Key, CompiledShape>,
+    used: FnvHashSet<Key>,
+    directory: Option<PathBuf>,
+    hits: usize,
+    misses: usize,
+}
+
+impl Cache {
+    pub fn new() -> Cache {
+        Cache::default()
+    }
+
+    /// A cache that also writes fields into `directory`, so that they can be
+    /// reused by later processes.  Entries in the directory are never
+    /// removed, and entries from other versions of the compiler are never
+    /// read (see `VERSION`).
+    pub fn with_directory(directory: PathBuf) -> io::Result<Cache> {
+        fs::create_dir_all(&directory)?;
+        Ok(Cache {
+            directory: Some(directory),
+            ..Cache::default()
+        })
+    }
+
+    pub fn field<F>(&mut self, key: Key, compute: F) -> FieldBuffer
+    where
+        F: FnOnce() -> FieldBuffer,
+    {
+        self.used.insert(key);
+        if let Some(field) = self.fields.get(&key) {
+            self.hits += 1;
+            return field.clone();
+        }
+
+        let field = match self.load(key) {
+            Some(field) => {
+                self.hits += 1;
+                field
+            }
+            None => {
+                self.misses += 1;
+                let mut field = compute();
+                self.store(key, &mut field);
+                field
+            }
+        };
+        self.fields.insert(key, field.clone());
+        field
+    }
+
+    pub fn lines<F>(&mut self, key: Key, compute: F) -> Vec<PathSegment>
+    where
+        F: FnOnce() -> Vec<PathSegment>,
+    {
+        self.used.insert(key);
+        if let Some(lines) = self.lines.get(&key) {
+            self.hits += 1;
+            return lines.clone();
+        }
+
+        self.misses += 1;
+        let lines = compute();
+        self.lines.insert(key, lines.clone());
+        lines
+    }
+
+    /// A shape that reads parameters, compiled once and kept for as long as
+    /// it's used, so that runs with new values for the parameters only need
+    /// to run it again.  These aren't counted in `stats`.
+    pub fn compiled<F>(&mut self, key: Key, compile: F) -> &CompiledShape
+    where
+        F: FnOnce() -> CompiledShape,
+    {
+        self.used.insert(key);
+        self.compiled.entry(key).or_insert_with(compile)
+    }
+
+    /// Forgets everything in memory that hasn't been used since the last
+    /// call, so that the cache only ever holds onto about one program's
+    /// worth of results.
+    pub fn finish_run(&mut self) {
+        let used = &self.used;
+        self.fields.retain(|key, _| used.contains(key));
+        self.lines.retain(|key, _| used.contains(key));
+        self.compiled.retain(|key, _| used.contains(key));
+        self.used.clear();
+    }
+
+    /// How many results were reused and how many were computed, since the
+    /// cache was created.
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits, self.misses)
+    }
+
+    fn path(&self, key: Key) -> Option<PathBuf> {
+        self.directory
+            .as_ref()
+            .map(|directory| directory.join(format!("{:016x}.buf", key.0)))
+    }
+
+    // The disk cache is only an optimization, so entries that can't be read
+    // or written are treated as missing.
+    fn load(&self, key: Key) -> Option<FieldBuffer> {
+        let file = File::open(self.path(key)?).ok()?;
+        ::buffer_dump::read(BufReader::new(file)).ok()
+    }
+
+    fn store(&self, key: Key, field: &mut FieldBuffer) {
+        if let Some(path) = self.path(key) {
+            let written = File::create(&path).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                ::buffer_dump::write(&mut writer, field)?;
+                writer.flush()
+            });
+            if written.is_err() {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+#[test]
+fn keys_depend_on_their_inputs() {
+    use extern_api::*;
+    let viewport = Viewport::from_dimensions(10.0, 10.0);
+    let circle = |r| Shape::Terminal(Terminal::Circle(Circle { x: 1.0, y: 2.0, r }));
+    let key = Key::of("shape", &circle(3.0), &[], &viewport);
+    assert_eq!(key, Key::of("shape", &circle(3.0), &[], &viewport));
+    assert_ne!(key, Key::of("shape", &circle(4.0), &[], &viewport));
+    assert_ne!(key, Key::of("shape", &circle(3.0), &[Key(1)], &viewport));
+    assert_ne!(key, Key::of("poly", &circle(3.0), &[], &viewport));
+
+    let larger = Viewport::from_dimensions(20.0, 10.0);
+    assert_ne!(key, Key::of("shape", &circle(3.0), &[], &larger));
+}
+
+#[test]
+fn reuses_fields_until_they_go_unused() {
+    let field = || FieldBuffer::from_memory(vec![1.0, 2.0], 2, 1, 1);
+    let mut cache = Cache::new();
+
+    cache.field(Key(1), field);
+    cache.field(Key(2), field);
+    cache.finish_run();
+    assert_eq!(cache.stats(), (0, 2));
+
+    cache.field(Key(1), || panic!("should have been cached"));
+    cache.finish_run();
+    assert_eq!(cache.stats(), (1, 2));
+
+    // Key(2) wasn't used by the last run, so it was forgotten.
+    cache.field(Key(2), field);
+    assert_eq!(cache.stats(), (1, 3));
+}
+
+#[test]
+fn reads_fields_back_from_disk() {
+    let directory = ::std::env::temp_dir().join(format!("implicit-cache-{}", ::std::process::id()));
+    let mut first = Cache::with_directory(directory.clone()).unwrap();
+    first.field(Key(7), || FieldBuffer::from_memory(vec![1.0, 2.0], 2, 1, 1));
+
+    let mut second = Cache::with_directory(directory.clone()).unwrap();
+    let mut field = second.field(Key(7), || panic!("should have been on disk"));
+    assert_eq!(field.to_memory(), &[1.0, 2.0]);
+    assert_eq!(second.stats(), (1, 0));
+
+    fs::remove_dir_all(directory).unwrap();
+}
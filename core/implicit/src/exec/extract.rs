@@ -1,6 +1,9 @@
+use dual_contour::{dual_contour, Grid};
+use extern_api::{Contouring, ExportOptions};
 use geometry::PathSegment;
 use inspector::*;
 use opencl::{FieldBuffer, OpenClContext};
+use refine::ExactField;
 
 #[cfg(test)]
 use expectation::{extensions::TextDiffExtension, Provider};
@@ -8,25 +11,78 @@ use expectation_plugin::expectation_test;
 #[cfg(test)]
 use extern_api::Shape;
 
+/// Traces the surface of `field` into paths.  Marching squares names the
+/// ends of each line by the grid edge that they lie on, so lines are joined
+/// wherever they share an edge, without any matching of nearby points.  With
+/// `Contouring::Dual`, the field is read back and traced by `dual_contour`
+/// instead.
+///
+/// With an `exact` version of the field, each point is moved from where it's
+/// interpolated to between samples onto the surface itself.  `centers`
+/// samples the field in the middle of each cell, for marching squares to
+/// join saddle cells by (see `run_marching_edges`).
+///
+/// `options.stitch` can join the lines from marching squares by the distance
+/// between their ends with `connect_lines` instead, and simplifies the paths
+/// either way.
 pub fn extract_lines(
     ctx: &OpenClContext,
     inspector: BoxedInspector,
     field: &mut FieldBuffer,
+    options: &ExportOptions,
+    exact: Option<&ExactField>,
+    centers: Option<&mut FieldBuffer>,
 ) -> Vec<PathSegment> {
     use euclid::point2;
-    use itertools::Itertools;
-    use lines::connect_lines;
+    use line_stitch::connect_indexed;
+    use lines::{connect_lines, simplify_paths};
 
-    let (lines, count) = ::marching::run_marching(field, ctx);
-    let lines = lines.values(Some(count));
+    if options.contouring == Contouring::Dual {
+        let (width, height) = (field.width as usize, field.height as usize);
+        let grid = Grid::new(field.to_memory(), width, height);
+        let paths = match exact {
+            Some(exact) => dual_contour(&grid, exact),
+            None => dual_contour(&grid, &grid),
+        };
+        inspector.write_segments("dual", &paths);
+        return simplify_paths(paths, &options.stitch, inspector);
+    }
+
+    let (lines, crossings) = ::marching::run_marching_edges(field, centers, ctx);
     let lines = lines
-        .into_iter()
-        .tuples::<(_, _, _, _)>()
-        .take_while(|&(a, b, c, d)| !(a.is_nan() || b.is_nan() || c.is_nan() || d.is_nan()))
-        .map(|(a, b, c, d)| (point2(a, b), point2(c, d)))
+        .values(None)
+        .chunks(2)
+        .filter(|line| line[0] >= 0)
+        .map(|line| (line[0] as usize, line[1] as usize))
         .collect::<Vec<_>>();
+    let crossings = crossings.values(None);
+
+    // Even edges run right from their sample, and odd ones run down.
+    let width = field.width as usize;
+    let point = |edge: usize| {
+        let estimate = point2(crossings[edge * 2], crossings[edge * 2 + 1]);
+        match exact {
+            Some(exact) => {
+                let (x, y) = ((edge / 2 % width) as f32, (edge / 2 / width) as f32);
+                let a = point2(x, y);
+                let b = if edge % 2 == 0 {
+                    point2(x + 1.0, y)
+                } else {
+                    point2(x, y + 1.0)
+                };
+                exact.refine(a, b, estimate)
+            }
+            None => estimate,
+        }
+    };
 
-    connect_lines(lines, inspector)
+    if !options.stitch.by_edge {
+        let lines: Vec<_> = lines.iter().map(|&(a, b)| (point(a), point(b))).collect();
+        return connect_lines(lines, &options.stitch, inspector);
+    }
+    let paths = connect_indexed(&lines, point);
+    inspector.write_segments("stitched", &paths);
+    simplify_paths(paths, &options.stitch, inspector)
 }
 
 #[cfg(test)]
@@ -44,7 +100,14 @@ fn run_shape_paths(shape: Shape, width: u32, height: u32, provider: Provider) {
         height,
         |_| unimplemented!(),
     );
-    let mut extracted = extract_lines(&ctx, provider.duplicate(), &mut buffer);
+    let mut extracted = extract_lines(
+        &ctx,
+        provider.duplicate(),
+        &mut buffer,
+        &ExportOptions::default(),
+        None,
+        None,
+    );
     extracted.sort();
 
     let out = provider.text_writer("out.lines.txt");
@@ -0,0 +1,43 @@
+use exec::{exec_shape, extract_lines};
+use extern_api::{ExportOptions, Shape, Terminal};
+use geometry::PathSegment;
+use inspector::BoxedInspector;
+use opencl::{FieldBuffer, OpenClContext};
+
+/// Rings inside of the surface of `field`, each `spacing` (in world units)
+/// further in than the last, until there's nothing left inside.  The rings
+/// are traced the same way that the surface is, and are in pixels.
+pub fn exec_concentric(
+    ctx: &OpenClContext,
+    inspector: BoxedInspector,
+    field: &FieldBuffer,
+    spacing: f32,
+    resolution: f32,
+    options: &ExportOptions,
+) -> Vec<PathSegment> {
+    let (width, height) = (field.width, field.height);
+    if spacing <= 0.0 || !spacing.is_finite() {
+        return vec![];
+    }
+    // Nothing can be further inside than half of the field's width, but a
+    // field that isn't a distance field can shrink by less than `spacing`.
+    let limit = (width.max(height) as f32 / (spacing * resolution)).ceil() as usize;
+
+    let mut rings = vec![];
+    for k in 1..limit + 1 {
+        let inspector = inspector.specialize(&format!("ring_{}", k));
+        let shape = Shape::Modulate(
+            Box::new(Shape::Terminal(Terminal::Field("infill".into()))),
+            -(k as f32) * spacing,
+        );
+        let mut inset = exec_shape(ctx, inspector.duplicate(), shape, width, height, |_| {
+            field.clone()
+        });
+        let ring = extract_lines(ctx, inspector, &mut inset, options, None, None);
+        if ring.is_empty() {
+            break;
+        }
+        rings.extend(ring);
+    }
+    rings
+}
@@ -1,23 +1,61 @@
+mod cache;
 mod drag;
 mod extract;
 mod freeze;
+mod infill;
 mod noise;
 mod poly;
 mod shape;
 
+pub use self::cache::*;
 pub use self::drag::*;
 pub use self::extract::*;
 pub use self::freeze::*;
+pub use self::infill::*;
 pub use self::noise::*;
 pub use self::poly::*;
 pub use self::shape::*;
 
+use compiler::CompiledShape;
+use euclid::Transform2D;
 use expectation_plugin::expectation_test;
+use extern_api::bbox::{polygon_bounds, shape_bounds, Bounding};
+use extern_api::params::{Float, Params, Template};
+use extern_api::simplify::{simplify, Simplified};
 use extern_api::*;
 use geometry::PathSegment;
 use inspector::*;
+use line_stitch::{cross_hatch, hatch};
 use opencl::{FieldBuffer, OpenClContext};
+use program::{resolve_includes, ParseError};
+use refine::ExactField;
 use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct Export {
+    pub id: Id,
+    pub lines: Vec<PathSegment>,
+}
+
+// A field along with what's needed to name it in the cache and to prune the
+// shapes that read from it.  Fields that were defined by a shape keep it (in
+// pixels) so that their exports can be refined against it.
+struct Field {
+    key: Key,
+    buffer: FieldBuffer,
+    bounds: Bounding,
+    shape: Option<Shape>,
+}
+
+// What every command of a run shares: the viewport, and, when a template is
+// run, the shapes that read its parameters (by the id that they're defined
+// under) along with the values that the parameters were given.
+struct Run<'a> {
+    viewport: &'a Viewport,
+    shapes: HashMap<Id, &'a Shape<Float>>,
+    values: Params,
+}
 
 #[cfg(test)]
 use expectation::{extensions::TextDiffExtension, Provider};
@@ -27,66 +65,299 @@ pub fn exec(
     inspector: BoxedInspector,
     width: u32,
     height: u32,
-) -> HashMap<Id, Vec<PathSegment>> {
-    let ctx = OpenClContext::default();
+) -> Result<Vec<Export>, ParseError> {
+    let viewport = Viewport::from_dimensions(width as f32, height as f32);
+    exec_program(Program { command, viewport }, inspector)
+}
+
+/// Runs `command` over a viewport that is fitted around its exports, or
+/// returns `None` if they are all empty or unbounded.
+pub fn exec_fitted(
+    mut command: Command,
+    inspector: BoxedInspector,
+    resolution: f32,
+) -> Result<Option<Vec<Export>>, ParseError> {
+    // Includes have to be resolved before the exports can be measured.
+    resolve_includes(&mut command, Path::new("."))?;
+    match Viewport::fit(&command, resolution) {
+        Some(viewport) => exec_program(Program { command, viewport }, inspector).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Runs a program, returning its exports in world coordinates.
+pub fn exec_program(
+    program: Program,
+    inspector: BoxedInspector,
+) -> Result<Vec<Export>, ParseError> {
+    exec_program_with_context(&OpenClContext::default(), program, inspector)
+}
+
+pub fn exec_program_with_context(
+    ctx: &OpenClContext,
+    program: Program,
+    inspector: BoxedInspector,
+) -> Result<Vec<Export>, ParseError> {
+    exec_program_with_cache(ctx, program, inspector, &mut Cache::new())
+}
+
+/// Runs `template` with `params` filled in.  With the same `cache` for each
+/// set of parameters, only the commands that read a changed parameter (and
+/// the ones downstream of them) are run again, and the shapes that read one
+/// are compiled once, with the parameters left as inputs to the program.
+///
+/// Like every way of running a program, this first resolves its includes
+/// (relative to the working directory, if they weren't already resolved
+/// when it was loaded), and fails without running anything if the program
+/// is invalid.
+pub fn exec_template(
+    ctx: &OpenClContext,
+    template: &Template,
+    params: &Params,
+    inspector: BoxedInspector,
+    cache: &mut Cache,
+) -> Result<Vec<Export>, ParseError> {
+    let mut template = template.clone();
+    resolve_includes(&mut template.command, Path::new("."))?;
+    let program = template.resolve(params).map_err(ParseError::Params)?;
+    let mut run = Run {
+        viewport: &program.viewport,
+        shapes: HashMap::new(),
+        values: template.values(params).map_err(ParseError::Params)?,
+    };
+    parametric_shapes(&template.command, &mut run.shapes);
+    Ok(exec_run(ctx, program.command, &run, inspector, cache))
+}
+
+fn parametric_shapes<'a>(command: &'a Command<Float>, shapes: &mut HashMap<Id, &'a Shape<Float>>) {
+    match command {
+        Command::Concurrently(commands) | Command::Serially(commands) => {
+            for command in commands {
+                parametric_shapes(command, shapes);
+            }
+        }
+        Command::Define(id, Value::BasicShape(shape))
+            if shape.try_map(&mut |f: Float| f.value().ok_or(())).is_err() =>
+        {
+            shapes.insert(id.clone(), shape);
+        }
+        _ => {}
+    }
+}
+
+/// Runs a program, reusing any results in `cache` from earlier runs.
+pub fn exec_program_with_cache(
+    ctx: &OpenClContext,
+    program: Program,
+    inspector: BoxedInspector,
+    cache: &mut Cache,
+) -> Result<Vec<Export>, ParseError> {
+    let template = Template::from_program(&program);
+    exec_template(ctx, &template, &Params::new(), inspector, cache)
+}
+
+fn exec_run(
+    ctx: &OpenClContext,
+    command: Command,
+    run: &Run,
+    inspector: BoxedInspector,
+    cache: &mut Cache,
+) -> Vec<Export> {
     let mut mapping = HashMap::new();
-    let mut output = HashMap::new();
+    let mut output = vec![];
     exec_inner(
-        &ctx,
+        ctx,
         command,
         &mut mapping,
         &mut output,
         inspector,
-        width,
-        height,
+        run,
+        cache,
     );
+    cache.finish_run();
     output
 }
 
+// Fields are sampled at pixels, but hold distances measured in world units.
 fn exec_inner(
     ctx: &OpenClContext,
     command: Command,
-    mapping: &mut HashMap<Id, FieldBuffer>,
-    output: &mut HashMap<Id, Vec<PathSegment>>,
+    mapping: &mut HashMap<Id, Field>,
+    output: &mut Vec<Export>,
     inspector: BoxedInspector,
-    width: u32,
-    height: u32,
+    run: &Run,
+    cache: &mut Cache,
 ) {
+    let viewport = run.viewport;
+    let (width, height) = viewport.dimensions();
+    let to_pixel = viewport.world_to_pixel();
+    let is_identity = to_pixel == Matrix::identity();
     match command {
         Command::Simplex(id, simplex) => {
-            let mut field = get_noise(ctx, width, height, simplex.cutoff, simplex.matrix);
+            let key = Key::of("simplex", &simplex, &[], viewport);
+            let mut field = cache.field(key, || {
+                let matrix = simplex.matrix.post_mul(&to_pixel);
+                get_noise(ctx, width, height, simplex.cutoff, matrix)
+            });
             inspector.write_field(&format!("simplex_{}", id), &mut field);
-            mapping.insert(id, field);
+            mapping.insert(
+                id,
+                Field {
+                    key,
+                    buffer: field,
+                    bounds: Bounding::unknown(),
+                    shape: None,
+                },
+            );
         }
         Command::Define(id, Value::BasicShape(shape)) => {
-            let mut field = exec_shape(ctx, inspector.duplicate(), shape, width, height, |id| {
-                mapping[&id].clone()
+            let inputs: Vec<_> = shape.fields().iter().map(|f| mapping[f].key).collect();
+            let key = Key::of("shape", &shape, &inputs, viewport);
+            let field_bounds = |id: &Id| mapping[id].bounds;
+            let bounds = shape_bounds(&shape, &field_bounds);
+            // Shapes that read parameters aren't simplified, since what can
+            // be pruned from them depends on the values.  They're compiled
+            // once and run with the values as the program's inputs.
+            if let Some(template) = run.shapes.get(&id) {
+                let (template, shape) = if is_identity {
+                    ((*template).clone(), shape)
+                } else {
+                    let to_pixel_float = Transform2D::row_major(
+                        to_pixel.m11.into(),
+                        to_pixel.m12.into(),
+                        to_pixel.m21.into(),
+                        to_pixel.m22.into(),
+                        to_pixel.m31.into(),
+                        to_pixel.m32.into(),
+                    );
+                    (
+                        Shape::Transform(Box::new((*template).clone()), to_pixel_float),
+                        Shape::Transform(Box::new(shape), to_pixel),
+                    )
+                };
+                let compiled_key = Key::of("compiled", &template, &inputs, viewport);
+                let program = cache
+                    .compiled(compiled_key, || {
+                        CompiledShape::new(&template, &|id: &Id| mapping[id].buffer.clone())
+                    })
+                    .bind(&run.values);
+                let mut field = cache.field(key, || {
+                    exec_compiled(ctx, inspector.duplicate(), program, width, height)
+                });
+                inspector.write_field(&format!("shape_{}", id), &mut field);
+                mapping.insert(
+                    id,
+                    Field {
+                        key,
+                        buffer: field,
+                        bounds,
+                        shape: Some(shape),
+                    },
+                );
+                return;
+            }
+            let simplified = match simplify(&shape, &field_bounds) {
+                Simplified::Shape(shape) if !is_identity => {
+                    Simplified::Shape(Shape::Transform(Box::new(shape), to_pixel))
+                }
+                simplified => simplified,
+            };
+            let mut field = cache.field(key, || match &simplified {
+                Simplified::Nothing => ctx.field_buffer_inf(width, height, 1),
+                Simplified::Everything => ctx.field_buffer_neg_inf(width, height, 1),
+                Simplified::Shape(shape) => exec_shape(
+                    ctx,
+                    inspector.duplicate(),
+                    shape.clone(),
+                    width,
+                    height,
+                    |id| mapping[id].buffer.clone(),
+                ),
             });
             inspector.write_field(&format!("shape_{}", id), &mut field);
-            mapping.insert(id, field);
+            let shape = match simplified {
+                Simplified::Shape(shape) => Some(shape),
+                _ => None,
+            };
+            mapping.insert(
+                id,
+                Field {
+                    key,
+                    buffer: field,
+                    bounds,
+                    shape,
+                },
+            );
         }
-        Command::Define(id, Value::Polygon(poly)) => {
-            let mut field = exec_poly(ctx, poly, width, height);
+        Command::Define(id, Value::Polygon(mut poly)) => {
+            let key = Key::of("poly", &poly, &[], viewport);
+            let bounds = polygon_bounds(&poly);
+            let mut field = cache.field(key, || {
+                poly.matrix = poly.matrix.post_mul(&to_pixel);
+                exec_poly(ctx, poly, width, height)
+            });
             inspector.write_field(&format!("poly_{}", id), &mut field);
-            mapping.insert(id, field);
+            mapping.insert(
+                id,
+                Field {
+                    key,
+                    buffer: field,
+                    bounds,
+                    shape: None,
+                },
+            );
         }
         Command::Freeze { target, id } => {
-            let field = {
-                let buffer = mapping.get_mut(&target).unwrap();
-                let mut field = exec_freeze(ctx, buffer);
-                inspector.write_field(&format!("freeze_{}", id), &mut field);
-                field
+            let (key, mut field, bounds) = {
+                let target = mapping.get_mut(&target).unwrap();
+                let bounds = target.bounds;
+                let key = Key::of("freeze", &(), &[target.key], viewport);
+                // There's no surface to measure the distance to in a field
+                // that is empty or full, so it's left as it is.
+                let field = cache.field(key, || {
+                    if bounds.is_empty() || bounds.is_full() {
+                        return target.buffer.clone();
+                    }
+                    let field = exec_freeze(ctx, &mut target.buffer);
+                    if viewport.resolution != 1.0 {
+                        scale_field(ctx, field, 1.0 / viewport.resolution)
+                    } else {
+                        field
+                    }
+                });
+                (key, field, bounds)
             };
-            mapping.insert(id, field);
+            inspector.write_field(&format!("freeze_{}", id), &mut field);
+            mapping.insert(
+                id,
+                Field {
+                    key,
+                    buffer: field,
+                    bounds,
+                    shape: None,
+                },
+            );
         }
         Command::Drag { target, id, dx, dy } => {
-            let field = {
-                let buffer = mapping.get_mut(&target).unwrap();
-                let mut field = exec_drag(ctx, buffer, dx, dy);
-                inspector.write_field(&format!("drag_{}", id), &mut field);
-                field
+            let (key, mut field, bounds) = {
+                let target = mapping.get_mut(&target).unwrap();
+                let key = Key::of("drag", &(dx, dy), &[target.key], viewport);
+                let field = cache.field(key, || {
+                    let (dx, dy) = (dx * viewport.resolution, dy * viewport.resolution);
+                    exec_drag(ctx, &mut target.buffer, dx, dy)
+                });
+                (key, field, target.bounds.dragged(dx, dy))
             };
-            mapping.insert(id, field);
+            inspector.write_field(&format!("drag_{}", id), &mut field);
+            mapping.insert(
+                id,
+                Field {
+                    key,
+                    buffer: field,
+                    bounds,
+                    shape: None,
+                },
+            );
         }
         Command::Concurrently(commands) | Command::Serially(commands) => {
             for (i, command) in commands.into_iter().enumerate() {
@@ -96,16 +367,160 @@ fn exec_inner(
                     mapping,
                     output,
                     inspector.specialize(&format!("instr_{}", i)),
-                    width,
-                    height,
+                    run,
+                    cache,
                 );
             }
         }
-        Command::Export(id) => {
-            let buffer = mapping.get_mut(&id).unwrap();
-            let lines = extract_lines(ctx, inspector, buffer);
-            output.insert(id, lines);
+        Command::Export(id) => exec_inner(
+            ctx,
+            Command::ExportWith(id, ExportOptions::default()),
+            mapping,
+            output,
+            inspector,
+            run,
+            cache,
+        ),
+        Command::ExportWith(id, options) => {
+            // The fields that the shape reads, in case it's refined against
+            // or sampled again.
+            let inputs: HashMap<Id, FieldBuffer> = match &mapping[&id].shape {
+                Some(shape) if options.refine || options.sample_centers => shape
+                    .fields()
+                    .into_iter()
+                    .map(|input| {
+                        let buffer = mapping[&input].buffer.clone();
+                        (input, buffer)
+                    })
+                    .collect(),
+                _ => HashMap::new(),
+            };
+            let field = mapping.get_mut(&id).unwrap();
+            let key = Key::of("export", &options, &[field.key], viewport);
+            let lines = cache.lines(key, || {
+                let arena = ::typed_arena::Arena::new();
+                let ast = match &field.shape {
+                    Some(shape) if options.refine => {
+                        Some(::compiler::compile(shape, &arena, &|id: &Id| {
+                            let mut buffer = inputs[id].clone();
+                            buffer.to_memory();
+                            buffer
+                        }))
+                    }
+                    _ => None,
+                };
+                let exact = ast.as_ref().map(ExactField::new);
+                // Each cell's middle is half a pixel right of and below the
+                // sample at its corner.
+                let mut centers = match &field.shape {
+                    Some(shape)
+                        if options.sample_centers && options.contouring == Contouring::Marching =>
+                    {
+                        Some(exec_shape(
+                            ctx,
+                            inspector.specialize("centers"),
+                            Shape::Transform(
+                                Box::new(shape.clone()),
+                                Matrix::create_translation(-0.5, -0.5),
+                            ),
+                            width,
+                            height,
+                            |id| inputs[id].clone(),
+                        ))
+                    }
+                    _ => None,
+                };
+                let mut lines = extract_lines(
+                    ctx,
+                    inspector.duplicate(),
+                    &mut field.buffer,
+                    &options,
+                    exact.as_ref(),
+                    centers.as_mut(),
+                );
+                if let Some(Infill::Concentric { spacing }) = options.infill {
+                    lines.extend(exec_concentric(
+                        ctx,
+                        inspector.specialize("concentric"),
+                        &field.buffer,
+                        spacing,
+                        viewport.resolution,
+                        &options,
+                    ));
+                }
+                let mut lines = if is_identity {
+                    lines
+                } else {
+                    transform_lines(lines, &viewport.pixel_to_world())
+                };
+                // Hatching is spaced in world units, so it's done after the
+                // lines are moved there.
+                let hatching = match options.infill {
+                    Some(Infill::Hatch { angle, spacing }) => hatch(&lines, angle, spacing),
+                    Some(Infill::CrossHatch { angle, spacing }) => {
+                        cross_hatch(&lines, angle, spacing)
+                    }
+                    _ => vec![],
+                };
+                lines.extend(hatching);
+                lines
+            });
+            output.push(Export { id, lines });
         }
+        // Every include was resolved by `exec_template` before the run
+        // started.
+        Command::Include { .. } => {}
+    }
+}
+
+fn scale_field(ctx: &OpenClContext, field: FieldBuffer, factor: f32) -> FieldBuffer {
+    let (width, height) = (field.width, field.height);
+    let operands = [
+        ::gpu_interp::Ast::Buffer(field),
+        ::gpu_interp::Ast::Constant(factor),
+    ];
+    let ast = ::gpu_interp::Ast::Mul(&operands);
+    ::gpu_interp::gpu::execute_with_program(
+        ::gpu_interp::gpu::compile(&ast),
+        width,
+        height,
+        1,
+        ::gpu_interp::gpu::Triad {
+            context: ctx.context().clone(),
+            queue: ctx.queue().clone(),
+        },
+        &ctx.interpreter_program(),
+    )
+}
+
+fn transform_lines(lines: Vec<PathSegment>, matrix: &Matrix) -> Vec<PathSegment> {
+    lines
+        .into_iter()
+        .map(|segment| {
+            let path = segment
+                .path
+                .iter()
+                .map(|p| matrix.transform_point(p))
+                .collect::<Vec<_>>();
+            let mut transformed = PathSegment::new(path);
+            transformed.closed = segment.closed;
+            transformed
+        })
+        .collect()
+}
+
+#[test]
+fn includes_are_resolved_before_anything_runs() {
+    let command = Command::Serially(vec![
+        Command::Include {
+            path: "no/such/program.shape".into(),
+            namespace: "missing".into(),
+        },
+        Command::Export("missing.0".into()),
+    ]);
+    match exec_fitted(command, Box::new(()), 1.0) {
+        Err(ParseError::Io(path, _)) => assert!(path.ends_with("no/such/program.shape")),
+        other => panic!("expected the include to be missing, got {:?}", other),
     }
 }
 
@@ -121,12 +536,26 @@ fn exec_program_single(provider: Provider) {
     }));
 
     let program = Command::Serially(vec![
-        Command::Define(0, Value::BasicShape(shape)),
-        Command::Export(0),
+        Command::Define(0.into(), Value::BasicShape(shape)),
+        Command::Export(0.into()),
     ]);
 
-    let out = exec(program, provider.duplicate(), 22, 22);
-    for (id, lines) in out {
+    let out = exec(program, provider.duplicate(), 22, 22).unwrap();
+    for Export { id, lines } in out {
+        let writer = provider.text_writer(format!("export_{}.lines.txt", id));
+        print_path_segments(writer, &lines);
+    }
+}
+
+#[expectation_test]
+fn exec_program_from_builder(provider: Provider) {
+    use debug::print_path_segments;
+    use extern_api::builder::{circle, compile};
+
+    let program = compile(&circle(11.0, 11.0, 10.0));
+
+    let out = exec(program, provider.duplicate(), 22, 22).unwrap();
+    for Export { id, lines } in out {
         let writer = provider.text_writer(format!("export_{}.lines.txt", id));
         print_path_segments(writer, &lines);
     }
@@ -157,21 +586,21 @@ fn exec_program_with_multiple(provider: Provider) {
     };
 
     let combiner = Shape::Intersection(vec![
-        Shape::Terminal(Terminal::Field(0)),
-        Shape::Terminal(Terminal::Field(1)),
+        Shape::Terminal(Terminal::Field(0.into())),
+        Shape::Terminal(Terminal::Field(1.into())),
     ]);
 
     let program = Command::Serially(vec![
-        Command::Define(0, Value::BasicShape(shape)),
-        Command::Define(1, Value::Polygon(polygon)),
-        Command::Define(2, Value::BasicShape(combiner)),
-        Command::Export(0),
-        Command::Export(1),
-        Command::Export(2),
+        Command::Define(0.into(), Value::BasicShape(shape)),
+        Command::Define(1.into(), Value::Polygon(polygon)),
+        Command::Define(2.into(), Value::BasicShape(combiner)),
+        Command::Export(0.into()),
+        Command::Export(1.into()),
+        Command::Export(2.into()),
     ]);
 
-    let out = exec(program, provider.duplicate(), 22, 22);
-    for (id, lines) in out {
+    let out = exec(program, provider.duplicate(), 22, 22).unwrap();
+    for Export { id, lines } in out {
         let writer = provider.text_writer(format!("export_{}.lines.txt", id));
         print_path_segments(writer, &lines);
     }
@@ -184,17 +613,17 @@ fn exec_program_single_noise(provider: Provider) {
 
     let program = Command::Serially(vec![
         Command::Simplex(
-            0,
+            0.into(),
             Simplex {
                 cutoff: 0.5,
                 matrix: Matrix::identity(),
             },
         ),
-        Command::Export(0),
+        Command::Export(0.into()),
     ]);
 
-    let out = exec(program, provider.duplicate(), 100, 100);
-    for (id, lines) in out {
+    let out = exec(program, provider.duplicate(), 100, 100).unwrap();
+    for Export { id, lines } in out {
         let writer = provider.text_writer(format!("export_{}.lines.txt", id));
         print_path_segments(writer, &lines);
     }
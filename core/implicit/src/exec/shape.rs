@@ -1,5 +1,6 @@
 use inspector::BoxedInspector;
 use extern_api::{Id, Shape};
+use gpu_interp::gpu::bytecode::CompilationResult;
 use opencl::{FieldBuffer, OpenClContext};
 
 #[cfg(test)]
@@ -15,15 +16,27 @@ pub fn exec_shape<F>(
     buffer_find: F,
 ) -> FieldBuffer
 where
-    F: Fn(Id) -> FieldBuffer,
+    F: Fn(&Id) -> FieldBuffer,
 {
     let arena = ::typed_arena::Arena::new();
     let output = ::compiler::compile(&shape, &arena, &buffer_find);
     inspector.write_ast("ast", &output);
 
     let compiled = ::gpu_interp::gpu::compile(&output);
+    exec_compiled(ctx, inspector, compiled, width, height)
+}
+
+/// Runs a shape that has already been compiled, like the ones that
+/// `CompiledShape::bind` produces.
+pub fn exec_compiled(
+    ctx: &OpenClContext,
+    inspector: BoxedInspector,
+    compiled: CompilationResult,
+    width: u32,
+    height: u32,
+) -> FieldBuffer {
     inspector.write_compiled("compiled", &compiled);
-    ::gpu_interp::gpu::execute(
+    ::gpu_interp::gpu::execute_with_program(
         compiled,
         width as u32,
         height as u32,
@@ -32,6 +45,7 @@ where
             context: ctx.context().clone(),
             queue: ctx.queue().clone(),
         },
+        &ctx.interpreter_program(),
     )
 }
 
@@ -47,8 +61,8 @@ fn run_shape_helper(
     use debug::*;
     use inspector::Inspector;
 
-    let mut buffer = exec_shape(ctx, provider.duplicate(), shape, width, height, |i| {
-        fields[i as usize].clone()
+    let mut buffer = exec_shape(ctx, provider.duplicate(), shape, width, height, |id| {
+        fields[id.as_str().parse::<usize>().unwrap()].clone()
     });
 
     let w_color = provider.png_writer("out.color.png");
@@ -180,7 +194,7 @@ fn exec_field(provider: Provider) {
 
     let circle_field = run_shape_helper(&ctx, circle, 22, 22, provider.subdir("inner"), &[]);
 
-    let shape = Shape::Terminal(Terminal::Field(0));
+    let shape = Shape::Terminal(Terminal::Field(0.into()));
 
     run_shape_helper(&ctx, shape, 22, 22, provider, &[circle_field]);
 }
@@ -205,8 +219,8 @@ fn exec_field_intersection(provider: Provider) {
     let circle_field_2 = run_shape_helper(&ctx, circle_2, 22, 22, provider.subdir("c2"), &[]);
 
     let shape = Shape::Intersection(vec![
-        Shape::Terminal(Terminal::Field(0)),
-        Shape::Terminal(Terminal::Field(1)),
+        Shape::Terminal(Terminal::Field(0.into())),
+        Shape::Terminal(Terminal::Field(1.into())),
     ]);
 
     run_shape_helper(
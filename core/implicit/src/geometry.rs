@@ -4,6 +4,7 @@ use line_stitch;
 
 pub type Point = euclid::Point2D<f32>;
 pub type Rect = euclid::Rect<f32>;
+pub type Vector = euclid::Vector2D<f32>;
 pub type PathSegment = line_stitch::PathSegment<euclid::UnknownUnit>;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -1,5 +1,6 @@
 // Local crates
 extern crate aabb_quadtree;
+extern crate buffer_dump;
 extern crate gpu_interp;
 extern crate line_stitch;
 extern crate vectorphile;
@@ -14,28 +15,37 @@ extern crate extern_api;
 // External Crates
 extern crate euclid;
 extern crate flame;
+extern crate fnv;
 extern crate image;
 extern crate itertools;
 extern crate lazy_static;
+extern crate notify;
 extern crate ocl;
+extern crate rmp_serde;
 extern crate serde;
+extern crate serde_json;
+extern crate snoot;
 extern crate typed_arena;
 extern crate vecmath;
 
+#[macro_use]
 extern crate serde_derive;
-#[cfg(test)]
-extern crate serde_json;
 
 extern crate num_traits;
 
 pub mod compiler;
+pub mod daemon;
 pub mod debug;
+pub mod dual_contour;
 pub mod geometry;
 pub mod inspector;
 pub mod lines;
 pub mod marching;
 pub mod opencl;
 pub mod polygon;
+pub mod program;
+pub mod refine;
 pub mod surface_net;
+pub mod watch;
 
 pub mod exec;
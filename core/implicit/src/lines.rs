@@ -1,34 +1,42 @@
+use extern_api::StitchOptions;
 use geometry::{PathSegment, Point};
 use inspector::*;
 use line_stitch::*;
 use std::cmp::Ordering;
 
-const EPSILON: f32 = 0.001;
-
-/// Performs the following line operations:
+/// Performs the following line operations, skipping any that `options`
+/// disables:
 /// 0. Sort incoming lines
 /// 1. Remove Zero Area Loops
 /// 2. Prune Disconnected Edges
 /// 3. Connect Obvious
 /// 4. Graph Stitch
-pub fn connect_lines<I>(lines: I, inspector: BoxedInspector) -> Vec<PathSegment>
+/// 5. Simplify (if `options.simplify` is set)
+pub fn connect_lines<I>(
+    lines: I,
+    options: &StitchOptions,
+    inspector: BoxedInspector,
+) -> Vec<PathSegment>
 where
     I: Into<Vec<(Point, Point)>>,
 {
     let _guard = ::flame::start_guard("connect_lines");
     let mut lines = lines.into();
+    let epsilon = options.epsilon;
 
     // 0: Sort incoming lines to reduce nondeterminism
-    ::flame::span_of("sort lines", || {
-        lines.sort_by(
-            |&(Point { x: a1, y: b1, .. }, Point { x: c1, y: d1, .. }),
-             &(Point { x: a2, y: b2, .. }, Point { x: c2, y: d2, .. })| {
-                (a1, b1, c1, d1)
-                    .partial_cmp(&(a2, b2, c2, d2))
-                    .unwrap_or(Ordering::Equal)
-            },
-        )
-    });
+    if options.sort {
+        ::flame::span_of("sort lines", || {
+            lines.sort_by(
+                |&(Point { x: a1, y: b1, .. }, Point { x: c1, y: d1, .. }),
+                 &(Point { x: a2, y: b2, .. }, Point { x: c2, y: d2, .. })| {
+                    (a1, b1, c1, d1)
+                        .partial_cmp(&(a2, b2, c2, d2))
+                        .unwrap_or(Ordering::Equal)
+                },
+            )
+        });
+    }
     inspector.write_lines("0-input", &lines);
     inspector.do_slow(&|| {
         let segments: Vec<PathSegment> = lines
@@ -39,36 +47,92 @@ where
     });
 
     // 1: Remove Zero Area Loops
-    let lines = ::flame::span_of("zero area loops", || remove_zero_area_loops(lines, EPSILON));
-    inspector.write_lines("1-zero_area_removed", &lines);
-    inspector.do_slow(&|| {
-        let segments: Vec<PathSegment> = lines
-            .iter()
-            .map(|line| PathSegment::new(vec![line.0, line.1]))
-            .collect();
-        inspector.write_segments("1-zero_area_removed", &segments);
-    });
+    if options.remove_zero_area_loops {
+        lines = ::flame::span_of("zero area loops", || remove_zero_area_loops(lines, epsilon));
+        inspector.write_lines("1-zero_area_removed", &lines);
+        inspector.do_slow(&|| {
+            let segments: Vec<PathSegment> = lines
+                .iter()
+                .map(|line| PathSegment::new(vec![line.0, line.1]))
+                .collect();
+            inspector.write_segments("1-zero_area_removed", &segments);
+        });
+    }
 
     // 2: Prune Disconected Edges
-    let dual_qt = ::flame::span_of("prune", || {
-        prune(lines.into_iter().map(|(p1, p2)| [p1, p2]), EPSILON, true)
-    });
-    inspector.do_slow(&|| {
-        let collected = dual_qt.slow_iter();
-        inspector.write_segments("2-pruned", &collected);
-    });
+    let pruned = if options.prune {
+        let dual_qt = ::flame::span_of("prune", || {
+            prune(
+                lines.drain(..).map(|(p1, p2)| [p1, p2]),
+                epsilon,
+                options.oriented,
+            )
+        });
+        inspector.do_slow(&|| {
+            let collected = dual_qt.slow_iter();
+            inspector.write_segments("2-pruned", &collected);
+        });
+        Some(dual_qt)
+    } else {
+        None
+    };
 
     // 3: Connect Obvious
-    // TODO: fix this hack with the first parameter to
-    // connect_obvious
-    let lines = ::flame::span_of("connect obvious", || {
-        connect_obvious_from_dual_qt(dual_qt, EPSILON, true)
-    });
-    inspector.write_segments("3-connected_obvious", &lines);
+    let lines = match (pruned, options.connect_obvious) {
+        (Some(dual_qt), true) => ::flame::span_of("connect obvious", || {
+            connect_obvious_from_dual_qt(dual_qt, epsilon, options.oriented)
+        }),
+        (Some(dual_qt), false) => dual_qt.into_iter().collect(),
+        (None, true) => ::flame::span_of("connect obvious", || {
+            connect_obvious(
+                lines.into_iter().map(|(p1, p2)| [p1, p2]),
+                epsilon,
+                options.oriented,
+            )
+        }),
+        (None, false) => lines
+            .into_iter()
+            .map(|(p1, p2)| PathSegment::new(vec![p1, p2]))
+            .collect(),
+    };
+    if options.connect_obvious {
+        inspector.write_segments("3-connected_obvious", &lines);
+    }
 
     // 4: Graph Stitch
-    let lines = ::flame::span_of("graph stitch", || graph_stitch(lines));
-    inspector.write_segments("4-stitched", &lines);
+    let lines = if options.graph_stitch {
+        let lines = ::flame::span_of("graph stitch", || graph_stitch(lines));
+        inspector.write_segments("4-stitched", &lines);
+        lines
+    } else {
+        lines
+    };
+
+    // 5: Simplify
+    simplify_paths(lines, options, inspector)
+}
 
-    return lines;
+/// Simplifies paths that have already been joined, if `options` asks for
+/// it.  This is the last stage of `connect_lines`, and runs on its own for
+/// paths that were joined some other way.
+pub fn simplify_paths(
+    paths: Vec<PathSegment>,
+    options: &StitchOptions,
+    inspector: BoxedInspector,
+) -> Vec<PathSegment> {
+    let options = match &options.simplify {
+        Some(options) => options,
+        None => return paths,
+    };
+    let simplification = Simplification {
+        method: match options.method {
+            ::extern_api::SimplifyMethod::DouglasPeucker => SimplifyMethod::DouglasPeucker,
+            ::extern_api::SimplifyMethod::VisvalingamWhyatt => SimplifyMethod::VisvalingamWhyatt,
+        },
+        tolerance: options.tolerance,
+        preserve_topology: options.preserve_topology,
+    };
+    let paths = ::flame::span_of("simplify", || simplify(paths, &simplification));
+    inspector.write_segments("5-simplified", &paths);
+    paths
 }
@@ -1,6 +1,13 @@
-use opencl::{FieldBuffer, LineBuffer, OpenClContext};
+use opencl::{FieldBuffer, IndexBuffer, LineBuffer, OpenClContext};
 
-const PROGRAM: &'static str = include_str!("shaders/marching.c");
+const PROGRAM: &'static str = concat!(
+    include_str!("shaders/saddle.c"),
+    include_str!("shaders/marching.c")
+);
+const EDGES_PROGRAM: &'static str = concat!(
+    include_str!("shaders/saddle.c"),
+    include_str!("shaders/marching_edges.c")
+);
 
 pub fn run_marching(input: &mut FieldBuffer, ctx: &OpenClContext) -> (LineBuffer, u32) {
     let _guard = ::flame::start_guard("opencl marching [run_marching]");
@@ -36,6 +43,66 @@ pub fn run_marching(input: &mut FieldBuffer, ctx: &OpenClContext) -> (LineBuffer
     (line_buffer, count)
 }
 
+/// Runs marching squares, naming the ends of each line by the edge of the grid
+/// that they lie on.  Returns the lines, as four ids for each sample (two
+/// lines of `from, to`, or -1 where there's no line), and the point where the
+/// surface crosses each edge, as two floats for every id.
+///
+/// Saddle cells, with two opposite corners inside, are joined through the
+/// middle if the field is inside there.  That's interpolated from the
+/// corners, unless `centers` samples the field in the middle of each cell:
+/// `centers[x, y]` at `(x + 0.5, y + 0.5)`.
+pub fn run_marching_edges(
+    input: &mut FieldBuffer,
+    centers: Option<&mut FieldBuffer>,
+    ctx: &OpenClContext,
+) -> (IndexBuffer, LineBuffer) {
+    let _guard = ::flame::start_guard("opencl marching [run_marching_edges]");
+
+    let (width, height) = (input.width as usize, input.height as usize);
+    let with_centers = centers.is_some();
+    let name = if with_centers {
+        "apply_with_centers"
+    } else {
+        "apply"
+    };
+    let mut kernel = ctx.compile(name, EDGES_PROGRAM, |register| {
+        register.buffer("buffer");
+        register.long("width");
+        register.long("height");
+        register.buffer("crossings");
+        register.buffer("lines");
+        if with_centers {
+            register.buffer("centers");
+        }
+    });
+
+    let crossings = ctx.line_buffer_uninit(width * height * 4);
+    let lines = ctx.index_buffer_uninit(width * height * 4);
+
+    kernel.set_default_global_work_size(::ocl::SpatialDims::Two(width, height));
+    kernel
+        .set_arg("buffer", input.to_opencl(ctx.queue()))
+        .unwrap();
+    kernel.set_arg("width", width as u64).unwrap();
+    kernel.set_arg("height", height as u64).unwrap();
+    kernel.set_arg("crossings", crossings.buffer()).unwrap();
+    kernel.set_arg("lines", lines.buffer()).unwrap();
+    if let Some(centers) = centers {
+        assert_eq!(centers.width, input.width);
+        assert_eq!(centers.height, input.height);
+        kernel
+            .set_arg("centers", centers.to_opencl(ctx.queue()))
+            .unwrap();
+    }
+
+    unsafe {
+        ::flame::span_of("opencl marching [execution]", || kernel.enq().unwrap());
+    }
+
+    (lines, crossings)
+}
+
 #[test]
 fn basic() {
     fn test_this(a: f32, b: f32, c: f32, d: f32, ctx: &OpenClContext) -> ((f32, f32), (f32, f32)) {
@@ -98,3 +165,84 @@ fn basic() {
         ((0.55, 1.0), (1.0, 0.4375)),
     );
 }
+
+#[test]
+fn edges_are_shared() {
+    let ctx = OpenClContext::default();
+    // Only the middle sample is inside, so the four cells around it each
+    // have one line, which meet at the four edges that touch it.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    let mut buf = ctx.field_buffer(3, 3, 1, Some(&[
+        1.0, 1.0, 1.0,
+        1.0, -1.0, 1.0,
+        1.0, 1.0, 1.0,
+    ]));
+    let (lines, crossings) = run_marching_edges(&mut buf, None, &ctx);
+    let lines = lines.values(None);
+    let crossings = crossings.values(None);
+
+    let mut ends: Vec<_> = lines
+        .chunks(2)
+        .filter(|line| line[0] >= 0)
+        .map(|line| (line[0], line[1]))
+        .collect();
+    ends.sort();
+    // The edges right of (0, 1), below (1, 0), right of (1, 1), and below
+    // (1, 1), in a loop.
+    assert_eq!(ends, vec![(3, 8), (6, 3), (8, 9), (9, 6)]);
+
+    let at = |edge: usize| (crossings[edge * 2], crossings[edge * 2 + 1]);
+    assert_eq!(at(6), (0.5, 1.0));
+    assert_eq!(at(3), (1.0, 0.5));
+    assert_eq!(at(8), (1.5, 1.0));
+    assert_eq!(at(9), (1.0, 1.5));
+    assert!(at(0).0.is_nan());
+}
+
+#[test]
+fn saddles_follow_the_middle() {
+    fn lines(a: f32, b: f32, c: f32, d: f32, ctx: &OpenClContext) -> Vec<f32> {
+        let mut buf = ctx.field_buffer(2, 2, 1, Some(&[a, b, d, c]));
+        let (lines, count) = run_marching(&mut buf, &ctx);
+        assert_eq!(count, 2);
+        lines.values(None)[..8]
+            .iter()
+            .map(|v| (v * 1000.0).round() / 1000.0)
+            .collect()
+    }
+
+    let ctx = OpenClContext::default();
+    // The middle is inside, so the corners at a and c are joined, and the
+    // lines cut off b and d.
+    assert_eq!(
+        lines(-1.0, 0.5, -1.0, 0.5, &ctx),
+        vec![0.667, 0.0, 1.0, 0.333, 0.333, 1.0, 0.0, 0.667]
+    );
+    // The middle is outside, so the lines cut off a and c.
+    assert_eq!(
+        lines(-0.5, 1.0, -0.5, 1.0, &ctx),
+        vec![0.667, 1.0, 1.0, 0.667, 0.333, 0.0, 0.0, 0.333]
+    );
+}
+
+#[test]
+fn edge_saddles_follow_the_middle() {
+    fn ends(centers: Option<f32>, ctx: &OpenClContext) -> Vec<(i64, i64)> {
+        // b and d are inside, and the middle interpolates to -0.25.
+        let mut buf = ctx.field_buffer(2, 2, 1, Some(&[0.5, -1.0, -1.0, 0.5]));
+        let mut centers = centers.map(|c| ctx.field_buffer(2, 2, 1, Some(&[c, 0.0, 0.0, 0.0])));
+        let (lines, _) = run_marching_edges(&mut buf, centers.as_mut(), &ctx);
+        let lines = lines.values(None);
+        lines
+            .chunks(2)
+            .filter(|line| line[0] >= 0)
+            .map(|line| (line[0], line[1]))
+            .collect()
+    }
+
+    let ctx = OpenClContext::default();
+    // Edges 0, 3, 4, and 1 are north, east, south, and west of the cell.
+    assert_eq!(ends(None, &ctx), vec![(1, 0), (3, 4)]);
+    assert_eq!(ends(Some(-0.25), &ctx), vec![(1, 0), (3, 4)]);
+    assert_eq!(ends(Some(0.25), &ctx), vec![(3, 0), (1, 4)]);
+}
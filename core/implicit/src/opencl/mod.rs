@@ -42,6 +42,12 @@ pub fn all_devices() -> Vec<(Platform, Device)> {
     out
 }
 
+pub fn device_name(device: &Device) -> String {
+    device
+        .name()
+        .unwrap_or_else(|_| "<unknown device>".to_string())
+}
+
 pub struct Register<'a, 'b: 'a> {
     b: &'a mut KernelBuilder<'b>,
 }
@@ -105,21 +111,28 @@ impl OpenClContext {
     {
         let _guard = ::flame::start_guard("OpenClContext::compile");
         let name = name.into();
-        let source = source.into();
-
-        {
-            let program_cache = self.program_cache.lock().unwrap();
-            if let Some(&(_, ref p)) = program_cache
-                .iter()
-                .filter(|&&(ref s, _)| s == &source)
-                .next()
-            {
-                let _guard = ::flame::start_guard("Kernel::new");
-                let mut builder = KernelBuilder::new();
-                builder.queue(self.queue.clone()).name(name).program(p);
-                f(&mut Register { b: &mut builder });
-                return builder.build_unfinished().unwrap();
-            }
+        let program = self.program(source.into());
+
+        let _guard = ::flame::start_guard("Kernel::new");
+        let mut builder = KernelBuilder::new();
+        builder
+            .queue(self.queue.clone())
+            .name(name)
+            .program(&program);
+        f(&mut Register { b: &mut builder });
+        builder.build_unfinished().unwrap()
+    }
+
+    /// The bytecode interpreter that shapes are run with.  Like the other
+    /// programs, it is only built once per context.
+    pub fn interpreter_program(&self) -> Program {
+        self.program(::gpu_interp::gpu::INTERPRETER_SOURCE.to_string())
+    }
+
+    fn program(&self, source: String) -> Program {
+        let mut program_cache = self.program_cache.lock().unwrap();
+        if let Some(&(_, ref p)) = program_cache.iter().find(|&&(ref s, _)| s == &source) {
+            return p.clone();
         }
 
         let program = Program::builder()
@@ -127,22 +140,8 @@ impl OpenClContext {
             .devices(self.device)
             .build(&self.context)
             .unwrap();
-
-        {
-            let mut program_cache = self.program_cache.lock().unwrap();
-            program_cache.push((source, program.clone()));
-        }
-
-        {
-            let _guard = ::flame::start_guard("Kernel::new");
-            let mut builder = KernelBuilder::new();
-            builder
-                .queue(self.queue.clone())
-                .name(name)
-                .program(&program);
-            f(&mut Register { b: &mut builder });
-            return builder.build_unfinished().unwrap();
-        }
+        program_cache.push((source, program.clone()));
+        program
     }
 
     pub fn field_buffer(
@@ -0,0 +1,397 @@
+use extern_api::params::{Float, ParamError, Params, Template};
+use extern_api::sexp::{self, Sexp};
+#[cfg(test)]
+use extern_api::{Circle, Shape, Terminal, Value};
+use extern_api::{Command, Id, Program, Scalar, Viewport};
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use snoot::Sexpr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The encodings that programs can be read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The `ppx_sexp_conv` layout written by the OCaml frontend: a
+    /// `(Command, (width, height))` option, which may have the defaults of
+    /// its parameters after the size.
+    Sexp,
+    /// A `Template` object, which is a `Program` that may have `params`.
+    Json,
+    /// A `Template`, encoded with `rmp-serde`.
+    MessagePack,
+}
+
+// The layout that the OCaml frontend writes programs in, wrapped in an
+// option: a command, the size of the canvas, and the defaults of any
+// parameters that the command reads.  The frontend doesn't write any, so
+// they're optional, which a derived impl wouldn't allow.
+struct SexpTemplate(Command<Float>, (f32, f32), Params);
+
+impl<'de> Deserialize<'de> for SexpTemplate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SexpTemplate, D::Error> {
+        deserializer.deserialize_tuple(3, SexpTemplateVisitor)
+    }
+}
+
+struct SexpTemplateVisitor;
+
+impl<'de> Visitor<'de> for SexpTemplateVisitor {
+    type Value = SexpTemplate;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a command and a size, then the defaults of its parameters")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SexpTemplate, A::Error> {
+        let command = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let size = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let defaults = seq.next_element()?.unwrap_or_default();
+        Ok(SexpTemplate(command, size, defaults))
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Sexp(String),
+    Json(::serde_json::Error),
+    MessagePack(::rmp_serde::decode::Error),
+    Params(ParamError),
+    /// An included program couldn't be read.
+    Io(PathBuf, io::Error),
+    /// An included program was invalid.
+    Include(PathBuf, Box<ParseError>),
+    /// A program includes itself, directly or not.
+    Cycle(PathBuf),
+    /// An id was defined more than once, possibly by different programs.
+    DuplicateId(Id),
+    /// An id was read before anything defined it.
+    UndefinedId(Id),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Sexp(e) => write!(f, "invalid s-expression program: {}", e),
+            ParseError::Json(e) => write!(f, "invalid json program: {}", e),
+            ParseError::MessagePack(e) => write!(f, "invalid messagepack program: {}", e),
+            ParseError::Params(e) => write!(f, "{}", e),
+            ParseError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+            ParseError::Include(path, e) => write!(f, "in {}: {}", path.display(), e),
+            ParseError::Cycle(path) => write!(f, "{} includes itself", path.display()),
+            ParseError::DuplicateId(id) => write!(f, "{} is defined more than once", id),
+            ParseError::UndefinedId(id) => write!(f, "{} is used before it's defined", id),
+        }
+    }
+}
+
+impl ::std::error::Error for ParseError {}
+
+impl Format {
+    /// Guesses the format from the first non-whitespace byte.  S-expressions
+    /// start with `(` and json with `{`; anything else is assumed to be
+    /// MessagePack.
+    pub fn detect(bytes: &[u8]) -> Format {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'(') => Format::Sexp,
+            Some(b'{') => Format::Json,
+            _ => Format::MessagePack,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "sexp" => Ok(Format::Sexp),
+            "json" => Ok(Format::Json),
+            "msgpack" | "messagepack" => Ok(Format::MessagePack),
+            other => Err(format!(
+                "unknown format {:?}, expected one of sexp, json, or msgpack",
+                other
+            )),
+        }
+    }
+}
+
+/// Reads a program, detecting its format if `format` is `None`.  Any
+/// parameters that it takes are given their default values.
+pub fn parse(bytes: &[u8], format: Option<Format>) -> Result<Program, ParseError> {
+    parse_template(bytes, format)?
+        .resolve(&Params::new())
+        .map_err(ParseError::Params)
+}
+
+/// Reads a program that may take parameters.
+pub fn parse_template(bytes: &[u8], format: Option<Format>) -> Result<Template, ParseError> {
+    match format.unwrap_or_else(|| Format::detect(bytes)) {
+        Format::Sexp => parse_sexp(bytes),
+        Format::Json => ::serde_json::from_slice(bytes).map_err(ParseError::Json),
+        Format::MessagePack => ::rmp_serde::from_slice(bytes).map_err(ParseError::MessagePack),
+    }
+}
+
+/// Replaces every `Include` in `command` with the commands of the program
+/// that it names, reading paths relative to `dir`.  Included programs take
+/// their default parameters, and their viewports are ignored.  Fails if any
+/// id ends up defined more than once, or used before it's defined.
+pub fn resolve_includes<S: Scalar>(command: &mut Command<S>, dir: &Path) -> Result<(), ParseError> {
+    include_all(command, dir, &mut vec![])?;
+    if let Some(id) = command.duplicate_id() {
+        return Err(ParseError::DuplicateId(id));
+    }
+    match command.undefined_id() {
+        Some(id) => Err(ParseError::UndefinedId(id)),
+        None => Ok(()),
+    }
+}
+
+// `stack` holds the canonical paths of the programs that are being included,
+// innermost last.
+fn include_all<S: Scalar>(
+    command: &mut Command<S>,
+    dir: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<(), ParseError> {
+    let included = match command {
+        Command::Concurrently(commands) | Command::Serially(commands) => {
+            for command in commands {
+                include_all(command, dir, stack)?;
+            }
+            return Ok(());
+        }
+        Command::Include { path, namespace } => {
+            let path = dir.join(path);
+            let canonical = path
+                .canonicalize()
+                .map_err(|e| ParseError::Io(path.clone(), e))?;
+            if stack.contains(&canonical) {
+                return Err(ParseError::Cycle(path));
+            }
+            let bytes = fs::read(&path).map_err(|e| ParseError::Io(path.clone(), e))?;
+
+            stack.push(canonical);
+            let included = parse(&bytes, None).and_then(|mut program| {
+                let dir = path.parent().unwrap_or(dir);
+                include_all(&mut program.command, dir, stack).map(|_| program.command)
+            });
+            stack.pop();
+            let included: Result<_, ::std::convert::Infallible> = included
+                .map_err(|e| ParseError::Include(path, Box::new(e)))?
+                .scoped(namespace)
+                .try_map(&mut |v| Ok(S::from(v)));
+            match included {
+                Ok(included) => included,
+                Err(never) => match never {},
+            }
+        }
+        _ => return Ok(()),
+    };
+    *command = included;
+    Ok(())
+}
+
+fn parse_sexp(bytes: &[u8]) -> Result<Template, ParseError> {
+    let root = read_sexp(bytes)?;
+    match sexp::from_sexp::<Option<SexpTemplate>>(&root) {
+        Ok(Some(SexpTemplate(command, (w, h), defaults))) => Ok(Template {
+            command,
+            viewport: Viewport::from_dimensions(w, h),
+            defaults,
+        }),
+        Ok(None) => Err(ParseError::Sexp("the program is empty".into())),
+        Err(e) => Err(ParseError::Sexp(e.to_string())),
+    }
+}
+
+/// Reads the one s-expression in `bytes`, for `extern_api::sexp::from_sexp`
+/// to read values out of.
+pub fn read_sexp(bytes: &[u8]) -> Result<Sexp, ParseError> {
+    let text = ::std::str::from_utf8(bytes).map_err(|e| ParseError::Sexp(e.to_string()))?;
+    let sexprs = ::snoot::simple_parse(text, &[], Some("<input>"));
+    if !sexprs.diagnostics.is_empty() {
+        return Err(ParseError::Sexp(sexprs.diagnostics.to_string()));
+    }
+    if sexprs.roots.len() != 1 {
+        return Err(ParseError::Sexp(format!(
+            "expected one program, found {}",
+            sexprs.roots.len()
+        )));
+    }
+
+    Ok(from_snoot(&sexprs.roots[0]))
+}
+
+fn from_snoot(sexpr: &Sexpr) -> Sexp {
+    match sexpr {
+        Sexpr::List { children, .. } => Sexp::List(children.iter().map(from_snoot).collect()),
+        _ => Sexp::Atom(sexpr.text().to_string()),
+    }
+}
+
+#[test]
+fn detects_formats() {
+    assert_eq!(Format::detect(b"  (((Export 0)) (1 1))"), Format::Sexp);
+    assert_eq!(Format::detect(b"\n{\"command\": {}}"), Format::Json);
+    assert_eq!(Format::detect(&[0x82, 0xa7]), Format::MessagePack);
+}
+
+#[test]
+fn parses_json() {
+    let json = r#"{
+        "command": {"Serially": [{"Export": 0}]},
+        "viewport": {"bbox": {"x": 0, "y": 0, "w": 20, "h": 10}, "resolution": 2}
+    }"#;
+    let program = parse(json.as_bytes(), None).unwrap();
+    assert_eq!(
+        program.command,
+        Command::Serially(vec![Command::Export(0.into())])
+    );
+    assert_eq!(program.viewport.dimensions(), (40, 20));
+}
+
+#[test]
+fn parses_messagepack() {
+    let program = Program {
+        command: Command::Export(3.into()),
+        viewport: Viewport::from_dimensions(5.0, 5.0),
+    };
+    let bytes = ::rmp_serde::to_vec(&program).unwrap();
+    assert_eq!(parse(&bytes, Some(Format::MessagePack)).unwrap(), program);
+}
+
+#[test]
+fn parses_parameters_in_every_format() {
+    let sexp = b"(((Define 0 (BasicShape (Terminal (Circle ((x 1) (y 1) (r (Param r))))))) \
+                  (4 4) ((r 2))))";
+    let template = parse_template(sexp, None).unwrap();
+    assert_eq!(template.defaults["r"], 2.0);
+
+    let bytes = ::rmp_serde::to_vec(&template).unwrap();
+    assert_eq!(parse_template(&bytes, None).unwrap(), template);
+    let json = ::serde_json::to_vec(&template).unwrap();
+    assert_eq!(parse_template(&json, None).unwrap(), template);
+
+    let params = vec![("r".to_string(), 3.0)].into_iter().collect();
+    let circle = Terminal::Circle(Circle {
+        x: 1.0,
+        y: 1.0,
+        r: 3.0,
+    });
+    assert_eq!(
+        template.resolve(&params).unwrap().command,
+        Command::Define(0.into(), Value::BasicShape(Shape::Terminal(circle)))
+    );
+}
+
+#[test]
+fn parses_programs_without_parameters_from_sexp() {
+    let program = parse(b"(((Export body) (3 4)))", None).unwrap();
+    assert_eq!(program.command, Command::Export("body".into()));
+    assert_eq!(program.viewport.dimensions(), (3, 4));
+}
+
+#[test]
+fn rejects_malformed_programs() {
+    match parse(b"((Export 0) (1 1)", Some(Format::Sexp)) {
+        Err(ParseError::Sexp(_)) => {}
+        other => panic!("expected an s-expression error, got {:?}", other),
+    }
+
+    let json = r#"{
+        "command": {"Serially": [{"Export": 0}]},
+        "viewport": {"bbox": {"x": 0, "y": 0, "w": 20, "h": 10}, "resolution": 2}
+    }"#;
+    let mut program = parse(json.as_bytes(), None).unwrap();
+    match resolve_includes(&mut program.command, Path::new(".")) {
+        Err(ParseError::UndefinedId(id)) => assert_eq!(id, Id::from(0)),
+        other => panic!("expected an undefined id, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+fn write_program(dir: &Path, name: &str, command: &str) {
+    let program = format!(
+        r#"{{"command": {}, "viewport": {{"bbox": {{"x": 0, "y": 0, "w": 10, "h": 10}}, "resolution": 1}}}}"#,
+        command
+    );
+    fs::write(dir.join(name), program).unwrap();
+}
+
+#[test]
+fn includes_programs_under_a_namespace() {
+    let dir = ::std::env::temp_dir().join(format!("includes_{}", ::std::process::id()));
+    fs::create_dir_all(dir.join("parts")).unwrap();
+    write_program(
+        &dir.join("parts"),
+        "gear.shape",
+        r#"{"Serially": [
+            {"Include": {"path": "hole.shape", "namespace": "hole"}},
+            {"Define": ["body", {"BasicShape": {"Terminal": {"Field": "hole.0"}}}]}
+        ]}"#,
+    );
+    write_program(
+        &dir.join("parts"),
+        "hole.shape",
+        r#"{"Define": [0, {"BasicShape": {"Terminal": {"Circle": {"x": 0, "y": 0, "r": 1}}}}]}"#,
+    );
+    write_program(
+        &dir,
+        "main.shape",
+        r#"{"Serially": [
+            {"Include": {"path": "parts/gear.shape", "namespace": "left"}},
+            {"Include": {"path": "parts/gear.shape", "namespace": "right"}},
+            {"Export": "left.body"}
+        ]}"#,
+    );
+
+    let mut program = parse(&fs::read(dir.join("main.shape")).unwrap(), None).unwrap();
+    resolve_includes(&mut program.command, &dir).unwrap();
+    let ids: Vec<_> = program
+        .command
+        .defined_ids()
+        .iter()
+        .map(|id| id.to_string())
+        .collect();
+    assert_eq!(
+        ids,
+        vec!["left.hole.0", "left.body", "right.hole.0", "right.body"]
+    );
+
+    write_program(
+        &dir,
+        "twice.shape",
+        r#"{"Serially": [
+            {"Include": {"path": "parts/hole.shape", "namespace": "a"}},
+            {"Include": {"path": "parts/hole.shape", "namespace": "a"}}
+        ]}"#,
+    );
+    let mut program = parse(&fs::read(dir.join("twice.shape")).unwrap(), None).unwrap();
+    match resolve_includes(&mut program.command, &dir) {
+        Err(ParseError::DuplicateId(id)) => assert_eq!(id, Id::from("a.0")),
+        other => panic!("expected a duplicate id, got {:?}", other),
+    }
+
+    write_program(
+        &dir,
+        "loop.shape",
+        r#"{"Include": {"path": "loop.shape", "namespace": "again"}}"#,
+    );
+    let mut program = parse(&fs::read(dir.join("loop.shape")).unwrap(), None).unwrap();
+    match resolve_includes(&mut program.command, &dir) {
+        Err(ParseError::Include(_, e)) => match *e {
+            ParseError::Cycle(_) => {}
+            other => panic!("expected a cycle, got {:?}", other),
+        },
+        other => panic!("expected a cycle, got {:?}", other),
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,136 @@
+use dual_contour::Surface;
+use euclid::vec2;
+use geometry::{Point, Vector};
+use gpu_interp::ast_walk::interpret;
+use gpu_interp::Ast;
+
+/// Halving the interval this many times puts a crossing within a millionth
+/// of a pixel of the surface, which is past what an `f32` can tell apart
+/// anywhere but near the origin.
+const BISECTIONS: usize = 20;
+
+/// How many Newton steps a point takes towards the surface, and how far (in
+/// pixels) it's allowed to go before it's left where it was.
+const NEWTON_STEPS: usize = 4;
+const NEWTON_REACH: f32 = 0.5;
+
+/// How far apart (in pixels) the samples of the central differences for the
+/// gradient are.
+const GRADIENT_STEP: f32 = 0.01;
+
+/// A shape, evaluated exactly wherever it's asked about rather than only at
+/// pixels.  Fields that the shape reads are still sampled, and interpolated
+/// between their pixels.
+pub struct ExactField<'a> {
+    ast: &'a Ast<'a>,
+}
+
+impl<'a> ExactField<'a> {
+    /// The buffers in `ast` need to have been read into memory.
+    pub fn new(ast: &'a Ast<'a>) -> ExactField<'a> {
+        ExactField { ast }
+    }
+
+    pub fn value(&self, p: Point) -> f32 {
+        interpret(self.ast, p.x, p.y, 0.0)
+    }
+
+    /// Bisects the segment from `a` to `b` for where the field crosses zero,
+    /// starting from `estimate`.  The estimate is kept if the exact field
+    /// doesn't change sides between `a` and `b`, which happens where the
+    /// samples and the shape disagree about a feature smaller than a pixel.
+    pub fn refine(&self, a: Point, b: Point, estimate: Point) -> Point {
+        let (inside_a, inside_b) = (self.value(a) <= 0.0, self.value(b) <= 0.0);
+        if inside_a == inside_b {
+            return estimate;
+        }
+
+        let (mut outer, mut inner) = if inside_a { (b, a) } else { (a, b) };
+        let mut middle = estimate;
+        for _ in 0..BISECTIONS {
+            let value = self.value(middle);
+            if value.is_nan() {
+                return estimate;
+            }
+            if value <= 0.0 {
+                inner = middle;
+            } else {
+                outer = middle;
+            }
+            middle = outer.lerp(inner, 0.5);
+        }
+        middle
+    }
+}
+
+impl<'a> Surface for ExactField<'a> {
+    fn gradient(&self, p: Point) -> Option<Vector> {
+        let dx = vec2(GRADIENT_STEP, 0.0);
+        let dy = vec2(0.0, GRADIENT_STEP);
+        let gradient = vec2(
+            self.value(p + dx) - self.value(p - dx),
+            self.value(p + dy) - self.value(p - dy),
+        ) / (2.0 * GRADIENT_STEP);
+        if gradient.x.is_finite() && gradient.y.is_finite() {
+            Some(gradient)
+        } else {
+            None
+        }
+    }
+
+    fn crossing(&self, a: Point, b: Point, estimate: Point) -> Point {
+        self.refine(a, b, estimate)
+    }
+
+    /// Steps along the gradient to where the field is zero.  Near a corner
+    /// the gradient points at neither of its sides, so points that would go
+    /// far (or nowhere sensible) are kept as they are.
+    fn snap(&self, p: Point) -> Point {
+        let mut snapped = p;
+        for _ in 0..NEWTON_STEPS {
+            let gradient = match self.gradient(snapped) {
+                Some(g) if g.square_length() > 0.0 => g,
+                _ => return p,
+            };
+            snapped -= gradient * (self.value(snapped) / gradient.square_length());
+        }
+        if (snapped - p).length() <= NEWTON_REACH && self.value(snapped).is_finite() {
+            snapped
+        } else {
+            p
+        }
+    }
+}
+
+#[test]
+fn crossings_land_on_the_circle() {
+    use euclid::point2;
+    use extern_api::{Circle, Shape, Terminal};
+
+    let arena = ::typed_arena::Arena::new();
+    let shape = Shape::Terminal(Terminal::Circle(Circle {
+        x: 0.0,
+        y: 0.0,
+        r: 2.5,
+    }));
+    let ast = ::compiler::compile(&shape, &arena, &|_| unimplemented!());
+    let exact = ExactField::new(&ast);
+
+    // Interpolating the samples at x = 2 and x = 3 puts the crossing at
+    // 2.5 along the axis, but at y = 1 the circle crosses at sqrt(5.25).
+    let p = exact.refine(point2(2.0, 1.0), point2(3.0, 1.0), point2(2.2, 1.0));
+    assert!((p.x - 5.25f32.sqrt()).abs() < 1e-5, "{:?}", p);
+    assert_eq!(p.y, 1.0);
+
+    // Both ends outside, so there's nothing to find.
+    let p = exact.refine(point2(3.0, 0.0), point2(4.0, 0.0), point2(3.5, 0.0));
+    assert_eq!(p, point2(3.5, 0.0));
+
+    let normal = exact.gradient(point2(0.0, 2.5)).unwrap();
+    assert!((normal - vec2(0.0, 1.0)).length() < 1e-3, "{:?}", normal);
+
+    // A point on the chord between two crossings is pushed out onto the arc.
+    let p = exact.snap(point2(1.7, 1.7));
+    assert!(exact.value(p).abs() < 1e-5, "{:?}", p);
+    assert!((p.x - p.y).abs() < 1e-5);
+}
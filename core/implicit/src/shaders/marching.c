@@ -51,6 +51,7 @@ static void write_line(float2 o1, float2 o2, __global float *out, size_t out_pos
 
 static void march(
     float sra, float srb, float src, float srd,
+    float center,
     float2 p,
     float dist,
     __global float *out,
@@ -115,12 +116,22 @@ static void march(
     // 01
     // 10
     case 5:
+        if (center <= 0.0f)
+        {
+            // The insides meet in the middle, so cut off the outside
+            // corners instead, as in cases 7 and 13.
+            o1 = w(dist, p, lerp(sra, srd, dist));
+            o2 = n(dist, p, lerp(sra, srb, dist));
+
+            o3 = e(dist, p, lerp(srb, src, dist));
+            o4 = s(dist, p, lerp(srd, src, dist));
+            break;
+        }
         o2 = n(dist, p, lerp(sra, srb, dist));
         o1 = e(dist, p, lerp(srb, src, dist));
 
         o3 = w(dist, p, lerp(sra, srd, dist));
         o4 = s(dist, p, -lerp(src, srd, dist));
-        // WEW LADS
         break;
 
     // 0110
@@ -159,12 +170,21 @@ static void march(
     // 10
     // 01
     case 10:
+        if (center <= 0.0f)
+        {
+            // As in cases 11 and 14.
+            o1 = n(dist, p, lerp(sra, srb, dist));
+            o2 = e(dist, p, -lerp(src, srb, dist));
+
+            o3 = s(dist, p, lerp(srd, src, dist));
+            o4 = w(dist, p, lerp(sra, srd, dist));
+            break;
+        }
         o1 = s(dist, p, lerp(srd, src, dist));
         o2 = e(dist, p, -lerp(src, srb, dist));
 
         o4 = w(dist, p, lerp(sra, srd, dist));
         o3 = n(dist, p, lerp(sra, srb, dist));
-        // PUNT
         break;
 
     // 1011
@@ -251,5 +271,6 @@ __kernel void apply(
     float srd = buffer[d];
 
     float2 p = (float2)(x + 0.5f, y + 0.5f);
-    march(sra, srb, src, srd, p, 1.0f, out, atomic);
+    float center = bilinear_center(sra, srb, src, srd);
+    march(sra, srb, src, srd, center, p, 1.0f, out, atomic);
 }
@@ -0,0 +1,166 @@
+// Marching squares that names the ends of each line by the edge of the grid
+// that they lie on, instead of writing out their coordinates.
+//
+// Every sample owns two edges: the one to its right (id `2 * pos`) and the
+// one below it (id `2 * pos + 1`).  The point where the surface crosses an
+// edge is computed once, by the sample that owns it, and every cell that
+// touches the edge refers to it by id, so lines that meet share an id
+// exactly.
+
+#define NORTH 0
+#define EAST 1
+#define SOUTH 2
+#define WEST 3
+
+// The lines through a cell for each case, as (from, to) pairs of its edges,
+// oriented the same way as in marching.c.  -1 where there's no line.
+__constant int LINES[16][4] = {
+    {-1, -1, -1, -1},           // 0000
+    {WEST, SOUTH, -1, -1},      // 0001
+    {SOUTH, EAST, -1, -1},      // 0010
+    {WEST, EAST, -1, -1},       // 0011
+    {EAST, NORTH, -1, -1},      // 0100
+    {EAST, NORTH, WEST, SOUTH}, // 0101
+    {SOUTH, NORTH, -1, -1},     // 0110
+    {WEST, NORTH, -1, -1},      // 0111
+    {NORTH, WEST, -1, -1},      // 1000
+    {NORTH, SOUTH, -1, -1},     // 1001
+    {SOUTH, EAST, NORTH, WEST}, // 1010
+    {NORTH, EAST, -1, -1},      // 1011
+    {EAST, WEST, -1, -1},       // 1100
+    {EAST, SOUTH, -1, -1},      // 1101
+    {SOUTH, WEST, -1, -1},      // 1110
+    {-1, -1, -1, -1},           // 1111
+};
+
+// The lines through the saddle cases, 0101 and 1010, when the middle of the
+// cell is inside, so that the two inside corners are joined.
+__constant int JOINED_LINES[2][4] = {
+    {WEST, NORTH, EAST, SOUTH}, // 0101
+    {NORTH, EAST, SOUTH, WEST}, // 1010
+};
+
+// How far along the edge from `fa` to `fb` the field reaches zero.
+static float crossing(float fa, float fb)
+{
+    if (isinf(fa) && isinf(fb))
+    {
+        return 0.5f;
+    }
+    if (isinf(fa))
+    {
+        return 1.0f;
+    }
+    if (isinf(fb))
+    {
+        return 0.0f;
+    }
+    return clamp(fa / (fa - fb), 0.0f, 1.0f);
+}
+
+static void write_crossing(
+    float fa, float fb,
+    float2 a, float2 b,
+    __global float *crossings,
+    size_t edge)
+{
+    float2 p = (float2)(NAN, NAN);
+    if ((fa <= 0.0f) != (fb <= 0.0f))
+    {
+        p = mix(a, b, crossing(fa, fb));
+    }
+    crossings[edge * 2 + 0] = p.x;
+    crossings[edge * 2 + 1] = p.y;
+}
+
+static void march(
+    __global float *buffer,
+    ulong width, ulong height,
+    __global float *crossings,
+    __global long *lines,
+    __global float *centers)
+{
+    size_t x = get_global_id(0);
+    size_t y = get_global_id(1);
+
+    size_t pos = x + y * width;
+    float2 here = (float2)(x, y);
+    float sra = buffer[pos];
+
+    write_crossing(
+        sra, x == width - 1 ? sra : buffer[pos + 1],
+        here, here + (float2)(1.0f, 0.0f),
+        crossings, pos * 2);
+    write_crossing(
+        sra, y == height - 1 ? sra : buffer[pos + width],
+        here, here + (float2)(0.0f, 1.0f),
+        crossings, pos * 2 + 1);
+
+    for (size_t i = 0; i < 4; i++)
+    {
+        lines[pos * 4 + i] = -1;
+    }
+    if (x == width - 1 || y == height - 1)
+    {
+        return;
+    }
+
+    float srb = buffer[pos + 1];
+    float src = buffer[pos + 1 + width];
+    float srd = buffer[pos + width];
+
+    size_t a_on = sra <= 0.0f;
+    size_t b_on = srb <= 0.0f;
+    size_t c_on = src <= 0.0f;
+    size_t d_on = srd <= 0.0f;
+    size_t which = (a_on << 3) + (b_on << 2) + (c_on << 1) + (d_on << 0);
+
+    __constant int *cell = LINES[which];
+    if (which == 5 || which == 10)
+    {
+        float center = centers == 0
+                           ? bilinear_center(sra, srb, src, srd)
+                           : centers[pos];
+        if (center <= 0.0f)
+        {
+            cell = JOINED_LINES[which == 5 ? 0 : 1];
+        }
+    }
+
+    long edges[4];
+    edges[NORTH] = pos * 2;
+    edges[EAST] = (pos + 1) * 2 + 1;
+    edges[SOUTH] = (pos + width) * 2;
+    edges[WEST] = pos * 2 + 1;
+
+    for (size_t i = 0; i < 4; i++)
+    {
+        int edge = cell[i];
+        if (edge >= 0)
+        {
+            lines[pos * 4 + i] = edges[edge];
+        }
+    }
+}
+
+__kernel void apply(
+    __global float *buffer,
+    ulong width, ulong height,
+    __global float *crossings,
+    __global long *lines)
+{
+    march(buffer, width, height, crossings, lines, 0);
+}
+
+// Like `apply`, but saddle cells are decided by `centers`, where the field
+// was sampled in the middle of each cell (at `x + 0.5, y + 0.5`) instead of
+// interpolated from its corners.
+__kernel void apply_with_centers(
+    __global float *buffer,
+    ulong width, ulong height,
+    __global float *crossings,
+    __global long *lines,
+    __global float *centers)
+{
+    march(buffer, width, height, crossings, lines, centers);
+}
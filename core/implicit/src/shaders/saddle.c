@@ -0,0 +1,7 @@
+// The value of the field in the middle of a cell, interpolated bilinearly
+// from its corners.  Only the sign matters: it picks how the lines through a
+// saddle cell (two opposite corners inside, the other two outside) connect.
+static float bilinear_center(float sra, float srb, float src, float srd)
+{
+    return (sra + srb + src + srd) * 0.25f;
+}
@@ -0,0 +1,140 @@
+//! Finds the programs under a set of files and directories, and reports
+//! whenever one of them is written to.
+
+use notify::{self, DebouncedEvent, RecursiveMode, Watcher};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+/// Files inside of a watched directory are only picked up if they have this
+/// extension.  Files that are watched by name can be called anything.
+pub const PROGRAM_EXTENSION: &str = "shape";
+
+pub enum Event<'a> {
+    Changed(&'a Path),
+    Error(notify::Error),
+}
+
+pub struct WatchSet {
+    files: Vec<PathBuf>,
+    directories: Vec<PathBuf>,
+}
+
+impl WatchSet {
+    pub fn new(roots: &[PathBuf]) -> io::Result<WatchSet> {
+        let mut set = WatchSet {
+            files: vec![],
+            directories: vec![],
+        };
+        for root in roots {
+            let root = root.canonicalize()?;
+            if root.is_dir() {
+                set.directories.push(root);
+            } else {
+                set.files.push(root);
+            }
+        }
+        Ok(set)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.iter().any(|f| f == path)
+            || (is_program(path) && self.directories.iter().any(|d| path.starts_with(d)))
+    }
+
+    /// Every program that is currently in the set.
+    pub fn programs(&self) -> io::Result<Vec<PathBuf>> {
+        let mut out = self.files.clone();
+        for directory in &self.directories {
+            programs_in(directory, &mut out)?;
+        }
+        out.sort();
+        out.dedup();
+        Ok(out)
+    }
+}
+
+fn is_program(path: &Path) -> bool {
+    path.extension().map_or(false, |e| e == PROGRAM_EXTENSION)
+}
+
+fn programs_in(directory: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            programs_in(&path, out)?;
+        } else if is_program(&path) {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Calls `on_event` once for every program in `roots`, and then again each
+/// time one of them is changed.  Changes are debounced by `delay`.  Only
+/// returns if the watcher couldn't be set up.
+pub fn watch<F>(roots: &[PathBuf], delay: Duration, mut on_event: F) -> Result<(), notify::Error>
+where
+    F: FnMut(Event),
+{
+    let set = WatchSet::new(roots)?;
+    let (tx, rx) = channel();
+    let mut watcher = notify::watcher(tx, delay)?;
+
+    // Editors often save by renaming a new file over the old one, which
+    // loses a watch on the file itself, so files are watched through the
+    // directory that they are in.
+    for file in &set.files {
+        if let Some(parent) = file.parent() {
+            watcher.watch(parent, RecursiveMode::NonRecursive)?;
+        }
+    }
+    for directory in &set.directories {
+        watcher.watch(directory, RecursiveMode::Recursive)?;
+    }
+
+    for program in set.programs()? {
+        on_event(Event::Changed(&program));
+    }
+
+    for event in rx {
+        match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Rename(_, path) => {
+                if set.contains(&path) {
+                    on_event(Event::Changed(&path));
+                }
+            }
+            DebouncedEvent::Error(error, _) => on_event(Event::Error(error)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn finds_programs_in_directories() {
+    let root = ::std::env::temp_dir().join(format!("implicit-watch-{}", ::std::process::id()));
+    fs::create_dir_all(root.join("nested")).unwrap();
+    for name in &["a.shape", "b.svg", "nested/c.shape"] {
+        fs::write(root.join(name), "").unwrap();
+    }
+
+    let set = WatchSet::new(&[root.clone()]).unwrap();
+    let root = root.canonicalize().unwrap();
+    assert_eq!(
+        set.programs().unwrap(),
+        vec![root.join("a.shape"), root.join("nested/c.shape")]
+    );
+    assert!(set.contains(&root.join("nested/c.shape")));
+    assert!(!set.contains(&root.join("b.svg")));
+
+    let explicit = WatchSet::new(&[root.join("b.svg")]).unwrap();
+    assert!(explicit.contains(&root.join("b.svg")));
+    assert!(!explicit.contains(&root.join("a.shape")));
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -35,5 +35,5 @@ pub trait Strategy {
 
     fn shape<F>(&self, shape: Shape, width: u32, height: u32, buffer_find: F) -> Self::FieldBuf
     where
-        F: Fn(Id) -> Self::FieldBuf;
+        F: Fn(&Id) -> Self::FieldBuf;
 }
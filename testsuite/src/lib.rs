@@ -1,7 +1,9 @@
 extern crate expectation;
+extern crate extern_api;
 extern crate implicit;
 extern crate serde;
-extern crate snoot;
+#[cfg(test)]
+extern crate serde_json;
 #[cfg(test)]
 use expectation::extensions::TextDiffExtension;
 #[cfg(test)]
@@ -9,23 +11,15 @@ use std::fs::File;
 #[cfg(test)]
 use std::io::{BufRead, BufReader, Read};
 
+use extern_api::Command;
 use serde::de::DeserializeOwned;
 
-pub fn deser<T: DeserializeOwned>(out: &str) -> T {
-    use snoot::serde_serialization::*;
-    let sexprs = snoot::simple_parse(out, &[], Some("<stdin>"));
-    sexprs.diagnostics.assert_empty();
-    assert!(sexprs.roots.len() == 1);
+/// The layout of the `.shape` files: a command and the size of the canvas.
+pub type TestProgram = Option<(Command, (f32, f32))>;
 
-    let program = sexprs.roots.into_iter().next().unwrap();
-    let deser = match deserialize::<T>(&program) {
-        DeserializeResult::AllGood(v) => v,
-        DeserializeResult::CouldntRecover(bag) | DeserializeResult::CouldRecover(_, bag) => {
-            bag.assert_empty();
-            panic!()
-        }
-    };
-    deser
+pub fn deser<T: DeserializeOwned>(out: &str) -> T {
+    let sexp = implicit::program::read_sexp(out.as_bytes()).unwrap();
+    extern_api::sexp::from_sexp(&sexp).unwrap()
 }
 
 #[test]
@@ -43,7 +37,7 @@ fn expectation_test_all() {
                 let mut test_contents = String::new();
                 test_file.read_to_string(&mut test_contents).unwrap();
 
-                let deser: Option<(implicit::ocaml::Command, (f32, f32))> = deser(&test_contents);
+                let deser: TestProgram = deser(&test_contents);
                 let (command, (w, h)) = deser.unwrap();
 
                 provider.debug(format!("command.txt"), &command).unwrap();
@@ -54,7 +48,8 @@ fn expectation_test_all() {
                     provider.duplicate(),
                     w.ceil() as u32,
                     h.ceil() as u32,
-                );
+                )
+                .unwrap();
             });
         });
         if res.is_err() {
@@ -65,3 +60,27 @@ fn expectation_test_all() {
         panic!("expectation test failed");
     }
 }
+
+#[test]
+fn round_trip_all() {
+    for entry in std::fs::read_dir("./tests").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "shape") {
+            continue;
+        }
+        let mut contents = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        let program: TestProgram = deser(&contents);
+
+        let sexp = extern_api::sexp::to_string(&program).unwrap();
+        let from_sexp: TestProgram = deser(&sexp);
+        assert_eq!(program, from_sexp, "sexp round trip of {}", path.display());
+
+        let json = serde_json::to_string(&program).unwrap();
+        let from_json: TestProgram = serde_json::from_str(&json).unwrap();
+        assert_eq!(program, from_json, "json round trip of {}", path.display());
+    }
+}
@@ -87,21 +87,14 @@ pub fn svg_path_segments<W: Write>(out: W, extracted: &[PathSegment]) -> IoResul
     let svgb = SvgBackend::new(out)?;
     let mut canvas = Canvas::new(svgb);
 
-    let mut additive: Vec<Vec<_>> = vec![];
-    let mut subtractive: Vec<Vec<_>> = vec![];
-    let mut non_filled: Vec<Vec<_>> = vec![];
-    for segment in extracted.iter().cloned() {
-        if !segment.closed {
-            non_filled.push(segment.into_iter().collect());
-        } else if is_clockwise(&segment.path) {
-            additive.push(segment.into_iter().collect());
-        } else {
-            subtractive.push(segment.into_iter().collect());
-        }
-    }
+    // Whether a path is an outline or a hole depends on how many paths it's
+    // inside of, not on which way it happens to wind.
+    let tree = line_stitch::containment_tree(extracted.to_vec());
+    let additive: Vec<Vec<_>> = tree.outlines().map(|p| p.path.to_vec()).collect();
+    let subtractive: Vec<Vec<_>> = tree.holes().map(|p| p.path.to_vec()).collect();
 
     canvas.draw_holy_polygon(additive, subtractive, DrawOptions::filled((0, 0, 0)))?;
-    for segment in non_filled {
+    for segment in tree.open {
         let mut is_first = true;
         canvas.apply(Command::StartShape(DrawOptions::default()))?;
         for point in segment {
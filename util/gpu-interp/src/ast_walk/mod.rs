@@ -1,28 +1,95 @@
-use super::Ast;
+use super::{Ast, Buffer};
 
+/// Evaluates `ast` at (`x`, `y`, `z`), the same way that the GPU
+/// interpreter does.  Buffers are read at the sample that evaluation started
+/// from (as they are on the GPU, regardless of any transforms around them),
+/// and they need to have been read into memory.
 pub fn interpret(ast: &Ast, x: f32, y: f32, z: f32) -> f32 {
+    interpret_from(ast, (x, y, z), x, y, z)
+}
+
+fn interpret_from(ast: &Ast, start: (f32, f32, f32), x: f32, y: f32, z: f32) -> f32 {
+    let walk = |ast: &Ast, x: f32, y: f32, z: f32| interpret_from(ast, start, x, y, z);
     match ast {
-        Ast::Buffer(_) => unimplemented!(),
-        Ast::DistToPoly(_) => unimplemented!(),
+        Ast::Buffer(buffer) => sample_buffer(buffer, start),
+        Ast::DistToPoly(lines) => dist_to_poly(lines, x, y),
         Ast::Constant(c) => *c,
         Ast::Transform { target, matrix } => {
             let ::euclid::Point3D { x, y, z, .. } = matrix
                 .transform_point3d(&::euclid::point3(x, y, z))
                 .unwrap();
-            interpret(target, x, y, z)
+            walk(target, x, y, z)
+        }
+        Ast::RepeatGrid {
+            target,
+            spacing: (sx, sy),
+            count: (nx, ny),
+        } => walk(
+            target,
+            fold_grid_axis(x, *sx, *nx),
+            fold_grid_axis(y, *sy, *ny),
+            z,
+        ),
+        Ast::RepeatPolar {
+            target,
+            count,
+            center: (cx, cy),
+        } => {
+            let (x, y) = fold_polar(x, y, *count, *cx, *cy);
+            walk(target, x, y, z)
+        }
+        Ast::Mirror {
+            target,
+            point: (px, py),
+            normal: (nx, ny),
+        } => {
+            let (x, y) = fold_mirror(x, y, *px, *py, *nx, *ny);
+            walk(target, x, y, z)
+        }
+        Ast::Symmetry {
+            target,
+            count,
+            center: (cx, cy),
+        } => {
+            let (x, y) = fold_symmetry(x, y, *count, *cx, *cy);
+            walk(target, x, y, z)
+        }
+        Ast::Twist {
+            target,
+            center: (cx, cy),
+            rate,
+        } => {
+            let (x, y) = warp_twist(x, y, *cx, *cy, *rate);
+            walk(target, x, y, z)
+        }
+        Ast::Bend {
+            target,
+            center: (cx, cy),
+            rate,
+        } => {
+            let (x, y) = warp_bend(x, y, *cx, *cy, *rate);
+            walk(target, x, y, z)
+        }
+        Ast::Taper {
+            target,
+            center: (cx, cy),
+            rate,
+        } => {
+            let (x, y) = warp_taper(x, y, *cx, *cy, *rate);
+            walk(target, x, y, z)
         }
         Ast::X => x,
         Ast::Y => y,
         Ast::Z => z,
-        Ast::Add(list) => list.iter().map(|a| interpret(a, x, y, z)).sum(),
-        Ast::Mul(list) => list.iter().map(|a| interpret(a, x, y, z)).product(),
-        Ast::Sub(l, r) => interpret(l, x, y, z) - interpret(r, x, y, z),
+        Ast::Add(list) => list.iter().map(|a| walk(a, x, y, z)).sum(),
+        Ast::Mul(list) => list.iter().map(|a| walk(a, x, y, z)).product(),
+        Ast::Sub(l, r) => walk(l, x, y, z) - walk(r, x, y, z),
         Ast::Max(list) => {
             if list.len() == 0 {
                 panic!()
             }
             list.iter()
-                .map(|a| interpret(a, x, y, z))
+                .map(|a| walk(a, x, y, z))
                 .fold(::std::f32::MIN, |a, b| a.max(b))
         }
         Ast::Min(list) => {
@@ -30,15 +97,239 @@ pub fn interpret(ast: &Ast, x: f32, y: f32, z: f32) -> f32 {
                 panic!()
             }
             list.iter()
-                .map(|a| interpret(a, x, y, z))
-                .fold(::std::f32::MAX, |a, b| a.max(b))
+                .map(|a| walk(a, x, y, z))
+                .fold(::std::f32::MAX, |a, b| a.min(b))
         }
-        Ast::Abs(a) => interpret(a, x, y, z).abs(),
-        Ast::Neg(a) => -interpret(a, x, y, z),
-        Ast::Sqrt(a) => interpret(a, x, y, z).sqrt(),
+        Ast::Abs(a) => walk(a, x, y, z).abs(),
+        Ast::Neg(a) => -walk(a, x, y, z),
+        Ast::Sqrt(a) => walk(a, x, y, z).sqrt(),
         Ast::Square(a) => {
-            let v = interpret(a, x, y, z);
+            let v = walk(a, x, y, z);
             v * v
         }
     }
 }
+
+/// Reads `buffer` at `(x, y, z)`, interpolating between samples.
+fn sample_buffer(buffer: &Buffer, (x, y, z): (f32, f32, f32)) -> f32 {
+    let values = buffer
+        .memory()
+        .expect("buffers need to be read into memory before they're walked");
+    let (width, height) = (buffer.width as usize, buffer.height as usize);
+    let layer = (z.max(0.0) as usize).min(buffer.depth as usize - 1) * width * height;
+    let at = |x: usize, y: usize| values[layer + x + y * width];
+
+    let clamp = |v: f32, len: usize| v.max(0.0).min((len - 1) as f32);
+    let (x, y) = (clamp(x, width), clamp(y, height));
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+    let top = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+    let bottom = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// The distance from (`x`, `y`) to the nearest of `lines`, negative inside
+/// of the polygon that they outline.  Mirrors `OP_DIST_TO_LINE` and
+/// `OP_COLLECT_POLY` in the GPU interpreter.
+fn dist_to_poly(lines: &[(f32, f32, f32, f32)], x: f32, y: f32) -> f32 {
+    let mut winding = 0;
+    let mut minimum = f32::INFINITY;
+    for &(x1, y1, x2, y2) in lines {
+        let (c, d) = (x2 - x1, y2 - y1);
+        let len_sq = c * c + d * d;
+        let param = if len_sq != 0.0 {
+            ((x - x1) * c + (y - y1) * d) / len_sq
+        } else {
+            -1.0
+        };
+        let (xx, yy) = if param < 0.0 {
+            (x1, y1)
+        } else if param > 1.0 {
+            (x2, y2)
+        } else {
+            (x1 + param * c, y1 + param * d)
+        };
+        minimum = minimum.min(((x - xx) * (x - xx) + (y - yy) * (y - yy)).sqrt());
+
+        let is_left = c * (y - y1) - d * (x - x1);
+        if y1 <= y {
+            if y2 > y && is_left > 0.0 {
+                winding += 1;
+            }
+        } else if y2 <= y && is_left < 0.0 {
+            winding -= 1;
+        }
+    }
+    if winding == 0 {
+        minimum
+    } else {
+        -minimum
+    }
+}
+
+/// Moves `v` into the cell of a `count` long row of cells that it is
+/// closest to.  Cells are `spacing` apart and the first one is at 0.
+pub fn fold_grid_axis(v: f32, spacing: f32, count: u32) -> f32 {
+    if spacing == 0.0 || count <= 1 {
+        return v;
+    }
+    let cell = (v / spacing + 0.5).floor().max(0.0).min((count - 1) as f32);
+    v - spacing * cell
+}
+
+/// Rotates (`x`, `y`) around (`cx`, `cy`) into the wedge that straddles
+/// the positive x axis.
+pub fn fold_polar(x: f32, y: f32, count: u32, cx: f32, cy: f32) -> (f32, f32) {
+    let sector = polar_sector(count);
+    let (dx, dy) = (x - cx, y - cy);
+    let angle = dy.atan2(dx);
+    let angle = angle - sector * (angle / sector + 0.5).floor();
+    let r = (dx * dx + dy * dy).sqrt();
+    (cx + r * angle.cos(), cy + r * angle.sin())
+}
+
+/// The angle covered by each of the `count` wedges of a polar repetition.
+pub fn polar_sector(count: u32) -> f32 {
+    2.0 * ::std::f32::consts::PI / (::std::cmp::max(count, 1) as f32)
+}
+
+/// Reflects (`x`, `y`) across the line through (`px`, `py`) if it is behind
+/// the unit normal (`nx`, `ny`).
+pub fn fold_mirror(x: f32, y: f32, px: f32, py: f32, nx: f32, ny: f32) -> (f32, f32) {
+    let behind = ((x - px) * nx + (y - py) * ny).min(0.0);
+    (x - 2.0 * behind * nx, y - 2.0 * behind * ny)
+}
+
+/// Like `fold_polar`, but also reflects the lower half of the wedge onto
+/// the upper half.
+pub fn fold_symmetry(x: f32, y: f32, count: u32, cx: f32, cy: f32) -> (f32, f32) {
+    let sector = polar_sector(count);
+    let (dx, dy) = (x - cx, y - cy);
+    let angle = dy.atan2(dx);
+    let angle = (angle - sector * (angle / sector + 0.5).floor()).abs();
+    let r = (dx * dx + dy * dy).sqrt();
+    (cx + r * angle.cos(), cy + r * angle.sin())
+}
+
+/// Rotates (`x`, `y`) around (`cx`, `cy`) by `-angle` radians.
+fn unrotate(x: f32, y: f32, cx: f32, cy: f32, angle: f32) -> (f32, f32) {
+    let (dx, dy) = (x - cx, y - cy);
+    let (s, c) = angle.sin_cos();
+    (cx + c * dx + s * dy, cy - s * dx + c * dy)
+}
+
+/// Finds the point that a twist moves onto (`x`, `y`).
+pub fn warp_twist(x: f32, y: f32, cx: f32, cy: f32, rate: f32) -> (f32, f32) {
+    let r = ((x - cx) * (x - cx) + (y - cy) * (y - cy)).sqrt();
+    unrotate(x, y, cx, cy, rate * r)
+}
+
+/// Finds the point that a bend moves onto (`x`, `y`).
+pub fn warp_bend(x: f32, y: f32, cx: f32, cy: f32, rate: f32) -> (f32, f32) {
+    unrotate(x, y, cx, cy, rate * (x - cx))
+}
+
+/// The least that a taper scales heights by.  Without a limit, the scale
+/// reaches zero `1 / rate` away from the center and turns the shape inside
+/// out beyond that; with it, the shape stays at its narrowest instead.
+pub const TAPER_MIN_SCALE: f32 = 0.01;
+
+/// Finds the point that a taper moves onto (`x`, `y`).
+pub fn warp_taper(x: f32, y: f32, cx: f32, cy: f32, rate: f32) -> (f32, f32) {
+    let scale = (1.0 + rate * (x - cx)).max(TAPER_MIN_SCALE);
+    (x, cy + (y - cy) / scale)
+}
+
+#[test]
+fn grid_folds_into_nearest_cell() {
+    assert_eq!(fold_grid_axis(0.25, 1.0, 3), 0.25);
+    assert_eq!(fold_grid_axis(1.25, 1.0, 3), 0.25);
+    assert_eq!(fold_grid_axis(1.75, 1.0, 3), -0.25);
+    assert_eq!(fold_grid_axis(-3.0, 1.0, 3), -3.0);
+    assert_eq!(fold_grid_axis(5.0, 1.0, 3), 3.0);
+    assert_eq!(fold_grid_axis(5.0, 1.0, 1), 5.0);
+}
+
+#[test]
+fn tapers_stop_at_their_narrowest() {
+    assert_eq!(warp_taper(1.0, 2.0, 0.0, 0.0, 1.0), (1.0, 1.0));
+    // The scale would be zero at x = -1 and negative past it.
+    for &x in &[-1.0, -5.0] {
+        let (_, y) = warp_taper(x, 2.0, 0.0, 0.0, 1.0);
+        assert!((y - 2.0 / TAPER_MIN_SCALE).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn polar_folds_onto_first_wedge() {
+    let (x, y) = fold_polar(0.0, 1.0, 4, 0.0, 0.0);
+    assert!((x - 1.0).abs() < 1e-6 && y.abs() < 1e-6);
+    let (x, y) = fold_polar(3.0, 2.0, 2, 2.0, 2.0);
+    assert!((x - 3.0).abs() < 1e-6 && (y - 2.0).abs() < 1e-6);
+}
+
+#[test]
+fn mirror_reflects_the_back_half() {
+    assert_eq!(fold_mirror(3.0, 1.0, 2.0, 0.0, 1.0, 0.0), (3.0, 1.0));
+    assert_eq!(fold_mirror(1.0, 1.0, 2.0, 0.0, 1.0, 0.0), (3.0, 1.0));
+    assert_eq!(fold_mirror(1.0, -4.0, 0.0, 0.0, 0.0, 1.0), (1.0, 4.0));
+}
+
+#[test]
+fn symmetry_folds_onto_half_wedge() {
+    let (x, y) = fold_symmetry(1.0, -1.0, 4, 0.0, 0.0);
+    assert!((x - 1.0).abs() < 1e-6 && (y - 1.0).abs() < 1e-6);
+    let (x, y) = fold_symmetry(-1.0, 0.5, 2, 0.0, 0.0);
+    assert!((x - 1.0).abs() < 1e-6 && (y - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn warps_leave_their_center_alone() {
+    assert_eq!(warp_twist(1.0, 2.0, 1.0, 2.0, 3.0), (1.0, 2.0));
+    assert_eq!(warp_bend(1.0, 5.0, 1.0, 2.0, 3.0), (1.0, 5.0));
+    assert_eq!(warp_taper(1.0, 5.0, 1.0, 2.0, 3.0), (1.0, 5.0));
+    assert_eq!(warp_taper(2.0, 5.0, 1.0, 2.0, 2.0), (2.0, 3.0));
+
+    let quarter = ::std::f32::consts::PI / 2.0;
+    let (x, y) = warp_twist(0.0, 1.0, 0.0, 0.0, quarter);
+    assert!((x - 1.0).abs() < 1e-6 && y.abs() < 1e-6);
+}
+
+#[test]
+fn min_and_max_pick_their_ends() {
+    let list = [Ast::X, Ast::Y, Ast::Constant(2.0)];
+    assert_eq!(interpret(&Ast::Min(&list), 1.0, 3.0, 0.0), 1.0);
+    assert_eq!(interpret(&Ast::Max(&list), 1.0, 3.0, 0.0), 3.0);
+    assert_eq!(interpret(&Ast::Sub(&Ast::X, &Ast::Y), 1.0, 3.0, 0.0), -2.0);
+}
+
+#[test]
+fn polygons_are_negative_inside() {
+    // A 2x2 square, wound the way the compiler winds rects.
+    let square = Ast::DistToPoly(vec![
+        (0.0, 0.0, 2.0, 0.0),
+        (2.0, 0.0, 2.0, 2.0),
+        (2.0, 2.0, 0.0, 2.0),
+        (0.0, 2.0, 0.0, 0.0),
+    ]);
+    assert_eq!(interpret(&square, 1.0, 1.0, 0.0), -1.0);
+    assert_eq!(interpret(&square, 1.5, 0.5, 0.0), -0.5);
+    assert_eq!(interpret(&square, 3.0, 1.0, 0.0), 1.0);
+    assert_eq!(interpret(&square, 5.0, 6.0, 0.0), 5.0);
+}
+
+#[test]
+fn buffers_are_read_where_evaluation_started() {
+    let buffer = Ast::Buffer(Buffer::from_memory(vec![0.0, 1.0, 2.0, 3.0], 2, 2, 1));
+    assert_eq!(interpret(&buffer, 0.5, 0.5, 0.0), 1.5);
+    assert_eq!(interpret(&buffer, 1.0, 0.0, 0.0), 1.0);
+    assert_eq!(interpret(&buffer, -4.0, 9.0, 0.0), 2.0);
+
+    let matrix = ::euclid::Transform3D::create_translation(1.0, 1.0, 0.0);
+    let moved = Ast::Transform {
+        target: &buffer,
+        matrix,
+    };
+    assert_eq!(interpret(&moved, 0.0, 0.0, 0.0), 0.0);
+}
@@ -81,6 +81,14 @@ impl Buffer {
         }
     }
 
+    /// The contents of the buffer, if they've been read into memory.
+    pub fn memory(&self) -> Option<&[f32]> {
+        match &self.kind {
+            BufferKind::Memory(c) | BufferKind::Both(_, c) => Some(c),
+            _ => None,
+        }
+    }
+
     pub fn to_memory(&mut self) -> &[f32] {
         let mut contents = BufferKind::Memory(vec![]);
         ::std::mem::swap(&mut self.kind, &mut contents);
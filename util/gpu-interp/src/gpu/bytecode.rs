@@ -8,8 +8,10 @@ pub mod ops {
 #[derive(PartialOrd, PartialEq)]
 struct OrderedF32(f32);
 
+// The first `inputs` slots of the constant buffer are left for inputs.
 pub struct ConstantCache {
     map: ::std::collections::BTreeMap<OrderedF32, u32>,
+    inputs: u32,
 }
 impl Eq for OrderedF32 {}
 
@@ -21,26 +23,38 @@ impl Ord for OrderedF32 {
 }
 
 impl ConstantCache {
-    fn new() -> ConstantCache {
+    fn new(inputs: u32) -> ConstantCache {
         ConstantCache {
             map: ::std::collections::BTreeMap::new(),
+            inputs,
         }
     }
     fn record(&mut self, value: f32) -> u32 {
-        let size = self.map.len();
-        *self.map.entry(OrderedF32(value)).or_insert(size as u32)
+        let size = self.len();
+        *self.map.entry(OrderedF32(value)).or_insert(size)
+    }
+    fn slot(&mut self, operand: Operand) -> u32 {
+        match operand {
+            Operand::Constant(value) => self.record(value),
+            Operand::Input(i) => {
+                assert!(i < self.inputs, "input {} of {}", i, self.inputs);
+                i
+            }
+        }
     }
     fn to_vec(self) -> Vec<f32> {
         let mut v = self.map.into_iter().collect::<Vec<_>>();
         v.sort_by_key(|&(_, v)| v);
-        v.into_iter().map(|(OrderedF32(k), _)| k).collect()
+        let mut constants = vec![0.0; self.inputs as usize];
+        constants.extend(v.into_iter().map(|(OrderedF32(k), _)| k));
+        constants
     }
     fn len(&self) -> u32 {
-        self.map.len() as u32
+        self.inputs + self.map.len() as u32
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CompilationResult {
     pub code: Vec<u8>,
     pub constants: Vec<f32>,
@@ -49,13 +63,35 @@ pub struct CompilationResult {
     pub buffers: Vec<Buffer>,
 }
 
+impl CompilationResult {
+    /// Gives a program from `compile_with_inputs` the values of its inputs,
+    /// in order.
+    pub fn set_inputs(&mut self, inputs: &[f32]) {
+        self.constants[..inputs.len()].copy_from_slice(inputs);
+    }
+}
+
 pub fn compile(ast: &Ast) -> CompilationResult {
-    fn transform_depth(ast: &Ast) -> u32 {
+    compile_with_inputs(ast, 0)
+}
+
+/// Compiles a tree that reads `inputs` inputs.  They're kept at the start
+/// of the constant buffer, so that the program can be run with new values
+/// for them (from `set_inputs`) without compiling it again.
+pub fn compile_with_inputs<V: Number>(ast: &Ast<V>, inputs: u32) -> CompilationResult {
+    fn transform_depth<V: Number>(ast: &Ast<V>) -> u32 {
         use std::cmp::max;
         match ast {
             Ast::X | Ast::Y | Ast::Z | Ast::DistToPoly(_) => 1,
             Ast::Constant(_) | Ast::Buffer(_) => 0,
-            Ast::Transform { target, .. } => 1 + transform_depth(target),
+            Ast::Transform { target, .. }
+            | Ast::RepeatGrid { target, .. }
+            | Ast::RepeatPolar { target, .. }
+            | Ast::Mirror { target, .. }
+            | Ast::Symmetry { target, .. }
+            | Ast::Twist { target, .. }
+            | Ast::Bend { target, .. }
+            | Ast::Taper { target, .. } => 1 + transform_depth(target),
             Ast::Sub(l, r) => max(transform_depth(l), transform_depth(r)),
             Ast::Mul(lst) | Ast::Add(lst) | Ast::Min(lst) | Ast::Max(lst) => {
                 lst.iter().map(transform_depth).fold(0, max)
@@ -63,12 +99,19 @@ pub fn compile(ast: &Ast) -> CompilationResult {
             Ast::Square(t) | Ast::Abs(t) | Ast::Sqrt(t) | Ast::Neg(t) => transform_depth(t),
         }
     }
-    fn depth(ast: &Ast) -> u32 {
+    fn depth<V: Number>(ast: &Ast<V>) -> u32 {
         use std::cmp::max;
         match ast {
             Ast::X | Ast::Y | Ast::Z | Ast::Constant(_) | Ast::Buffer(_) => 1,
             Ast::DistToPoly(v) => v.len() as u32,
-            Ast::Transform { target, .. } => depth(target),
+            Ast::Transform { target, .. }
+            | Ast::RepeatGrid { target, .. }
+            | Ast::RepeatPolar { target, .. }
+            | Ast::Mirror { target, .. }
+            | Ast::Symmetry { target, .. }
+            | Ast::Twist { target, .. }
+            | Ast::Bend { target, .. }
+            | Ast::Taper { target, .. } => depth(target),
             Ast::Sub(l, r) => max(depth(l), depth(r)) + 1,
             Ast::Mul(lst) | Ast::Add(lst) | Ast::Min(lst) | Ast::Max(lst) => {
                 lst.iter().map(depth).fold(0, max) + 1
@@ -77,20 +120,20 @@ pub fn compile(ast: &Ast) -> CompilationResult {
         }
     }
 
-    fn compile_inner(
-        ast: &Ast,
+    fn compile_inner<V: Number>(
+        ast: &Ast<V>,
         code: &mut Vec<u8>,
         constants: &mut ConstantCache,
         buffers: &mut Vec<Buffer>,
     ) {
-        fn push_const(constant: f32, code: &mut Vec<u8>, constants: &mut ConstantCache) {
+        fn push_const<V: Number>(constant: V, code: &mut Vec<u8>, constants: &mut ConstantCache) {
             if constants.len() >= 255 {
                 panic!("large constants not supported yet")
             }
-            code.push(constants.record(constant) as u8);
+            code.push(constants.slot(constant.operand()) as u8);
         }
-        fn compile_inner_list(
-            asts: &[Ast],
+        fn compile_inner_list<V: Number>(
+            asts: &[Ast<V>],
             code: &mut Vec<u8>,
             constants: &mut ConstantCache,
             buffers: &mut Vec<Buffer>,
@@ -154,6 +197,94 @@ pub fn compile(ast: &Ast) -> CompilationResult {
                 compile_inner(target, code, constants, buffers);
                 code.push(ops::POP_TRANSFORM);
             }
+            Ast::RepeatGrid {
+                target,
+                spacing: (sx, sy),
+                count: (nx, ny),
+            } => {
+                // A degenerate axis is folded with a single cell, which leaves
+                // the coordinate untouched without dividing by zero.  The
+                // kernel does the same for inputs that turn out to be zero.
+                fn axis(spacing: Operand, count: u32) -> (Operand, Operand) {
+                    if spacing == Operand::Constant(0.0) || count <= 1 {
+                        (Operand::Constant(1.0), Operand::Constant(0.0))
+                    } else {
+                        (spacing, Operand::Constant((count - 1) as f32))
+                    }
+                }
+                let (sx, mx) = axis(sx.operand(), *nx);
+                let (sy, my) = axis(sy.operand(), *ny);
+                code.push(ops::PUSH_REPEAT_GRID);
+                push_const(sx, code, constants);
+                push_const(sy, code, constants);
+                push_const(mx, code, constants);
+                push_const(my, code, constants);
+                compile_inner(target, code, constants, buffers);
+                code.push(ops::POP_TRANSFORM);
+            }
+            Ast::RepeatPolar {
+                target,
+                count,
+                center: (cx, cy),
+            } => {
+                code.push(ops::PUSH_REPEAT_POLAR);
+                push_const(*cx, code, constants);
+                push_const(*cy, code, constants);
+                push_const(::ast_walk::polar_sector(*count), code, constants);
+                compile_inner(target, code, constants, buffers);
+                code.push(ops::POP_TRANSFORM);
+            }
+            Ast::Mirror {
+                target,
+                point: (px, py),
+                normal: (nx, ny),
+            } => {
+                code.push(ops::PUSH_MIRROR);
+                push_const(*px, code, constants);
+                push_const(*py, code, constants);
+                push_const(*nx, code, constants);
+                push_const(*ny, code, constants);
+                compile_inner(target, code, constants, buffers);
+                code.push(ops::POP_TRANSFORM);
+            }
+            Ast::Symmetry {
+                target,
+                count,
+                center: (cx, cy),
+            } => {
+                code.push(ops::PUSH_SYMMETRY);
+                push_const(*cx, code, constants);
+                push_const(*cy, code, constants);
+                push_const(::ast_walk::polar_sector(*count), code, constants);
+                compile_inner(target, code, constants, buffers);
+                code.push(ops::POP_TRANSFORM);
+            }
+            Ast::Twist {
+                target,
+                center: (cx, cy),
+                rate,
+            }
+            | Ast::Bend {
+                target,
+                center: (cx, cy),
+                rate,
+            }
+            | Ast::Taper {
+                target,
+                center: (cx, cy),
+                rate,
+            } => {
+                code.push(match ast {
+                    Ast::Twist { .. } => ops::PUSH_TWIST,
+                    Ast::Bend { .. } => ops::PUSH_BEND,
+                    _ => ops::PUSH_TAPER,
+                });
+                push_const(*cx, code, constants);
+                push_const(*cy, code, constants);
+                push_const(*rate, code, constants);
+                compile_inner(target, code, constants, buffers);
+                code.push(ops::POP_TRANSFORM);
+            }
             Ast::Sub(l, r) => {
                 compile_inner(l, code, constants, buffers);
                 compile_inner(r, code, constants, buffers);
@@ -183,7 +314,7 @@ pub fn compile(ast: &Ast) -> CompilationResult {
     }
 
     let mut code = vec![];
-    let mut constants = ConstantCache::new();
+    let mut constants = ConstantCache::new(inputs);
     let max_stack = depth(ast);
     let transform_depth = transform_depth(ast);
     let mut buffers = vec![];
@@ -318,3 +449,107 @@ fn compile_a_transform() {
         }
     )
 }
+
+#[test]
+fn compile_a_repeat_grid() {
+    assert_eq!(
+        compile(&Ast::RepeatGrid {
+            target: &Ast::X,
+            spacing: (10.0, 0.0),
+            count: (4, 4),
+        }),
+        CompilationResult {
+            code: vec![
+                ops::PUSH_REPEAT_GRID,
+                0,
+                1,
+                2,
+                3,
+                ops::X,
+                ops::POP_TRANSFORM
+            ],
+            constants: vec![10.0, 1.0, 3.0, 0.0],
+            max_stack: 1,
+            transform_depth: 2,
+            buffers: vec![],
+        }
+    )
+}
+
+#[test]
+fn compile_a_repeat_polar() {
+    assert_eq!(
+        compile(&Ast::RepeatPolar {
+            target: &Ast::Y,
+            count: 4,
+            center: (5.0, 5.0),
+        }),
+        CompilationResult {
+            code: vec![ops::PUSH_REPEAT_POLAR, 0, 0, 1, ops::Y, ops::POP_TRANSFORM],
+            constants: vec![5.0, ::std::f32::consts::PI / 2.0],
+            max_stack: 1,
+            transform_depth: 2,
+            buffers: vec![],
+        }
+    )
+}
+
+#[test]
+fn compile_a_mirror() {
+    assert_eq!(
+        compile(&Ast::Mirror {
+            target: &Ast::X,
+            point: (2.0, 0.0),
+            normal: (1.0, 0.0),
+        }),
+        CompilationResult {
+            code: vec![ops::PUSH_MIRROR, 0, 1, 2, 1, ops::X, ops::POP_TRANSFORM],
+            constants: vec![2.0, 0.0, 1.0],
+            max_stack: 1,
+            transform_depth: 2,
+            buffers: vec![],
+        }
+    )
+}
+
+#[test]
+fn compile_a_twist() {
+    assert_eq!(
+        compile(&Ast::Twist {
+            target: &Ast::X,
+            center: (1.0, 1.0),
+            rate: 0.5,
+        }),
+        CompilationResult {
+            code: vec![ops::PUSH_TWIST, 0, 0, 1, ops::X, ops::POP_TRANSFORM],
+            constants: vec![1.0, 0.5],
+            max_stack: 1,
+            transform_depth: 2,
+            buffers: vec![],
+        }
+    )
+}
+
+#[test]
+fn compile_with_inputs_first() {
+    let mut compiled = compile_with_inputs(
+        &Ast::Mirror {
+            target: &Ast::X,
+            point: (Operand::Constant(2.0), Operand::Input(1)),
+            normal: (Operand::Input(0), Operand::Constant(0.0)),
+        },
+        2,
+    );
+    assert_eq!(
+        compiled,
+        CompilationResult {
+            code: vec![ops::PUSH_MIRROR, 2, 1, 0, 3, ops::X, ops::POP_TRANSFORM],
+            constants: vec![0.0, 0.0, 2.0, 0.0],
+            max_stack: 1,
+            transform_depth: 2,
+            buffers: vec![],
+        }
+    );
+    compiled.set_inputs(&[1.0, 5.0]);
+    assert_eq!(compiled.constants, vec![1.0, 5.0, 2.0, 0.0]);
+}
@@ -17,12 +17,39 @@ impl Triad {
     }
 }
 
+/// The OpenCL source of the bytecode interpreter.
+pub const INTERPRETER_SOURCE: &str = concat!(
+    include_str!("../../../../core/implicit/src/shaders/dist_to_line.c"),
+    include_str!(concat!(env!("OUT_DIR"), "/opcodes.c")),
+    include_str!("./interp.c")
+);
+
+pub fn build_program(context: &Context) -> Program {
+    Program::builder()
+        .source(INTERPRETER_SOURCE)
+        .build(context)
+        .unwrap()
+}
+
 pub fn execute(
     compilation: CompilationResult,
     width: u32,
     height: u32,
     depth: u32,
-    Triad { context, queue }: Triad,
+    triad: Triad,
+) -> Buffer {
+    let program = build_program(&triad.context);
+    execute_with_program(compilation, width, height, depth, triad, &program)
+}
+
+/// Like `execute`, but reuses an interpreter built by `build_program`.
+pub fn execute_with_program(
+    compilation: CompilationResult,
+    width: u32,
+    height: u32,
+    depth: u32,
+    Triad { queue, .. }: Triad,
+    program: &Program,
 ) -> Buffer {
     assert!(compilation.buffers.len() <= super::bytecode::ops::BUFFER_COUNT);
 
@@ -80,18 +107,9 @@ pub fn execute(
         .unwrap();
     output_clone = output.clone();
 
-    let program = Program::builder()
-        .source(concat!(
-            include_str!("../../../../core/implicit/src/shaders/dist_to_line.c"),
-            include_str!(concat!(env!("OUT_DIR"), "/opcodes.c")),
-            include_str!("./interp.c")
-        ))
-        .build(&context)
-        .unwrap();
-
     let mut kernel_builder = Kernel::builder();
     let kernel = kernel_builder
-        .program(&program)
+        .program(program)
         .name("apply")
         .queue(queue.clone())
         .global_work_size([width, height, depth])
@@ -264,3 +282,16 @@ fn max_of_a_few_buffers() {
     let mut buf = execute(c, 3, 1, 1, triad.clone());
     assert_eq!(buf.to_memory(), &[1.0, 1.0, 2.0]);
 }
+
+#[test]
+fn repeat_grid_x() {
+    use super::bytecode::*;
+    use super::*;
+    let c = compile(&Ast::RepeatGrid {
+        target: &Ast::X,
+        spacing: (2.0, 2.0),
+        count: (2, 1),
+    });
+    let mut b = execute(c, 6, 1, 1, Triad::default());
+    assert_eq!(b.to_memory(), &[0.0, -1.0, 0.0, 1.0, 2.0, 3.0]);
+}
@@ -83,8 +83,8 @@ __kernel void apply(
                 break;
             }
             case OP_SUB: {
-                float l = POP();
                 float r = POP();
+                float l = POP();
                 PUSH(l - r);
                 break;
             }
@@ -190,6 +190,107 @@ __kernel void apply(
                 PUSH_POS(x / w, y / w, z / w);
                 break;
             }
+            case OP_PUSH_REPEAT_GRID: {
+                float spacing_x = FETCH_SMALL();
+                float spacing_y = FETCH_SMALL();
+                float max_x = FETCH_SMALL();
+                float max_y = FETCH_SMALL();
+
+                float x_s = X_POS();
+                float y_s = Y_POS();
+                float z_s = Z_POS();
+
+                // Spacings that are inputs can be zero, which folds nothing.
+                float cell_x = spacing_x == 0.0f ? 0.0f : clamp(floor(x_s / spacing_x + 0.5f), 0.0f, max_x);
+                float cell_y = spacing_y == 0.0f ? 0.0f : clamp(floor(y_s / spacing_y + 0.5f), 0.0f, max_y);
+
+                PUSH_POS(x_s - spacing_x * cell_x, y_s - spacing_y * cell_y, z_s);
+                break;
+            }
+            case OP_PUSH_REPEAT_POLAR: {
+                float cx = FETCH_SMALL();
+                float cy = FETCH_SMALL();
+                float sector = FETCH_SMALL();
+
+                float dx = X_POS() - cx;
+                float dy = Y_POS() - cy;
+                float z_s = Z_POS();
+
+                float angle = atan2(dy, dx);
+                angle -= sector * floor(angle / sector + 0.5f);
+                float r = sqrt(dx * dx + dy * dy);
+
+                PUSH_POS(cx + r * cos(angle), cy + r * sin(angle), z_s);
+                break;
+            }
+            case OP_PUSH_MIRROR: {
+                float px = FETCH_SMALL();
+                float py = FETCH_SMALL();
+                float nx = FETCH_SMALL();
+                float ny = FETCH_SMALL();
+
+                float x_s = X_POS();
+                float y_s = Y_POS();
+                float z_s = Z_POS();
+
+                float behind = fmin((x_s - px) * nx + (y_s - py) * ny, 0.0f);
+
+                PUSH_POS(x_s - 2.0f * behind * nx, y_s - 2.0f * behind * ny, z_s);
+                break;
+            }
+            case OP_PUSH_SYMMETRY: {
+                float cx = FETCH_SMALL();
+                float cy = FETCH_SMALL();
+                float sector = FETCH_SMALL();
+
+                float dx = X_POS() - cx;
+                float dy = Y_POS() - cy;
+                float z_s = Z_POS();
+
+                float angle = atan2(dy, dx);
+                angle = fabs(angle - sector * floor(angle / sector + 0.5f));
+                float r = sqrt(dx * dx + dy * dy);
+
+                PUSH_POS(cx + r * cos(angle), cy + r * sin(angle), z_s);
+                break;
+            }
+            case OP_PUSH_TWIST:
+            case OP_PUSH_BEND: {
+                float cx = FETCH_SMALL();
+                float cy = FETCH_SMALL();
+                float rate = FETCH_SMALL();
+
+                float dx = X_POS() - cx;
+                float dy = Y_POS() - cy;
+                float z_s = Z_POS();
+
+                float angle;
+                if (code == OP_PUSH_TWIST) {
+                    angle = rate * sqrt(dx * dx + dy * dy);
+                } else {
+                    angle = rate * dx;
+                }
+                float s = sin(angle);
+                float c = cos(angle);
+
+                PUSH_POS(cx + c * dx + s * dy, cy - s * dx + c * dy, z_s);
+                break;
+            }
+            case OP_PUSH_TAPER: {
+                float cx = FETCH_SMALL();
+                float cy = FETCH_SMALL();
+                float rate = FETCH_SMALL();
+
+                float x_s = X_POS();
+                float y_s = Y_POS();
+                float z_s = Z_POS();
+
+                // Past where the taper would pinch to nothing, it stays at
+                // its narrowest (see `ast_walk::TAPER_MIN_SCALE`).
+                float scale = fmax(1.0f + rate * (x_s - cx), 0.01f);
+                PUSH_POS(x_s, cy + (y_s - cy) / scale, z_s);
+                break;
+            }
             case OP_POP_TRANSFORM: {
                 POP_POS();
                 break;
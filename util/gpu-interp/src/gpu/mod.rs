@@ -2,4 +2,6 @@ pub mod bytecode;
 mod gpu_interp;
 
 pub use self::bytecode::compile;
-pub use self::gpu_interp::{execute, Triad};
+pub use self::gpu_interp::{
+    build_program, execute, execute_with_program, Triad, INTERPRETER_SOURCE,
+};
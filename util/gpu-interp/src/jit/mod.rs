@@ -93,6 +93,101 @@ impl JIT {
 }
 
 impl<'a> FunctionTranslator<'a> {
+    fn position_variables(
+        &self,
+        transform_depth: usize,
+    ) -> (Variable, Variable, Variable, Variable) {
+        let get = |dim| {
+            self.variables
+                .get(&format!("{}_{}", dim, transform_depth))
+                .unwrap()
+                .clone()
+        };
+        (get("x"), get("y"), get("z"), get("w"))
+    }
+
+    // Mirrors `ast_walk::fold_grid_axis`.
+    fn fold_grid_axis(&mut self, v: Value, spacing: f32, count: u32) -> Value {
+        if spacing == 0.0 || count <= 1 {
+            return v;
+        }
+        let spacing = self.builder.ins().f32const(Ieee32::with_float(spacing));
+        let half = self.builder.ins().f32const(Ieee32::with_float(0.5));
+        let first = self.builder.ins().f32const(Ieee32::with_float(0.0));
+        let last = self
+            .builder
+            .ins()
+            .f32const(Ieee32::with_float((count - 1) as f32));
+
+        let cell = self.builder.ins().fdiv(v, spacing);
+        let cell = self.builder.ins().fadd(cell, half);
+        let cell = self.builder.ins().floor(cell);
+        let cell = self.builder.ins().fmax(cell, first);
+        let cell = self.builder.ins().fmin(cell, last);
+        let offset = self.builder.ins().fmul(cell, spacing);
+        self.builder.ins().fsub(v, offset)
+    }
+
+    // Mirrors `ast_walk::fold_polar`, or `ast_walk::fold_symmetry` when
+    // `reflect` is set.
+    fn fold_polar(
+        &mut self,
+        x: Value,
+        y: Value,
+        count: u32,
+        (cx, cy): (f32, f32),
+        reflect: bool,
+    ) -> (Value, Value) {
+        let cx = self.builder.ins().f32const(Ieee32::with_float(cx));
+        let cy = self.builder.ins().f32const(Ieee32::with_float(cy));
+        let dx = self.builder.ins().fsub(x, cx);
+        let dy = self.builder.ins().fsub(y, cy);
+
+        let sector = ::ast_walk::polar_sector(count);
+        let sector = self.builder.ins().f32const(Ieee32::with_float(sector));
+        let half = self.builder.ins().f32const(Ieee32::with_float(0.5));
+        let angle = self.call_libm("atan2f", &[dy, dx]);
+        let wedge = self.builder.ins().fdiv(angle, sector);
+        let wedge = self.builder.ins().fadd(wedge, half);
+        let wedge = self.builder.ins().floor(wedge);
+        let offset = self.builder.ins().fmul(wedge, sector);
+        let mut angle = self.builder.ins().fsub(angle, offset);
+        if reflect {
+            angle = self.builder.ins().fabs(angle);
+        }
+
+        let dx2 = self.builder.ins().fmul(dx, dx);
+        let dy2 = self.builder.ins().fmul(dy, dy);
+        let r = self.builder.ins().fadd(dx2, dy2);
+        let r = self.builder.ins().sqrt(r);
+
+        let cos = self.call_libm("cosf", &[angle]);
+        let sin = self.call_libm("sinf", &[angle]);
+        let x = self.builder.ins().fmul(r, cos);
+        let x = self.builder.ins().fadd(cx, x);
+        let y = self.builder.ins().fmul(r, sin);
+        let y = self.builder.ins().fadd(cy, y);
+        (x, y)
+    }
+
+    // Calls a single precision function from the C math library.
+    fn call_libm(&mut self, name: &str, args: &[Value]) -> Value {
+        let mut sig = self.module.make_signature();
+        for _ in args {
+            sig.params.push(AbiParam::new(self.float));
+        }
+        sig.returns.push(AbiParam::new(self.float));
+        let callee = self
+            .module
+            .declare_function(name, Linkage::Import, &sig)
+            .expect("problem declaring libm function");
+        let local_callee = self
+            .module
+            .declare_func_in_func(callee, &mut self.builder.func);
+        let call = self.builder.ins().call(local_callee, args);
+        self.builder.inst_results(call)[0]
+    }
+
     fn translate_binary_slice<F>(
         &mut self,
         slice: &[Ast],
@@ -174,24 +269,7 @@ impl<'a> FunctionTranslator<'a> {
             }
 
             Ast::Transform { target, matrix } => {
-                let (new_x, new_y, new_z, new_w) = (
-                    self.variables
-                        .get(&format!("x_{}", transform_depth))
-                        .unwrap()
-                        .clone(),
-                    self.variables
-                        .get(&format!("y_{}", transform_depth))
-                        .unwrap()
-                        .clone(),
-                    self.variables
-                        .get(&format!("z_{}", transform_depth))
-                        .unwrap()
-                        .clone(),
-                    self.variables
-                        .get(&format!("w_{}", transform_depth))
-                        .unwrap()
-                        .clone(),
-                );
+                let (new_x, new_y, new_z, new_w) = self.position_variables(transform_depth);
                 let (x, y, z, w) = {
                     let mut form = |a, b, c, d| {
                         let xx = self.builder.use_var(xvar);
@@ -241,6 +319,163 @@ impl<'a> FunctionTranslator<'a> {
 
                 self.translate_expr(target, new_x, new_y, new_z, transform_depth + 1)
             }
+            Ast::RepeatGrid {
+                target,
+                spacing: (sx, sy),
+                count: (nx, ny),
+            } => {
+                let (new_x, new_y, new_z, _) = self.position_variables(transform_depth);
+                let x = self.builder.use_var(xvar);
+                let y = self.builder.use_var(yvar);
+                let z = self.builder.use_var(zvar);
+                let x = self.fold_grid_axis(x, *sx, *nx);
+                let y = self.fold_grid_axis(y, *sy, *ny);
+                self.builder.def_var(new_x, x);
+                self.builder.def_var(new_y, y);
+                self.builder.def_var(new_z, z);
+
+                self.translate_expr(target, new_x, new_y, new_z, transform_depth + 1)
+            }
+            Ast::RepeatPolar {
+                target,
+                count,
+                center,
+            } => {
+                let (new_x, new_y, new_z, _) = self.position_variables(transform_depth);
+                let x = self.builder.use_var(xvar);
+                let y = self.builder.use_var(yvar);
+                let z = self.builder.use_var(zvar);
+                let (x, y) = self.fold_polar(x, y, *count, *center, false);
+                self.builder.def_var(new_x, x);
+                self.builder.def_var(new_y, y);
+                self.builder.def_var(new_z, z);
+
+                self.translate_expr(target, new_x, new_y, new_z, transform_depth + 1)
+            }
+            Ast::Symmetry {
+                target,
+                count,
+                center,
+            } => {
+                let (new_x, new_y, new_z, _) = self.position_variables(transform_depth);
+                let x = self.builder.use_var(xvar);
+                let y = self.builder.use_var(yvar);
+                let z = self.builder.use_var(zvar);
+                let (x, y) = self.fold_polar(x, y, *count, *center, true);
+                self.builder.def_var(new_x, x);
+                self.builder.def_var(new_y, y);
+                self.builder.def_var(new_z, z);
+
+                self.translate_expr(target, new_x, new_y, new_z, transform_depth + 1)
+            }
+            Ast::Mirror {
+                target,
+                point: (px, py),
+                normal: (nx, ny),
+            } => {
+                let (new_x, new_y, new_z, _) = self.position_variables(transform_depth);
+                let x = self.builder.use_var(xvar);
+                let y = self.builder.use_var(yvar);
+                let z = self.builder.use_var(zvar);
+
+                let px = self.builder.ins().f32const(Ieee32::with_float(*px));
+                let py = self.builder.ins().f32const(Ieee32::with_float(*py));
+                let nx = self.builder.ins().f32const(Ieee32::with_float(*nx));
+                let ny = self.builder.ins().f32const(Ieee32::with_float(*ny));
+                let zero = self.builder.ins().f32const(Ieee32::with_float(0.0));
+                let two = self.builder.ins().f32const(Ieee32::with_float(2.0));
+
+                let dx = self.builder.ins().fsub(x, px);
+                let dy = self.builder.ins().fsub(y, py);
+                let dx = self.builder.ins().fmul(dx, nx);
+                let dy = self.builder.ins().fmul(dy, ny);
+                let behind = self.builder.ins().fadd(dx, dy);
+                let behind = self.builder.ins().fmin(behind, zero);
+                let behind = self.builder.ins().fmul(behind, two);
+
+                let ox = self.builder.ins().fmul(behind, nx);
+                let oy = self.builder.ins().fmul(behind, ny);
+                let x = self.builder.ins().fsub(x, ox);
+                let y = self.builder.ins().fsub(y, oy);
+
+                self.builder.def_var(new_x, x);
+                self.builder.def_var(new_y, y);
+                self.builder.def_var(new_z, z);
+
+                self.translate_expr(target, new_x, new_y, new_z, transform_depth + 1)
+            }
+            Ast::Twist {
+                target,
+                center: (cx, cy),
+                rate,
+            }
+            | Ast::Bend {
+                target,
+                center: (cx, cy),
+                rate,
+            }
+            | Ast::Taper {
+                target,
+                center: (cx, cy),
+                rate,
+            } => {
+                let (new_x, new_y, new_z, _) = self.position_variables(transform_depth);
+                let x = self.builder.use_var(xvar);
+                let y = self.builder.use_var(yvar);
+                let z = self.builder.use_var(zvar);
+
+                let cx = self.builder.ins().f32const(Ieee32::with_float(*cx));
+                let cy = self.builder.ins().f32const(Ieee32::with_float(*cy));
+                let rate = self.builder.ins().f32const(Ieee32::with_float(*rate));
+                let dx = self.builder.ins().fsub(x, cx);
+                let dy = self.builder.ins().fsub(y, cy);
+
+                let (x, y) = match expr {
+                    Ast::Taper { .. } => {
+                        let one = self.builder.ins().f32const(Ieee32::with_float(1.0));
+                        let scale = self.builder.ins().fmul(rate, dx);
+                        let scale = self.builder.ins().fadd(one, scale);
+                        let least = self
+                            .builder
+                            .ins()
+                            .f32const(Ieee32::with_float(::ast_walk::TAPER_MIN_SCALE));
+                        let scale = self.builder.ins().fmax(scale, least);
+                        let dy = self.builder.ins().fdiv(dy, scale);
+                        (x, self.builder.ins().fadd(cy, dy))
+                    }
+                    _ => {
+                        let along = match expr {
+                            Ast::Twist { .. } => {
+                                let dx2 = self.builder.ins().fmul(dx, dx);
+                                let dy2 = self.builder.ins().fmul(dy, dy);
+                                let r = self.builder.ins().fadd(dx2, dy2);
+                                self.builder.ins().sqrt(r)
+                            }
+                            _ => dx,
+                        };
+                        let angle = self.builder.ins().fmul(rate, along);
+                        let s = self.call_libm("sinf", &[angle]);
+                        let c = self.call_libm("cosf", &[angle]);
+
+                        let cdx = self.builder.ins().fmul(c, dx);
+                        let sdy = self.builder.ins().fmul(s, dy);
+                        let sdx = self.builder.ins().fmul(s, dx);
+                        let cdy = self.builder.ins().fmul(c, dy);
+                        let x = self.builder.ins().fadd(cdx, sdy);
+                        let y = self.builder.ins().fsub(cdy, sdx);
+                        (
+                            self.builder.ins().fadd(cx, x),
+                            self.builder.ins().fadd(cy, y),
+                        )
+                    }
+                };
+
+                self.builder.def_var(new_x, x);
+                self.builder.def_var(new_y, y);
+                self.builder.def_var(new_z, z);
+
+                self.translate_expr(target, new_x, new_y, new_z, transform_depth + 1)
+            }
             Ast::Buffer(_) | Ast::DistToPoly(_) => unimplemented!(),
         }
     }
@@ -302,6 +537,13 @@ fn max_depth_transforms(expr: AstPtr) -> usize {
             a.max(b)
         }
 
-        Ast::Transform { target, .. } => max_depth_transforms(target) + 1,
+        Ast::Transform { target, .. }
+        | Ast::RepeatGrid { target, .. }
+        | Ast::RepeatPolar { target, .. }
+        | Ast::Mirror { target, .. }
+        | Ast::Symmetry { target, .. }
+        | Ast::Twist { target, .. }
+        | Ast::Bend { target, .. }
+        | Ast::Taper { target, .. } => max_depth_transforms(target) + 1,
     }
 }
@@ -158,3 +158,74 @@ fn z_scale() {
     assert_eq!(run_test(ast, 1.0, 0.0, 1.0), 2.0);
     assert_eq!(run_test(ast, 0.0, 1.0, 1.0), 2.0);
 }
+
+#[test]
+fn repeat_grid() {
+    let ast = &Ast::RepeatGrid {
+        target: &Ast::Add(&[Ast::X, Ast::Y]),
+        spacing: (4.0, 2.0),
+        count: (3, 3),
+    };
+    assert_eq!(run_test(ast, 1.0, 0.5, 0.0), 1.5);
+    assert_eq!(run_test(ast, 9.0, 4.5, 0.0), 1.5);
+    assert_eq!(run_test(ast, 20.0, -1.0, 0.0), 11.0);
+}
+
+#[test]
+fn repeat_polar() {
+    let ast = &Ast::RepeatPolar {
+        target: &Ast::Y,
+        count: 4,
+        center: (1.0, 1.0),
+    };
+    assert!((run_test(ast, 1.0, 3.0, 0.0) - 1.0).abs() < 1e-5);
+    assert!((run_test(ast, -1.0, 1.0, 0.0) - 1.0).abs() < 1e-5);
+    assert!((run_test(ast, 2.0, 1.5, 0.0) - 1.5).abs() < 1e-5);
+}
+
+#[test]
+fn mirror() {
+    let ast = &Ast::Mirror {
+        target: &Ast::X,
+        point: (2.0, 0.0),
+        normal: (1.0, 0.0),
+    };
+    assert_eq!(run_test(ast, 3.0, 0.0, 0.0), 3.0);
+    assert_eq!(run_test(ast, 0.0, 5.0, 0.0), 4.0);
+}
+
+#[test]
+fn symmetry() {
+    let ast = &Ast::Symmetry {
+        target: &Ast::Y,
+        count: 4,
+        center: (0.0, 0.0),
+    };
+    assert!((run_test(ast, 1.0, -1.0, 0.0) - 1.0).abs() < 1e-5);
+    assert!((run_test(ast, 0.5, 2.0, 0.0) - 0.5).abs() < 1e-5);
+}
+
+#[test]
+fn twist_bend_and_taper() {
+    let quarter = ::std::f32::consts::PI / 2.0;
+    let twist = &Ast::Twist {
+        target: &Ast::X,
+        center: (0.0, 0.0),
+        rate: quarter,
+    };
+    assert!((run_test(twist, 0.0, 1.0, 0.0) - 1.0).abs() < 1e-5);
+
+    let bend = &Ast::Bend {
+        target: &Ast::Y,
+        center: (0.0, 0.0),
+        rate: quarter,
+    };
+    assert!((run_test(bend, 1.0, 0.0, 0.0) + 1.0).abs() < 1e-5);
+
+    let taper = &Ast::Taper {
+        target: &Ast::Y,
+        center: (1.0, 2.0),
+        rate: 2.0,
+    };
+    assert_eq!(run_test(taper, 2.0, 5.0, 0.0), 3.0);
+}
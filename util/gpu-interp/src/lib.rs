@@ -2,8 +2,8 @@ extern crate cranelift;
 extern crate cranelift_module;
 extern crate cranelift_simplejit;
 extern crate euclid;
-extern crate strategy;
 extern crate ocl;
+extern crate strategy;
 
 pub mod ast_walk;
 mod buffer;
@@ -12,28 +12,121 @@ pub mod jit;
 
 pub use buffer::*;
 
-pub type AstPtr<'a> = &'a Ast<'a>;
-pub type AstSlice<'a> = &'a [Ast<'a>];
+use euclid::num::{One, Zero};
+use std::fmt::Debug;
+
+pub type AstPtr<'a, V = f32> = &'a Ast<'a, V>;
+pub type AstSlice<'a, V = f32> = &'a [Ast<'a, V>];
+
+/// The numbers that an `Ast` is made of.  Most trees are made of `f32`s, but
+/// a tree made of `Operand`s can also read inputs, which are only given to
+/// it when it's run.
+pub trait Number: Copy + PartialEq + Debug + One + Zero {
+    fn operand(self) -> Operand;
+}
+
+/// A number that's either known when a tree is compiled, or is the input
+/// with the given index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Constant(f32),
+    Input(u32),
+}
+
+impl Number for f32 {
+    fn operand(self) -> Operand {
+        Operand::Constant(self)
+    }
+}
+
+impl Number for Operand {
+    fn operand(self) -> Operand {
+        self
+    }
+}
+
+impl One for Operand {
+    fn one() -> Operand {
+        Operand::Constant(1.0)
+    }
+}
+
+impl Zero for Operand {
+    fn zero() -> Operand {
+        Operand::Constant(0.0)
+    }
+}
 
 #[derive(Debug, Clone)]
-pub enum Ast<'a> {
+pub enum Ast<'a, V: Number = f32> {
     Buffer(Buffer),
-    Constant(f32),
+    Constant(V),
     X,
     Y,
     Z,
-    Add(AstSlice<'a>),
-    Mul(AstSlice<'a>),
-    Sub(AstPtr<'a>, AstPtr<'a>),
-    Max(AstSlice<'a>),
-    Min(AstSlice<'a>),
-    Abs(AstPtr<'a>),
-    Neg(AstPtr<'a>),
-    Sqrt(AstPtr<'a>),
-    Square(AstPtr<'a>),
-    DistToPoly(Vec<(f32, f32, f32, f32)>),
+    Add(AstSlice<'a, V>),
+    Mul(AstSlice<'a, V>),
+    Sub(AstPtr<'a, V>, AstPtr<'a, V>),
+    Max(AstSlice<'a, V>),
+    Min(AstSlice<'a, V>),
+    Abs(AstPtr<'a, V>),
+    Neg(AstPtr<'a, V>),
+    Sqrt(AstPtr<'a, V>),
+    Square(AstPtr<'a, V>),
+    DistToPoly(Vec<(V, V, V, V)>),
     Transform {
-        target: AstPtr<'a>,
-        matrix: euclid::Transform3D<f32>,
+        target: AstPtr<'a, V>,
+        matrix: euclid::Transform3D<V>,
+    },
+    // Folds the plane so that `target` is repeated `count` times along
+    // each axis, starting at the origin and `spacing` apart.
+    RepeatGrid {
+        target: AstPtr<'a, V>,
+        spacing: (V, V),
+        count: (u32, u32),
+    },
+    // Folds the plane into `count` wedges around `center` so that `target`
+    // is repeated once per wedge.
+    RepeatPolar {
+        target: AstPtr<'a, V>,
+        count: u32,
+        center: (V, V),
+    },
+    // Reflects the half-plane behind the line through `point` onto the
+    // half-plane that the unit length `normal` points into.  A zero `normal`
+    // leaves `target` as it is.
+    Mirror {
+        target: AstPtr<'a, V>,
+        point: (V, V),
+        normal: (V, V),
+    },
+    // Folds the plane into the wedge between the positive x axis and
+    // `PI / count` radians around `center`, giving `target` the symmetry of
+    // a regular `count`-gon.
+    Symmetry {
+        target: AstPtr<'a, V>,
+        count: u32,
+        center: (V, V),
+    },
+    // Rotates `target` around `center` by `rate` radians per unit of
+    // distance from `center`.
+    Twist {
+        target: AstPtr<'a, V>,
+        center: (V, V),
+        rate: V,
+    },
+    // Rotates `target` around `center` by `rate` radians per unit of
+    // distance along the x axis from `center`.
+    Bend {
+        target: AstPtr<'a, V>,
+        center: (V, V),
+        rate: V,
+    },
+    // Scales the height of `target` around `center` by `1 + rate * dx`, where
+    // `dx` is the distance along the x axis from `center`.
+    Taper {
+        target: AstPtr<'a, V>,
+        center: (V, V),
+        rate: V,
     },
 }
@@ -0,0 +1,74 @@
+use fnv::{FnvHashMap, FnvHashSet};
+use smallvec::SmallVec;
+use std::hash::Hash;
+use *;
+
+/// Joins lines into paths by the ids of their ends, following each line on
+/// to the one that starts where it ends.  Unlike the other passes, there is
+/// no epsilon: two ends meet if and only if they have the same id.
+///
+/// Every id should start at most one line and end at most one line, as the
+/// ids of grid edges from marching squares do.  `point` gives the position
+/// of each id.  Paths that end where they start are closed, and paths are
+/// returned in the order of their first lines.  Points that repeat the one
+/// before them are dropped, along with any paths that are left with too few
+/// points to draw.
+pub fn connect_indexed<I, F, S>(lines: &[(I, I)], point: F) -> Vec<PathSegment<S>>
+where
+    I: Copy + Eq + Hash,
+    F: Fn(I) -> Point<S>,
+{
+    let _guard = ::flame::start_guard("connect_indexed");
+    let starting_at: FnvHashMap<I, usize> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, &(from, _))| (from, i))
+        .collect();
+    let ends: FnvHashSet<I> = lines.iter().map(|&(_, to)| to).collect();
+
+    let mut used = vec![false; lines.len()];
+    let mut out = vec![];
+    // Open paths are walked first, from the lines that nothing leads into,
+    // so that none of them are started from the middle.  Everything that's
+    // left over is a loop.
+    for &open in &[true, false] {
+        for first in 0..lines.len() {
+            if used[first] || (open && ends.contains(&lines[first].0)) {
+                continue;
+            }
+
+            let start = lines[first].0;
+            let mut path: SmallVec<[Point<S>; 2]> = SmallVec::new();
+            path.push(point(start));
+            let mut closed = false;
+            let mut at = first;
+            loop {
+                used[at] = true;
+                let to = lines[at].1;
+                if to == start {
+                    closed = true;
+                    break;
+                }
+                let p = point(to);
+                if path.last() != Some(&p) {
+                    path.push(p);
+                }
+                match starting_at.get(&to) {
+                    Some(&next) if !used[next] => at = next,
+                    _ => break,
+                }
+            }
+
+            if closed && path.len() > 1 && path.first() == path.last() {
+                path.pop();
+            }
+            let shortest = if closed { 3 } else { 2 };
+            if path.len() >= shortest {
+                let mut segment = PathSegment::new(path);
+                segment.closed = closed;
+                out.push(segment);
+            }
+        }
+    }
+    out
+}
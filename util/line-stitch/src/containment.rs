@@ -0,0 +1,232 @@
+use euclid::{point2, TypedRect};
+use util::{
+    compute_bounding_box, index_margin, inside, is_clockwise, quad_tree_over, signed_area, Index,
+};
+use {PathSegment, Point};
+
+// Closed paths, by their index in order of size.
+type PathIndex<S> = Index<usize, S>;
+
+struct Closed<S> {
+    path: PathSegment<S>,
+    index: usize,
+    area: f32,
+    aabb: TypedRect<f32, S>,
+    interior: Point<S>,
+}
+
+/// A closed path, along with the closed paths that are directly inside of
+/// it.
+#[derive(Clone, Debug)]
+pub struct Contour<S> {
+    /// Wound clockwise (as `vectorphile` measures it) if this is an outline,
+    /// and counter-clockwise if it's a hole.
+    pub path: PathSegment<S>,
+    /// Where the path was in the paths that the tree was built from.
+    pub index: usize,
+    /// How many other paths this one is inside of.  Outlines have an even
+    /// depth, and holes an odd one.
+    pub depth: usize,
+    /// The paths directly inside of this one: holes in an outline, or
+    /// islands in a hole.
+    pub children: Vec<Contour<S>>,
+}
+
+impl<S> Contour<S> {
+    /// Whether this path is the inside of a hole, rather than of the shape.
+    pub fn is_hole(&self) -> bool {
+        self.depth % 2 == 1
+    }
+
+    /// The area inside of the path but outside of its children, which is
+    /// negative for holes.
+    pub fn area(&self) -> f32 {
+        signed_area(&self.path.path)
+            + self
+                .children
+                .iter()
+                .map(|c| signed_area(&c.path.path))
+                .sum::<f32>()
+    }
+
+    /// This contour and everything inside of it, outside in.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &Contour<S>> + '_> {
+        Box::new(::std::iter::once(self).chain(self.children.iter().flat_map(|c| c.iter())))
+    }
+}
+
+/// The closed paths of a drawing, arranged by which are inside of which, and
+/// the open paths that can't be inside of anything.
+#[derive(Clone, Debug)]
+pub struct ContainmentTree<S> {
+    /// The outermost outlines.
+    pub roots: Vec<Contour<S>>,
+    /// The open paths, as they were given.
+    pub open: Vec<PathSegment<S>>,
+}
+
+impl<S> ContainmentTree<S> {
+    /// Every closed path, each before the ones that are inside of it.
+    pub fn contours(&self) -> impl Iterator<Item = &Contour<S>> {
+        self.roots.iter().flat_map(|c| c.iter())
+    }
+
+    /// The outlines of the shape.
+    pub fn outlines(&self) -> impl Iterator<Item = &PathSegment<S>> {
+        self.contours().filter(|c| !c.is_hole()).map(|c| &c.path)
+    }
+
+    /// The holes in the shape.
+    pub fn holes(&self) -> impl Iterator<Item = &PathSegment<S>> {
+        self.contours().filter(|c| c.is_hole()).map(|c| &c.path)
+    }
+
+    /// The area inside of the outlines and outside of the holes.
+    pub fn area(&self) -> f32 {
+        self.contours().map(|c| signed_area(&c.path.path)).sum()
+    }
+}
+
+/// Arranges `paths` by which of their closed paths are inside of which, and
+/// winds each one to match: outlines clockwise and holes counter-clockwise,
+/// whichever way they were wound before.  Closed paths are expected not to
+/// cross each other, as the contours of a field never do.
+pub fn containment_tree<S>(paths: Vec<PathSegment<S>>) -> ContainmentTree<S> {
+    let _guard = ::flame::start_guard("containment_tree");
+    let (closed, open): (Vec<_>, Vec<_>) =
+        paths.into_iter().enumerate().partition(|(_, p)| p.closed);
+    let open: Vec<_> = open.into_iter().map(|(_, p)| p).collect();
+    if closed.is_empty() {
+        return ContainmentTree {
+            roots: vec![],
+            open,
+        };
+    }
+
+    // Parents are bigger than their children, so going from the biggest
+    // path to the smallest puts every path after anything it could be
+    // inside of.
+    let mut closed: Vec<Closed<S>> = closed
+        .into_iter()
+        .map(|(index, path)| {
+            let aabb = compute_bounding_box(path.path.iter().cloned());
+            Closed {
+                index,
+                area: signed_area(&path.path).abs(),
+                aabb,
+                interior: interior_point(&path.path, aabb),
+                path,
+            }
+        })
+        .collect();
+    closed.sort_by(|a, b| {
+        b.area
+            .partial_cmp(&a.area)
+            .unwrap_or(::std::cmp::Ordering::Equal)
+    });
+
+    let scene = closed
+        .iter()
+        .skip(1)
+        .fold(closed[0].aabb, |scene, c| scene.union(&c.aabb));
+    let margin = index_margin(scene);
+    let mut placed: PathIndex<S> = quad_tree_over(scene, margin, closed.len());
+
+    let mut parents: Vec<Option<usize>> = Vec::with_capacity(closed.len());
+    for (i, c) in closed.iter().enumerate() {
+        let aabb = c.aabb.inflate(margin, margin);
+        let parent = placed
+            .query(aabb)
+            .into_iter()
+            .map(|(&j, _, _)| j)
+            .filter(|&j| inside(c.interior, &closed[j].path.path))
+            // The smallest of the paths that contain this one is its parent.
+            .max();
+        parents.push(parent);
+        placed.insert_with_box(i, aabb);
+    }
+
+    let mut depths = vec![0; closed.len()];
+    let mut children: Vec<Vec<usize>> = vec![vec![]; closed.len()];
+    let mut roots = vec![];
+    for (i, &parent) in parents.iter().enumerate() {
+        match parent {
+            Some(parent) => {
+                depths[i] = depths[parent] + 1;
+                children[parent].push(i);
+            }
+            None => roots.push(i),
+        }
+    }
+
+    let mut paths: Vec<Option<(PathSegment<S>, usize)>> = closed
+        .into_iter()
+        .zip(&depths)
+        .map(|(c, &depth)| {
+            let Closed {
+                mut path, index, ..
+            } = c;
+            let is_outline = depth % 2 == 0;
+            if is_clockwise(&path.path) != is_outline {
+                path.path.reverse();
+            }
+            Some((path, index))
+        })
+        .collect();
+
+    fn build<S>(
+        i: usize,
+        paths: &mut [Option<(PathSegment<S>, usize)>],
+        depths: &[usize],
+        children: &[Vec<usize>],
+    ) -> Contour<S> {
+        let (path, index) = paths[i].take().unwrap();
+        Contour {
+            path,
+            index,
+            depth: depths[i],
+            children: children[i]
+                .iter()
+                .map(|&c| build(c, paths, depths, children))
+                .collect(),
+        }
+    }
+
+    ContainmentTree {
+        roots: roots
+            .into_iter()
+            .map(|i| build(i, &mut paths, &depths, &children))
+            .collect(),
+        open,
+    }
+}
+
+/// A point that's inside of the polygon with the corners `points`, and away
+/// from its sides: the middle of the widest span of a line across it.
+/// Paths that touch can share points and sides, but not anything inside of
+/// them, so whichever side of another path this point is on, all of the
+/// polygon is.
+fn interior_point<S>(points: &[Point<S>], aabb: TypedRect<f32, S>) -> Point<S> {
+    let mut best = (points[0], -1.0);
+    for &fraction in &[0.5, 0.37, 0.63, 0.21, 0.79] {
+        let y = aabb.origin.y + aabb.size.height * fraction;
+        if points.iter().any(|p| p.y == y) {
+            continue;
+        }
+        let mut crossings: Vec<f32> = vec![];
+        for (i, &a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            if (a.y > y) != (b.y > y) {
+                crossings.push(a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x));
+            }
+        }
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        for span in crossings.chunks(2).filter(|span| span.len() == 2) {
+            let width = span[1] - span[0];
+            if width > best.1 {
+                best = (point2((span[0] + span[1]) / 2.0, y), width);
+            }
+        }
+    }
+    best.0
+}
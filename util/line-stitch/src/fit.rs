@@ -0,0 +1,486 @@
+use euclid::TypedVector2D;
+use std::f32::consts::PI;
+use std::fmt;
+use util::distance_to_line;
+use {PathSegment, Point};
+
+type Vector<S> = TypedVector2D<f32, S>;
+
+/// Turns sharper than this (in radians) are kept as corners, and curves are
+/// only fitted between them.
+const CORNER_ANGLE: f32 = PI / 3.0;
+
+/// Arcs can't sweep all the way around, since they'd end where they start.
+const MAX_SWEEP: f32 = 2.0 * PI - 1e-3;
+
+/// How many times a cubic that is close to fitting has its points moved
+/// along it before it's split instead.
+const REPARAMETERIZATIONS: usize = 4;
+
+/// A piece of a fitted path, which runs from wherever the piece before it
+/// ended.
+#[derive(Clone, Copy)]
+pub enum Curve<S> {
+    /// A straight line to a point.
+    Line(Point<S>),
+    /// A cubic Bézier curve through two control points to an end point.
+    Cubic(Point<S>, Point<S>, Point<S>),
+    /// An arc of a circle to a point.
+    Arc {
+        /// The center of the circle.
+        center: Point<S>,
+        /// The radius of the circle.
+        radius: f32,
+        /// The angle that the arc turns through, in radians, which is
+        /// positive where the angle increases from the start to the end.
+        sweep: f32,
+        /// Where the arc ends.
+        to: Point<S>,
+    },
+}
+
+impl<S> Curve<S> {
+    /// Where the curve ends.
+    pub fn end(&self) -> Point<S> {
+        match *self {
+            Curve::Line(to) | Curve::Cubic(_, _, to) | Curve::Arc { to, .. } => to,
+        }
+    }
+}
+
+// Units don't need to be comparable or printable for points to be, so these
+// are written out rather than derived.
+impl<S> PartialEq for Curve<S> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Curve::Line(a), Curve::Line(b)) => a == b,
+            (Curve::Cubic(a1, a2, a), Curve::Cubic(b1, b2, b)) => a1 == b1 && a2 == b2 && a == b,
+            (
+                Curve::Arc {
+                    center: a_center,
+                    radius: a_radius,
+                    sweep: a_sweep,
+                    to: a,
+                },
+                Curve::Arc {
+                    center: b_center,
+                    radius: b_radius,
+                    sweep: b_sweep,
+                    to: b,
+                },
+            ) => a_center == b_center && a_radius == b_radius && a_sweep == b_sweep && a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<S> fmt::Debug for Curve<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Curve::Line(to) => f.debug_tuple("Line").field(to).finish(),
+            Curve::Cubic(c1, c2, to) => f
+                .debug_tuple("Cubic")
+                .field(c1)
+                .field(c2)
+                .field(to)
+                .finish(),
+            Curve::Arc {
+                center,
+                radius,
+                sweep,
+                to,
+            } => f
+                .debug_struct("Arc")
+                .field("center", center)
+                .field("radius", radius)
+                .field("sweep", sweep)
+                .field("to", to)
+                .finish(),
+        }
+    }
+}
+
+/// A path made of curves.
+#[derive(Clone)]
+pub struct CurvePath<S> {
+    /// Where the first curve starts.
+    pub start: Point<S>,
+    /// The curves, in order.
+    pub curves: Vec<Curve<S>>,
+    /// True if the last curve ends at `start`.
+    pub closed: bool,
+}
+
+impl<S> PartialEq for CurvePath<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.start == other.start && self.curves == other.curves && self.closed == other.closed
+    }
+}
+
+impl<S> fmt::Debug for CurvePath<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CurvePath")
+            .field("start", &self.start)
+            .field("curves", &self.curves)
+            .field("closed", &self.closed)
+            .finish()
+    }
+}
+
+/// Fits lines, circular arcs, and cubic Béziers to the points of `segment`,
+/// so that every point is within `tolerance` of the curves.  Sharp turns are
+/// kept as corners, and the curves between them meet smoothly.  A segment
+/// without any points gives a path without any curves, from the origin.
+pub fn fit_curves<S>(segment: &PathSegment<S>, tolerance: f32) -> CurvePath<S> {
+    let _guard = ::flame::start_guard("fit_curves");
+    let mut points = segment.path.to_vec();
+    points.dedup();
+    if segment.closed {
+        while points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+    }
+
+    let n = points.len();
+    let closed = segment.closed && n >= 2;
+    if n == 0 {
+        return CurvePath {
+            start: Point::origin(),
+            curves: vec![],
+            closed: false,
+        };
+    }
+    if n < 3 {
+        let mut curves: Vec<_> = points[1..].iter().map(|&p| Curve::Line(p)).collect();
+        if closed {
+            curves.push(Curve::Line(points[0]));
+        }
+        return CurvePath {
+            start: points[0],
+            curves,
+            closed,
+        };
+    }
+
+    // Paths are fitted in runs between corners.  A closed path without any
+    // is cut in half instead, since an arc can't end where it starts.
+    let at = |i: usize| points[i % n];
+    let is_corner = |i: usize| {
+        if !closed && (i == 0 || i == n - 1) {
+            return false;
+        }
+        let before = at(i + n - 1);
+        let after = at(i + 1);
+        let (a, b) = (direction(before, at(i)), direction(at(i), after));
+        a.cross(b).atan2(a.dot(b)).abs() > CORNER_ANGLE
+    };
+    let mut breaks: Vec<usize> = (0..n).filter(|&i| is_corner(i)).collect();
+    if closed {
+        if breaks.is_empty() {
+            breaks = vec![0, n / 2];
+        }
+    } else {
+        breaks.insert(0, 0);
+        breaks.push(n - 1);
+    }
+
+    // The direction that a run leaves each break in, if the path goes
+    // smoothly through it.
+    let smooth = |i: usize| {
+        if is_corner(i) || (!closed && (i == 0 || i == n - 1)) {
+            None
+        } else {
+            Some(direction(at(i + n - 1), at(i + 1)))
+        }
+    };
+
+    let runs = if closed {
+        breaks.len()
+    } else {
+        breaks.len() - 1
+    };
+    let mut curves = vec![];
+    for r in 0..runs {
+        let from = breaks[r];
+        let mut to = breaks[(r + 1) % breaks.len()];
+        if to <= from {
+            to += n;
+        }
+        let run: Vec<_> = (from..to + 1).map(&at).collect();
+        let last = run.len() - 1;
+        let leaving = smooth(from).unwrap_or_else(|| direction(run[0], run[1]));
+        let arriving = smooth(to % n)
+            .map(|t| -t)
+            .unwrap_or_else(|| direction(run[last], run[last - 1]));
+        fit_run(&run, leaving, arriving, tolerance, &mut curves);
+    }
+
+    CurvePath {
+        start: points[breaks[0]],
+        curves,
+        closed,
+    }
+}
+
+fn direction<S>(from: Point<S>, to: Point<S>) -> Vector<S> {
+    let v = to - from;
+    v / v.length()
+}
+
+/// Fits the curves from the first of `points` to the last, with the first
+/// curve leaving in the direction of `leaving` and the last one arriving
+/// from the direction of `arriving` (which points back along the path).
+fn fit_run<S>(
+    points: &[Point<S>],
+    leaving: Vector<S>,
+    arriving: Vector<S>,
+    tolerance: f32,
+    out: &mut Vec<Curve<S>>,
+) {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let off_chord = points
+        .iter()
+        .map(|&p| distance_to_line(p, first, last))
+        .fold(0.0, f32::max);
+    if points.len() == 2 || off_chord <= tolerance {
+        out.push(Curve::Line(last));
+        return;
+    }
+
+    if let Some((center, radius, sweep)) = fit_arc(points, tolerance) {
+        // Splitting a run puts the split wherever the cubic was furthest
+        // off, which can be the middle of an arc, so arcs that continue the
+        // one before them are joined back up.
+        if let Some(&mut Curve::Arc {
+            center: c,
+            radius: r,
+            sweep: ref mut s,
+            to: ref mut t,
+        }) = out.last_mut()
+        {
+            if (c - center).length() <= tolerance
+                && (r - radius).abs() <= tolerance
+                && *s * sweep > 0.0
+                && (*s + sweep).abs() < MAX_SWEEP
+            {
+                *s += sweep;
+                *t = last;
+                return;
+            }
+        }
+        out.push(Curve::Arc {
+            center,
+            radius,
+            sweep,
+            to: last,
+        });
+        return;
+    }
+
+    let params = chord_lengths(points);
+    let (bezier, error, split) = fit_cubic(points, params, leaving, arriving, tolerance);
+    if error <= tolerance {
+        out.push(Curve::Cubic(bezier[1], bezier[2], bezier[3]));
+        return;
+    }
+
+    let split = split.max(1).min(points.len() - 2);
+    let through = direction(points[split - 1], points[split + 1]);
+    fit_run(&points[..split + 1], leaving, -through, tolerance, out);
+    fit_run(&points[split..], through, arriving, tolerance, out);
+}
+
+/// The circle through `points` that's closest to them in the least squares
+/// sense, as long as every point is within `tolerance` of it and they go
+/// around it in one direction, less than once.  Returns the center, radius,
+/// and sweep of the arc from the first point to the last.
+fn fit_arc<S>(points: &[Point<S>], tolerance: f32) -> Option<(Point<S>, f32, f32)> {
+    // Kåsa's fit, solved relative to the middle of the points so that it
+    // doesn't lose precision far from the origin.
+    let n = points.len() as f64;
+    let (mx, my) = points.iter().fold((0.0, 0.0), |(x, y), p| {
+        (x + p.x as f64 / n, y + p.y as f64 / n)
+    });
+    let (mut sxx, mut sxy, mut syy, mut sxz, mut syz, mut sz) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for p in points {
+        let (x, y) = (p.x as f64 - mx, p.y as f64 - my);
+        let z = x * x + y * y;
+        sxx += x * x;
+        sxy += x * y;
+        syy += y * y;
+        sxz += x * z;
+        syz += y * z;
+        sz += z;
+    }
+    let det = sxx * syy - sxy * sxy;
+    if det.abs() <= f64::EPSILON * sxx * syy {
+        return None;
+    }
+    let d = -(sxz * syy - syz * sxy) / det;
+    let e = -(sxx * syz - sxy * sxz) / det;
+    let f = -sz / n;
+    let radius = (d * d / 4.0 + e * e / 4.0 - f).sqrt() as f32;
+    let center = Point::new((mx - d / 2.0) as f32, (my - e / 2.0) as f32);
+    if !radius.is_finite() {
+        return None;
+    }
+
+    if points
+        .iter()
+        .any(|&p| ((p - center).length() - radius).abs() > tolerance)
+    {
+        return None;
+    }
+
+    let angle = |p: Point<S>| (p.y - center.y).atan2(p.x - center.x);
+    let mut sweep = 0.0;
+    let mut turning = 0.0f32;
+    for pair in points.windows(2) {
+        let mut step = angle(pair[1]) - angle(pair[0]);
+        if step > PI {
+            step -= 2.0 * PI;
+        } else if step < -PI {
+            step += 2.0 * PI;
+        }
+        if step == 0.0 || step.abs() > PI / 2.0 || step * turning < 0.0 {
+            return None;
+        }
+        turning = step.signum();
+        sweep += step;
+    }
+    if sweep.abs() >= MAX_SWEEP {
+        return None;
+    }
+    Some((center, radius, sweep))
+}
+
+/// How far along the path each point is, from 0 to 1.
+fn chord_lengths<S>(points: &[Point<S>]) -> Vec<f32> {
+    let mut params = vec![0.0];
+    for pair in points.windows(2) {
+        let so_far = params[params.len() - 1];
+        params.push(so_far + (pair[1] - pair[0]).length());
+    }
+    let total = params[params.len() - 1];
+    for param in &mut params {
+        *param /= total;
+    }
+    params
+}
+
+fn bezier_at<S>(b: &[Point<S>; 4], t: f32) -> Point<S> {
+    let s = 1.0 - t;
+    let weights = [s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t];
+    let mut p = Vector::zero();
+    for (point, weight) in b.iter().zip(&weights) {
+        p += point.to_vector() * *weight;
+    }
+    p.to_point()
+}
+
+/// Schneider's algorithm: the cubic with the given end tangents that's
+/// closest to `points` at `params`, then with the parameters improved by
+/// Newton's method if it's nearly close enough.  Returns the curve, the
+/// furthest that a point is from it, and which point that is.
+fn fit_cubic<S>(
+    points: &[Point<S>],
+    mut params: Vec<f32>,
+    leaving: Vector<S>,
+    arriving: Vector<S>,
+    tolerance: f32,
+) -> ([Point<S>; 4], f32, usize) {
+    let mut bezier = cubic_for(points, &params, leaving, arriving);
+    let (mut error, mut split) = furthest(points, &params, &bezier);
+    if error > tolerance && error <= tolerance * 4.0 {
+        for _ in 0..REPARAMETERIZATIONS {
+            for (t, &p) in params.iter_mut().zip(points) {
+                *t = newton_step(&bezier, p, *t);
+            }
+            bezier = cubic_for(points, &params, leaving, arriving);
+            let (e, s) = furthest(points, &params, &bezier);
+            error = e;
+            split = s;
+            if error <= tolerance {
+                break;
+            }
+        }
+    }
+    (bezier, error, split)
+}
+
+fn cubic_for<S>(
+    points: &[Point<S>],
+    params: &[f32],
+    leaving: Vector<S>,
+    arriving: Vector<S>,
+) -> [Point<S>; 4] {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let (mut c00, mut c01, mut c11, mut x0, mut x1) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (&p, &t) in points.iter().zip(params) {
+        let s = 1.0 - t;
+        let a0 = leaving * (3.0 * s * s * t);
+        let a1 = arriving * (3.0 * s * t * t);
+        let fixed = first.to_vector() * (s * s * s + 3.0 * s * s * t)
+            + last.to_vector() * (3.0 * s * t * t + t * t * t);
+        let rest = p.to_vector() - fixed;
+        c00 += a0.dot(a0);
+        c01 += a0.dot(a1);
+        c11 += a1.dot(a1);
+        x0 += a0.dot(rest);
+        x1 += a1.dot(rest);
+    }
+
+    let length = (last - first).length();
+    let det = c00 * c11 - c01 * c01;
+    let (mut alpha0, mut alpha1) = if det != 0.0 {
+        ((x0 * c11 - x1 * c01) / det, (c00 * x1 - c01 * x0) / det)
+    } else {
+        (0.0, 0.0)
+    };
+    // Handles that are backwards or vanishingly short make loops and cusps,
+    // so they're replaced with a guess that's always reasonable.
+    if !(alpha0 > length * 1e-6 && alpha1 > length * 1e-6) {
+        alpha0 = length / 3.0;
+        alpha1 = length / 3.0;
+    }
+    [
+        first,
+        first + leaving * alpha0,
+        last + arriving * alpha1,
+        last,
+    ]
+}
+
+fn furthest<S>(points: &[Point<S>], params: &[f32], bezier: &[Point<S>; 4]) -> (f32, usize) {
+    points
+        .iter()
+        .zip(params)
+        .enumerate()
+        .map(|(i, (&p, &t))| ((bezier_at(bezier, t) - p).length(), i))
+        .fold((0.0, points.len() / 2), |best, next| {
+            if next.0 > best.0 {
+                next
+            } else {
+                best
+            }
+        })
+}
+
+/// Moves `t` towards the parameter of the point on the curve closest to `p`.
+fn newton_step<S>(b: &[Point<S>; 4], p: Point<S>, t: f32) -> f32 {
+    let s = 1.0 - t;
+    let d1 = [
+        (b[1] - b[0]) * 3.0,
+        (b[2] - b[1]) * 3.0,
+        (b[3] - b[2]) * 3.0,
+    ];
+    let d2 = [(d1[1] - d1[0]) * 2.0, (d1[2] - d1[1]) * 2.0];
+    let first = d1[0] * (s * s) + d1[1] * (2.0 * s * t) + d1[2] * (t * t);
+    let second = d2[0] * s + d2[1] * t;
+    let off = bezier_at(b, t) - p;
+    let denominator = first.dot(first) + off.dot(second);
+    if denominator == 0.0 {
+        return t;
+    }
+    (t - off.dot(first) / denominator).clamp(0.0, 1.0)
+}
@@ -0,0 +1,83 @@
+use euclid::point2;
+use std::cmp::Ordering;
+use std::f32::consts::FRAC_PI_2;
+use {PathSegment, Point};
+
+/// Lines `spacing` apart and `angle` radians from the x axis, across the
+/// inside of the closed paths in `paths`.  A point is inside if it's inside
+/// of an odd number of them, so holes (and islands in holes) are kept
+/// whichever way the paths wind.  Open paths are ignored.
+///
+/// The lines sit halfway between multiples of `spacing`, so hatching that's
+/// split between exports lines up, and every other line runs the other way
+/// so that a plotter can draw them back and forth.
+pub fn hatch<S>(paths: &[PathSegment<S>], angle: f32, spacing: f32) -> Vec<PathSegment<S>> {
+    let _guard = ::flame::start_guard("hatch");
+    if spacing <= 0.0 || !spacing.is_finite() {
+        return vec![];
+    }
+
+    // Each point as how far it is along the hatch lines (u) and across them
+    // (v).
+    let (sin, cos) = angle.sin_cos();
+    let to_hatch = |p: &Point<S>| (p.x * cos + p.y * sin, p.y * cos - p.x * sin);
+    let from_hatch = |u: f32, v: f32| point2(u * cos - v * sin, u * sin + v * cos);
+
+    let mut edges = vec![];
+    let (mut low, mut high) = (f32::INFINITY, f32::NEG_INFINITY);
+    for path in paths.iter().filter(|p| p.closed) {
+        let points: Vec<(f32, f32)> = path.path.iter().map(&to_hatch).collect();
+        for (i, &a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            low = low.min(a.1);
+            high = high.max(a.1);
+            edges.push((a, b));
+        }
+    }
+    if edges.is_empty() {
+        return vec![];
+    }
+
+    // Line k is at v = (k + 0.5) * spacing.
+    let row_of = |v: f32| (v / spacing - 0.5).ceil() as i64;
+    let first = row_of(low);
+    let count = (row_of(high) - first).max(0) as usize + 1;
+    let mut rows: Vec<Vec<f32>> = vec![vec![]; count];
+    for &((au, av), (bu, bv)) in &edges {
+        let (from, to) = if av < bv { (av, bv) } else { (bv, av) };
+        for k in row_of(from)..row_of(to) + 1 {
+            let v = (k as f32 + 0.5) * spacing;
+            // Each line counts an edge that it passes through an end of
+            // once, by only counting the end that's above it.
+            if (av > v) != (bv > v) {
+                rows[(k - first) as usize].push(au + (v - av) / (bv - av) * (bu - au));
+            }
+        }
+    }
+
+    let mut out = vec![];
+    for (i, crossings) in rows.iter_mut().enumerate() {
+        let v = ((first + i as i64) as f32 + 0.5) * spacing;
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let mut spans: Vec<PathSegment<S>> = crossings
+            .chunks(2)
+            .filter(|span| span.len() == 2 && span[1] > span[0])
+            .map(|span| PathSegment::new(vec![from_hatch(span[0], v), from_hatch(span[1], v)]))
+            .collect();
+        if i % 2 == 1 {
+            spans.reverse();
+            for span in &mut spans {
+                span.path.reverse();
+            }
+        }
+        out.extend(spans);
+    }
+    out
+}
+
+/// Hatching at `angle`, and again at right angles to it.
+pub fn cross_hatch<S>(paths: &[PathSegment<S>], angle: f32, spacing: f32) -> Vec<PathSegment<S>> {
+    let mut out = hatch(paths, angle, spacing);
+    out.extend(hatch(paths, angle + FRAC_PI_2, spacing));
+    out
+}
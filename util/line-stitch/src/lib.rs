@@ -11,23 +11,35 @@ extern crate permutohedron;
 extern crate rayon;
 extern crate smallvec;
 
+mod connect_indexed;
 mod connect_obvious;
+mod containment;
 mod dual_quad_tree;
+mod fit;
 mod graph_stitch;
+mod hatch;
 mod prune;
+mod simplify;
 mod test;
+mod travel;
 pub(crate) mod util;
 mod zero_area_loop;
 
 use aabb_quadtree::*;
+pub use connect_indexed::connect_indexed;
 pub use connect_obvious::{connect_obvious, connect_obvious_from_dual_qt};
+pub use containment::{containment_tree, ContainmentTree, Contour};
 use dual_quad_tree::*;
+pub use fit::{fit_curves, Curve, CurvePath};
 pub use graph_stitch::connect_unconnected as graph_stitch;
+pub use hatch::{cross_hatch, hatch};
 pub use prune::prune;
+pub use simplify::{simplify, Simplification, SimplifyMethod};
 use smallvec::SmallVec;
 use std::cell::Cell;
 use std::cmp::Ordering;
 use std::iter::{FromIterator, IntoIterator};
+pub use travel::{order_for_travel, travel_distance, TravelReport};
 pub use zero_area_loop::remove_zero_area_loops;
 
 type Point<S> = euclid::TypedPoint2D<f32, S>;
@@ -0,0 +1,444 @@
+use euclid::TypedRect;
+use fnv::FnvHashMap as HashMap;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use util::{compute_bounding_box, distance_to_line, index_margin, inside, quad_tree_over, Index};
+use {PathSegment, Point};
+
+// Lines, named by the path that they're in and the point that they start at.
+type LineIndex<S> = Index<(usize, usize), S>;
+
+/// Which algorithm paths are simplified with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimplifyMethod {
+    /// Keeps the points that are further than the tolerance from the line
+    /// between the points that are kept on either side of them.
+    DouglasPeucker,
+    /// Removes points in order of the area of the triangle that they make
+    /// with their neighbours, until none of those triangles are smaller than
+    /// the tolerance.
+    VisvalingamWhyatt,
+}
+
+/// How paths are simplified.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Simplification {
+    /// The algorithm to simplify with.
+    pub method: SimplifyMethod,
+    /// A distance for `DouglasPeucker`, or an area for `VisvalingamWhyatt`.
+    pub tolerance: f32,
+    /// Keeps any point whose removal would make a path cross itself or
+    /// another path, or move past a point of another path.  This is slower,
+    /// since every removal is checked against the lines around it.
+    pub preserve_topology: bool,
+}
+
+/// Removes the points of `paths` that don't contribute to their shape.  The
+/// ends of open paths are always kept, as are three points of closed ones.
+/// Paths with fewer than two points are returned as they are.
+pub fn simplify<S>(
+    paths: Vec<PathSegment<S>>,
+    simplification: &Simplification,
+) -> Vec<PathSegment<S>> {
+    let _guard = ::flame::start_guard("simplify");
+    let Simplification {
+        method,
+        tolerance,
+        preserve_topology,
+    } = *simplification;
+
+    // Paths without any lines have nothing to simplify, so they're set aside
+    // and put back where they were afterwards.
+    let (short, long): (Vec<_>, Vec<_>) = paths
+        .into_iter()
+        .enumerate()
+        .partition(|(_, segment)| segment.path.len() < 2);
+    let (order, paths): (Vec<usize>, Vec<PathSegment<S>>) = long.into_iter().unzip();
+
+    let kept = match method {
+        SimplifyMethod::DouglasPeucker => douglas_peucker(&paths, tolerance, preserve_topology),
+        SimplifyMethod::VisvalingamWhyatt => {
+            visvalingam_whyatt(&paths, tolerance, preserve_topology)
+        }
+    };
+
+    let mut simplified: Vec<_> = paths
+        .into_iter()
+        .zip(kept)
+        .map(|(segment, keep)| {
+            let points: Vec<_> = segment
+                .path
+                .iter()
+                .zip(keep)
+                .filter(|&(_, keep)| keep)
+                .map(|(&p, _)| p)
+                .collect();
+            let mut simplified = PathSegment::new(points);
+            simplified.closed = segment.closed;
+            simplified
+        })
+        .zip(order)
+        .map(|(segment, i)| (i, segment))
+        .chain(short)
+        .collect();
+    simplified.sort_by_key(|&(i, _)| i);
+    simplified.into_iter().map(|(_, segment)| segment).collect()
+}
+
+fn douglas_peucker<S>(
+    paths: &[PathSegment<S>],
+    tolerance: f32,
+    preserve_topology: bool,
+) -> Vec<Vec<bool>> {
+    // Closed paths start again from the point that's furthest from their
+    // first one, since it has to be kept and that one is sure to be a corner.
+    // They get that point again at the end, so that every line of every path
+    // starts at its own index.
+    let starts: Vec<usize> = paths
+        .iter()
+        .map(|segment| {
+            if segment.closed {
+                furthest(&segment.path, |q| (q - segment.path[0]).length()).0
+            } else {
+                0
+            }
+        })
+        .collect();
+    let rings: Vec<Vec<Point<S>>> = paths
+        .iter()
+        .zip(&starts)
+        .map(|(segment, &start)| {
+            let mut ring = segment.path[start..].to_vec();
+            ring.extend_from_slice(&segment.path[..start]);
+            if segment.closed {
+                ring.push(ring[0]);
+            }
+            ring
+        })
+        .collect();
+    let originals = if preserve_topology {
+        Some(Originals::new(&rings))
+    } else {
+        None
+    };
+
+    let mut out = vec![];
+    for (p, ring) in rings.iter().enumerate() {
+        let last = ring.len() - 1;
+        let mut keep = vec![false; ring.len()];
+        keep[0] = true;
+        keep[last] = true;
+
+        // A closed path starts and ends at the same point, which says
+        // nothing about where the rest of it is, so it's split at the point
+        // that's furthest from there first.
+        let mut spans = vec![(0, last)];
+        if paths[p].closed && last > 1 {
+            let far = furthest(&ring[1..last], |q| (q - ring[0]).length()).0 + 1;
+            keep[far] = true;
+            spans = vec![(0, far), (far, last)];
+        }
+
+        while let Some((from, to)) = spans.pop() {
+            if to - from < 2 {
+                continue;
+            }
+            let (a, b) = (ring[from], ring[to]);
+            let (k, distance) = furthest(&ring[from + 1..to], |q| distance_to_line(q, a, b));
+            let k = k + from + 1;
+            let fits = distance <= tolerance
+                && match originals {
+                    Some(ref originals) => originals.allow(p, from, to),
+                    None => true,
+                };
+            if !fits {
+                keep[k] = true;
+                spans.push((from, k));
+                spans.push((k, to));
+            }
+        }
+
+        if paths[p].closed {
+            keep.pop();
+            if keep.iter().filter(|&&k| k).count() < 3 && ring.len() > 3 {
+                // Both halves were straight, so the third point is wherever
+                // the path is furthest from them.
+                let far = keep.iter().rposition(|&k| k).unwrap();
+                let (k, _) = furthest(&ring[..last], |q| distance_to_line(q, ring[0], ring[far]));
+                keep[k] = true;
+            }
+        }
+        keep.rotate_right(starts[p]);
+        out.push(keep);
+    }
+    out
+}
+
+/// The index of the point that `measure` is largest for, and how large.
+fn furthest<S, F>(points: &[Point<S>], measure: F) -> (usize, f32)
+where
+    F: Fn(Point<S>) -> f32,
+{
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| (i, measure(p)))
+        .fold(
+            (0, -1.0),
+            |best, next| if next.1 > best.1 { next } else { best },
+        )
+}
+
+/// The lines of the paths before they're simplified.  A run of a path can
+/// be replaced by a straight line if the line crosses none of the original
+/// lines, and none of their points are inside of the shape that the run
+/// makes with it.  Nothing else could pass between the run and its line
+/// without crossing one or the other, so the simplified lines can't cross
+/// each other either.
+struct Originals<'a, S: 'a> {
+    rings: &'a [Vec<Point<S>>],
+    lines: LineIndex<S>,
+    margin: f32,
+}
+
+impl<'a, S> Originals<'a, S> {
+    fn new(rings: &'a [Vec<Point<S>>]) -> Originals<'a, S> {
+        let (lines, margin) = line_index(rings.iter().flat_map(|ring| ring.iter().cloned()));
+        let mut originals = Originals {
+            rings,
+            lines,
+            margin,
+        };
+        for (p, ring) in rings.iter().enumerate() {
+            for s in 0..ring.len() - 1 {
+                let aabb = bounds(&ring[s..s + 2], margin);
+                originals.lines.insert_with_box((p, s), aabb);
+            }
+        }
+        originals
+    }
+
+    fn allow(&self, p: usize, from: usize, to: usize) -> bool {
+        let run = &self.rings[p][from..to + 1];
+        let (a, b) = (run[0], run[run.len() - 1]);
+        for (&(q, s), _, _) in self.lines.query(bounds(run, self.margin)) {
+            if q == p && from <= s && s < to {
+                continue;
+            }
+            let (c, d) = (self.rings[q][s], self.rings[q][s + 1]);
+            if lines_cross(a, b, c, d) {
+                return false;
+            }
+            for &point in &[c, d] {
+                if point != a && point != b && inside(point, run) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+// The index is sized to hold every point, and its boxes are padded a little
+// so that horizontal and vertical lines are still found.
+fn line_index<S, I>(points: I) -> (LineIndex<S>, f32)
+where
+    I: IntoIterator<Item = Point<S>>,
+{
+    let scene = compute_bounding_box(points);
+    let margin = index_margin(scene);
+    (quad_tree_over(scene, margin, 0), margin)
+}
+
+fn bounds<S>(points: &[Point<S>], margin: f32) -> TypedRect<f32, S> {
+    compute_bounding_box(points.iter().cloned()).inflate(margin, margin)
+}
+
+fn cross<S>(a: Point<S>, b: Point<S>, c: Point<S>) -> f32 {
+    (b - a).cross(c - a)
+}
+
+fn on_line<S>(p: Point<S>, a: Point<S>, b: Point<S>) -> bool {
+    cross(a, b, p) == 0.0
+        && p.x >= a.x.min(b.x)
+        && p.x <= a.x.max(b.x)
+        && p.y >= a.y.min(b.y)
+        && p.y <= a.y.max(b.y)
+}
+
+/// Whether the lines from `a` to `b` and from `c` to `d` meet anywhere but
+/// at an end that they share.
+fn lines_cross<S>(a: Point<S>, b: Point<S>, c: Point<S>, d: Point<S>) -> bool {
+    let touches =
+        |p: Point<S>, from: Point<S>, to: Point<S>| p != from && p != to && on_line(p, from, to);
+    if a == c || a == d || b == c || b == d {
+        // Lines that share an end only cross if they overlap.
+        return touches(c, a, b) || touches(d, a, b) || touches(a, c, d) || touches(b, c, d);
+    }
+    let (o1, o2) = (cross(a, b, c), cross(a, b, d));
+    let (o3, o4) = (cross(c, d, a), cross(c, d, b));
+    (o1 * o2 < 0.0 && o3 * o4 < 0.0)
+        || on_line(c, a, b)
+        || on_line(d, a, b)
+        || on_line(a, c, d)
+        || on_line(b, c, d)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    area: f32,
+    path: usize,
+    point: usize,
+}
+
+impl Eq for Candidate {}
+
+// The heap pops the largest first, so smaller areas are ordered after
+// larger ones.  Ties go to the earliest point, so the result doesn't
+// depend on anything but the input.
+impl Ord for Candidate {
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        other
+            .area
+            .partial_cmp(&self.area)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| (other.path, other.point).cmp(&(self.path, self.point)))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn visvalingam_whyatt<S>(
+    paths: &[PathSegment<S>],
+    tolerance: f32,
+    preserve_topology: bool,
+) -> Vec<Vec<bool>> {
+    let points: Vec<&[Point<S>]> = paths.iter().map(|segment| &segment.path[..]).collect();
+    let mut keep: Vec<Vec<bool>> = points.iter().map(|path| vec![true; path.len()]).collect();
+    let mut counts: Vec<usize> = points.iter().map(|path| path.len()).collect();
+    let mut prev: Vec<Vec<usize>> = vec![];
+    let mut next: Vec<Vec<usize>> = vec![];
+    for (segment, path) in paths.iter().zip(&points) {
+        let n = path.len();
+        if segment.closed {
+            prev.push((0..n).map(|i| (i + n - 1) % n).collect());
+            next.push((0..n).map(|i| (i + 1) % n).collect());
+        } else {
+            prev.push((0..n).map(|i| i.saturating_sub(1)).collect());
+            next.push((0..n).map(|i| (i + 1).min(n - 1)).collect());
+        }
+    }
+
+    // The lines as they are now, for checking removals against.
+    let mut lines = None;
+    let mut ids = HashMap::default();
+    if preserve_topology {
+        let (mut index, margin) = line_index(points.iter().flat_map(|path| path.iter().cloned()));
+        for (p, path) in points.iter().enumerate() {
+            for s in 0..path.len() {
+                if next[p][s] != s {
+                    let aabb = bounds(&[path[s], path[next[p][s]]], margin);
+                    ids.insert((p, s), index.insert_with_box((p, s), aabb).unwrap());
+                }
+            }
+        }
+        lines = Some((index, margin));
+    }
+
+    let removable = |p: usize, i: usize, count: usize| {
+        if paths[p].closed {
+            count > 3
+        } else {
+            i != 0 && i != points[p].len() - 1 && count > 2
+        }
+    };
+    let area = |p: usize, a: usize, i: usize, c: usize| {
+        cross(points[p][a], points[p][i], points[p][c]).abs() / 2.0
+    };
+
+    let mut areas: Vec<Vec<f32>> = vec![];
+    let mut heap = BinaryHeap::new();
+    for p in 0..points.len() {
+        areas.push(
+            (0..points[p].len())
+                .map(|i| area(p, prev[p][i], i, next[p][i]))
+                .collect(),
+        );
+        for (i, &area) in areas[p].iter().enumerate() {
+            if removable(p, i, counts[p]) {
+                heap.push(Candidate {
+                    area,
+                    path: p,
+                    point: i,
+                });
+            }
+        }
+    }
+
+    while let Some(Candidate {
+        area: smallest,
+        path: p,
+        point: i,
+    }) = heap.pop()
+    {
+        if !keep[p][i] || smallest != areas[p][i] {
+            continue;
+        }
+        if smallest >= tolerance {
+            break;
+        }
+        if !removable(p, i, counts[p]) {
+            continue;
+        }
+        let (a, c) = (prev[p][i], next[p][i]);
+        if let Some((ref index, _)) = lines {
+            let triangle = [points[p][a], points[p][i], points[p][c]];
+            let blocked = index
+                .query(bounds(&triangle, 0.0))
+                .into_iter()
+                .any(|(&(q, s), _, _)| {
+                    if q == p && (s == a || s == i) {
+                        return false;
+                    }
+                    let (x, y) = (points[q][s], points[q][next[q][s]]);
+                    lines_cross(triangle[0], triangle[2], x, y)
+                        || [x, y]
+                            .iter()
+                            .any(|&v| !triangle.contains(&v) && inside(v, &triangle))
+                });
+            // It's checked again if either of its neighbours is removed.
+            if blocked {
+                continue;
+            }
+        }
+
+        keep[p][i] = false;
+        counts[p] -= 1;
+        next[p][a] = c;
+        prev[p][c] = a;
+        if let Some((ref mut index, margin)) = lines {
+            index.remove(ids.remove(&(p, i)).unwrap());
+            index.remove(ids[&(p, a)]);
+            let aabb = bounds(&[points[p][a], points[p][c]], margin);
+            ids.insert((p, a), index.insert_with_box((p, a), aabb).unwrap());
+        }
+
+        // Areas never shrink as points are removed, so that a point isn't
+        // removed before the ones that were less important than it.
+        for &j in &[a, c] {
+            if removable(p, j, counts[p]) {
+                areas[p][j] = area(p, prev[p][j], j, next[p][j]).max(smallest);
+                heap.push(Candidate {
+                    area: areas[p][j],
+                    path: p,
+                    point: j,
+                });
+            }
+        }
+    }
+    keep
+}
@@ -0,0 +1,129 @@
+use super::util::*;
+use connect_indexed;
+use euclid::{point2, UnknownUnit};
+use permutohedron::heap_recursive as permute;
+
+type Point = ::Point<UnknownUnit>;
+type PathSegment = ::PathSegment<UnknownUnit>;
+
+// Ids are indices into `points`.
+fn run(mut lines: Vec<(usize, usize)>, points: &[Point], expected: &[PathSegment]) {
+    permute(&mut lines, |lines| {
+        let output = connect_indexed(lines, |id| points[id]);
+        assert_same(&output, expected, false).unwrap();
+    });
+}
+
+#[test]
+fn no_lines() {
+    run(vec![], &[], &[]);
+}
+
+#[test]
+fn one_line() {
+    let points = [point2(0.0, 0.0), point2(1.0, 0.0)];
+    run(
+        vec![(0, 1)],
+        &points,
+        &[PathSegment::new(vec![points[0], points[1]])],
+    );
+}
+
+#[test]
+fn open_chain_starts_at_its_start() {
+    let points = [
+        point2(0.0, 0.0),
+        point2(1.0, 0.0),
+        point2(2.0, 0.0),
+        point2(3.0, 0.0),
+    ];
+    run(
+        vec![(0, 1), (1, 2), (2, 3)],
+        &points,
+        &[PathSegment::new(points.to_vec())],
+    );
+}
+
+#[test]
+fn square_is_closed() {
+    let points = [
+        point2(0.0, 0.0),
+        point2(1.0, 0.0),
+        point2(1.0, 1.0),
+        point2(0.0, 1.0),
+    ];
+    run(
+        vec![(0, 1), (1, 2), (2, 3), (3, 0)],
+        &points,
+        &[closed(points.to_vec())],
+    );
+}
+
+#[test]
+fn touching_loops_stay_apart() {
+    // Two squares whose corners are at the same position, but on different
+    // edges, the way the saddle cases of marching squares come out.  Epsilon
+    // matching would be free to join them into one path.
+    let points = [
+        point2(0.0, 0.0),
+        point2(1.0, 0.0),
+        point2(1.0, 1.0),
+        point2(0.0, 1.0),
+        point2(1.0, 1.0),
+        point2(2.0, 1.0),
+        point2(2.0, 2.0),
+        point2(1.0, 2.0),
+    ];
+    run(
+        vec![
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 0),
+            (4, 5),
+            (5, 6),
+            (6, 7),
+            (7, 4),
+        ],
+        &points,
+        &[closed(points[..4].to_vec()), closed(points[4..].to_vec())],
+    );
+}
+
+#[test]
+fn loops_and_chains_together() {
+    let points = [
+        point2(0.0, 0.0),
+        point2(1.0, 0.0),
+        point2(1.0, 1.0),
+        point2(5.0, 5.0),
+        point2(6.0, 5.0),
+    ];
+    run(
+        vec![(0, 1), (3, 4), (1, 2), (2, 0)],
+        &points,
+        &[
+            closed(points[..3].to_vec()),
+            PathSegment::new(points[3..].to_vec()),
+        ],
+    );
+}
+
+#[test]
+fn repeated_points_are_dropped() {
+    // Ids 1 and 2 are at the same position, as when two grid edges are
+    // crossed at the sample between them.
+    let points = [
+        point2(0.0, 0.0),
+        point2(1.0, 0.0),
+        point2(1.0, 0.0),
+        point2(1.0, 1.0),
+        point2(0.0, 0.0),
+    ];
+    run(
+        vec![(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)],
+        &points,
+        &[closed(vec![points[0], points[1], points[3]])],
+    );
+    run(vec![(1, 2)], &points, &[]);
+}
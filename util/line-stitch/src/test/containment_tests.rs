@@ -0,0 +1,71 @@
+use super::util::square;
+use containment_tree;
+use euclid::{point2, UnknownUnit};
+use util::is_clockwise;
+
+type PathSegment = ::PathSegment<UnknownUnit>;
+
+#[test]
+fn nested_squares_alternate_between_outlines_and_holes() {
+    let line = PathSegment::new(vec![point2(-5.0, -5.0), point2(-1.0, -1.0)]);
+    // Given smallest first, and with every one wound the wrong way for at
+    // least one of the rules that only looks at winding.
+    let tree = containment_tree(vec![
+        square(4.0, 4.0, 2.0, false),
+        line.clone(),
+        square(2.0, 2.0, 6.0, true),
+        square(20.0, 0.0, 5.0, false),
+        square(0.0, 0.0, 10.0, false),
+    ]);
+
+    assert_eq!(tree.open, vec![line]);
+    assert_eq!(tree.roots.len(), 2);
+    let outer = &tree.roots[0];
+    assert_eq!(outer.depth, 0);
+    assert_eq!(outer.children.len(), 1);
+    let hole = &outer.children[0];
+    assert!(hole.is_hole());
+    assert_eq!(hole.children.len(), 1);
+    let island = &hole.children[0];
+    assert_eq!(island.depth, 2);
+    assert!(island.children.is_empty());
+    assert!(tree.roots[1].children.is_empty());
+    assert_eq!(outer.index, 4);
+    assert_eq!(hole.index, 2);
+    assert_eq!(island.index, 0);
+    assert_eq!(tree.roots[1].index, 3);
+
+    for contour in tree.contours() {
+        assert_eq!(is_clockwise(&contour.path.path), !contour.is_hole());
+    }
+    assert_eq!(tree.outlines().count(), 3);
+    assert_eq!(tree.holes().count(), 1);
+
+    assert_eq!(outer.area(), 100.0 - 36.0);
+    assert_eq!(hole.area(), -(36.0 - 4.0));
+    assert_eq!(tree.area(), 100.0 - 36.0 + 4.0 + 25.0);
+}
+
+#[test]
+fn paths_that_touch_are_still_nested() {
+    // The hole shares a corner and part of a side with its outline.
+    let tree = containment_tree(vec![
+        square(0.0, 0.0, 10.0, true),
+        square(0.0, 0.0, 5.0, true),
+    ]);
+    assert_eq!(tree.roots.len(), 1);
+    assert_eq!(tree.roots[0].children.len(), 1);
+    assert!(!is_clockwise(&tree.roots[0].children[0].path.path));
+    assert_eq!(tree.area(), 75.0);
+}
+
+#[test]
+fn side_by_side_paths_are_both_roots() {
+    let tree = containment_tree(vec![
+        square(0.0, 0.0, 1.0, false),
+        square(1.0, 0.0, 1.0, false),
+    ]);
+    assert_eq!(tree.roots.len(), 2);
+    assert!(tree.holes().next().is_none());
+    assert_eq!(tree.area(), 2.0);
+}
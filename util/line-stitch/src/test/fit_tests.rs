@@ -0,0 +1,206 @@
+use super::util::closed;
+use euclid::{point2, UnknownUnit};
+use fit_curves;
+use std::f32::consts::PI;
+
+type Point = ::Point<UnknownUnit>;
+type PathSegment = ::PathSegment<UnknownUnit>;
+type Curve = ::Curve<UnknownUnit>;
+type CurvePath = ::CurvePath<UnknownUnit>;
+
+fn circle(center: Point, radius: f32, count: usize) -> Vec<Point> {
+    (0..count)
+        .map(|i| {
+            let angle = i as f32 / count as f32 * 2.0 * PI;
+            point2(
+                center.x + radius * angle.cos(),
+                center.y + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+// Points along each curve, close enough together to measure against.
+fn flatten(path: &CurvePath) -> Vec<Point> {
+    let mut out = vec![path.start];
+    let mut at = path.start;
+    for curve in &path.curves {
+        for step in 1..201 {
+            let t = step as f32 / 200.0;
+            out.push(match *curve {
+                Curve::Line(to) => at.lerp(to, t),
+                Curve::Cubic(c1, c2, to) => {
+                    let s = 1.0 - t;
+                    (at.to_vector() * (s * s * s)
+                        + c1.to_vector() * (3.0 * s * s * t)
+                        + c2.to_vector() * (3.0 * s * t * t)
+                        + to.to_vector() * (t * t * t))
+                        .to_point()
+                }
+                Curve::Arc {
+                    center,
+                    radius,
+                    sweep,
+                    ..
+                } => {
+                    let start = (at.y - center.y).atan2(at.x - center.x);
+                    let angle = start + sweep * t;
+                    point2(
+                        center.x + radius * angle.cos(),
+                        center.y + radius * angle.sin(),
+                    )
+                }
+            });
+        }
+        at = curve.end();
+    }
+    out
+}
+
+fn distance_to_segment(p: Point, a: Point, b: Point) -> f32 {
+    let ab = b - a;
+    let t = if ab.square_length() == 0.0 {
+        0.0
+    } else {
+        (ab.dot(p - a) / ab.square_length()).clamp(0.0, 1.0)
+    };
+    (a + ab * t - p).length()
+}
+
+fn assert_within(path: &CurvePath, points: &[Point], tolerance: f32) {
+    let flat = flatten(path);
+    for p in points {
+        let off = flat
+            .windows(2)
+            .map(|f| distance_to_segment(*p, f[0], f[1]))
+            .fold(f32::INFINITY, f32::min);
+        assert!(
+            off <= tolerance * 1.1,
+            "{:?} is {} off of {:?}",
+            p,
+            off,
+            path
+        );
+    }
+    if path.closed {
+        assert!((path.curves.last().unwrap().end() - path.start).length() < 1e-3);
+    }
+}
+
+#[test]
+fn straight_lines_stay_lines() {
+    let points: Vec<_> = (0..10).map(|i| point2(i as f32, 2.0 * i as f32)).collect();
+    let fitted = fit_curves(&PathSegment::new(points), 0.01);
+    assert_eq!(fitted.start, point2(0.0, 0.0));
+    assert_eq!(fitted.curves, vec![Curve::Line(point2(9.0, 18.0))]);
+    assert!(!fitted.closed);
+}
+
+#[test]
+fn squares_keep_their_corners() {
+    let mut points = vec![];
+    for i in 0..4 {
+        points.push(point2(i as f32, 0.0));
+    }
+    for i in 0..4 {
+        points.push(point2(4.0, i as f32));
+    }
+    for i in 0..4 {
+        points.push(point2(4.0 - i as f32, 4.0));
+    }
+    for i in 0..4 {
+        points.push(point2(0.0, 4.0 - i as f32));
+    }
+    let fitted = fit_curves(&closed(points.clone()), 0.01);
+    assert!(fitted.closed);
+    assert_eq!(
+        fitted.curves,
+        vec![
+            Curve::Line(point2(4.0, 0.0)),
+            Curve::Line(point2(4.0, 4.0)),
+            Curve::Line(point2(0.0, 4.0)),
+            Curve::Line(point2(0.0, 0.0)),
+        ]
+    );
+    assert_within(&fitted, &points, 0.01);
+}
+
+#[test]
+fn circles_become_arcs() {
+    let points = circle(point2(30.0, -20.0), 10.0, 64);
+    let fitted = fit_curves(&closed(points.clone()), 0.01);
+    assert!(fitted.closed);
+    assert_eq!(fitted.curves.len(), 2);
+    let mut sweep = 0.0;
+    for curve in &fitted.curves {
+        match *curve {
+            Curve::Arc {
+                center,
+                radius,
+                sweep: s,
+                ..
+            } => {
+                assert!((center - point2(30.0, -20.0)).length() < 1e-3);
+                assert!((radius - 10.0).abs() < 1e-3);
+                sweep += s;
+            }
+            other => panic!("{:?} isn't an arc", other),
+        }
+    }
+    assert!((sweep - 2.0 * PI).abs() < 1e-3);
+    assert_within(&fitted, &points, 0.01);
+}
+
+#[test]
+fn waves_become_a_few_cubics() {
+    let points: Vec<_> = (0..200)
+        .map(|i| {
+            let x = i as f32 * 0.1;
+            point2(x, 3.0 * (x * 0.7).sin() + x * 0.2)
+        })
+        .collect();
+    let fitted = fit_curves(&PathSegment::new(points.clone()), 0.05);
+    assert!(!fitted.closed);
+    assert_eq!(fitted.curves.last().unwrap().end(), points[199]);
+    assert!(fitted.curves.len() < 20, "{:?}", fitted.curves);
+    assert_within(&fitted, &points, 0.05);
+}
+
+#[test]
+fn rounded_corners_fit_around_their_corners() {
+    // A quarter circle joined to two straight sides, with a sharp corner
+    // where the sides meet.
+    let mut points = vec![point2(0.0, 0.0), point2(5.0, 0.0)];
+    for i in 0..17 {
+        let angle = -PI / 2.0 + i as f32 / 16.0 * PI / 2.0;
+        points.push(point2(5.0 + 5.0 * angle.cos(), 5.0 + 5.0 * angle.sin()));
+    }
+    points.push(point2(10.0, 10.0));
+    points.push(point2(0.0, 10.0));
+    let fitted = fit_curves(&closed(points.clone()), 0.01);
+    assert!(fitted.closed);
+    assert!(fitted.curves.len() <= 6, "{:?}", fitted.curves);
+    assert!(fitted.curves.iter().any(|c| matches!(c, Curve::Arc { .. })));
+    assert_within(&fitted, &points, 0.01);
+}
+
+#[test]
+fn tiny_paths_are_lines() {
+    let fitted = fit_curves(
+        &PathSegment::new(vec![point2(1.0, 1.0), point2(1.0, 1.0), point2(2.0, 1.0)]),
+        0.1,
+    );
+    assert_eq!(fitted.curves, vec![Curve::Line(point2(2.0, 1.0))]);
+}
+
+#[test]
+fn empty_paths_have_no_curves() {
+    for &is_closed in &[false, true] {
+        let mut segment = PathSegment::new(vec![point2(0.0, 0.0), point2(1.0, 0.0)]);
+        segment.path.clear();
+        segment.closed = is_closed;
+        let fitted = fit_curves(&segment, 0.1);
+        assert!(fitted.curves.is_empty());
+        assert!(!fitted.closed);
+    }
+}
@@ -0,0 +1,75 @@
+use super::util::square;
+use euclid::{point2, UnknownUnit};
+use std::f32::consts::FRAC_PI_4;
+use {cross_hatch, hatch};
+
+type PathSegment = ::PathSegment<UnknownUnit>;
+
+fn total_length(paths: &[PathSegment]) -> f32 {
+    paths.iter().map(|p| p.length()).sum()
+}
+
+#[test]
+fn hatching_skips_holes() {
+    // The hole winds the same way as its outline, which doesn't matter.
+    let paths = vec![square(0.0, 0.0, 10.0, true), square(4.0, 4.0, 2.0, true)];
+    let lines = hatch(&paths, 0.0, 1.0);
+
+    // Ten rows, and the two through the hole are split in two.
+    assert_eq!(lines.len(), 12);
+    assert!((total_length(&lines) - (100.0 - 4.0)).abs() < 1e-4);
+    for line in &lines {
+        assert_eq!(line.path.len(), 2);
+        assert_eq!(line.path[0].y, line.path[1].y);
+        assert_eq!(line.path[0].y.fract(), 0.5);
+        let middle = line.path[0].lerp(line.path[1], 0.5);
+        assert!(!(middle.x > 4.0 && middle.x < 6.0 && middle.y > 4.0 && middle.y < 6.0));
+    }
+}
+
+#[test]
+fn hatch_lines_go_back_and_forth() {
+    let lines = hatch(&[square(0.0, 0.0, 4.0, false)], 0.0, 1.0);
+    assert_eq!(lines.len(), 4);
+    for (i, line) in lines.iter().enumerate() {
+        let rightwards = line.path[1].x > line.path[0].x;
+        assert_eq!(rightwards, i % 2 == 0);
+        // Each line starts near where the last one ended.
+        if i > 0 {
+            assert!((line.path[0] - lines[i - 1].path[1]).length() <= 1.0 + 1e-5);
+        }
+    }
+}
+
+#[test]
+fn angled_hatching_covers_the_area() {
+    let lines = hatch(&[square(0.0, 0.0, 20.0, true)], FRAC_PI_4, 0.5);
+    // Hatching with spacing s is about area / s long.
+    let expected = 400.0 / 0.5;
+    assert!((total_length(&lines) - expected).abs() < expected * 0.02);
+    for line in &lines {
+        let direction = line.path[1] - line.path[0];
+        assert!((direction.x.abs() - direction.y.abs()).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn cross_hatching_goes_both_ways() {
+    let paths = vec![square(0.0, 0.0, 4.0, true)];
+    let lines = cross_hatch(&paths, 0.0, 1.0);
+    assert_eq!(lines.len(), 8);
+    assert_eq!(
+        lines
+            .iter()
+            .filter(|l| (l.path[0].x - l.path[1].x).abs() < 1e-4)
+            .count(),
+        4
+    );
+}
+
+#[test]
+fn open_paths_and_bad_spacing_make_nothing() {
+    let line = PathSegment::new(vec![point2(0.0, 0.0), point2(10.0, 10.0)]);
+    assert!(hatch(&[line], 0.0, 1.0).is_empty());
+    assert!(hatch(&[square(0.0, 0.0, 4.0, true)], 0.0, 0.0).is_empty());
+}
@@ -1,8 +1,14 @@
 #![cfg(test)]
 
+mod connect_indexed_tests;
 mod connect_obvious_tests;
+mod containment_tests;
+mod fit_tests;
 mod graph_stitch_tests;
+mod hatch_tests;
 mod prune_tests;
 mod reg_tests;
+mod simplify_tests;
+mod travel_tests;
 mod util;
 mod zero_area_loop_tests;
@@ -0,0 +1,238 @@
+use super::util::closed;
+use euclid::{point2, UnknownUnit};
+use std::f32::consts::PI;
+use {simplify, Simplification, SimplifyMethod};
+
+type Point = ::Point<UnknownUnit>;
+type PathSegment = ::PathSegment<UnknownUnit>;
+
+const METHODS: [SimplifyMethod; 2] = [
+    SimplifyMethod::DouglasPeucker,
+    SimplifyMethod::VisvalingamWhyatt,
+];
+
+fn with(method: SimplifyMethod, tolerance: f32, preserve_topology: bool) -> Simplification {
+    Simplification {
+        method,
+        tolerance,
+        preserve_topology,
+    }
+}
+
+fn lines(segment: &PathSegment) -> Vec<(Point, Point)> {
+    let mut out: Vec<_> = segment.path.windows(2).map(|w| (w[0], w[1])).collect();
+    if segment.closed {
+        out.push((*segment.path.last().unwrap(), segment.path[0]));
+    }
+    out
+}
+
+fn cross(a: Point, b: Point, c: Point) -> f32 {
+    (b - a).cross(c - a)
+}
+
+fn assert_apart(a: &PathSegment, b: &PathSegment) {
+    for &(p1, p2) in &lines(a) {
+        for &(q1, q2) in &lines(b) {
+            let crossed = cross(p1, p2, q1) * cross(p1, p2, q2) < 0.0
+                && cross(q1, q2, p1) * cross(q1, q2, p2) < 0.0;
+            assert!(!crossed, "{:?}-{:?} crosses {:?}-{:?}", p1, p2, q1, q2);
+        }
+    }
+}
+
+// A circle with some deterministic wobble on it.
+fn wobbly_circle(radius: f32, wobble: f32, count: usize) -> Vec<Point> {
+    (0..count)
+        .map(|i| {
+            let angle = i as f32 / count as f32 * 2.0 * PI;
+            let r = radius + wobble * (angle * 17.0).sin() * (angle * 5.0).cos();
+            point2(r * angle.cos(), r * angle.sin())
+        })
+        .collect()
+}
+
+#[test]
+fn straight_runs_lose_their_middles() {
+    let points: Vec<_> = (0..10).map(|i| point2(i as f32, 0.0)).collect();
+    for &method in &METHODS {
+        let out = simplify(
+            vec![PathSegment::new(points.clone())],
+            &with(method, 0.01, false),
+        );
+        assert_eq!(out.len(), 1);
+        assert_eq!(&out[0].path[..], &[point2(0.0, 0.0), point2(9.0, 0.0)]);
+        assert!(!out[0].closed);
+    }
+}
+
+#[test]
+fn squares_keep_their_corners() {
+    let mut points = vec![];
+    for i in 0..4 {
+        points.push(point2(i as f32, 0.0));
+    }
+    for i in 0..4 {
+        points.push(point2(4.0, i as f32));
+    }
+    for i in 0..4 {
+        points.push(point2(4.0 - i as f32, 4.0));
+    }
+    for i in 0..4 {
+        points.push(point2(0.0, 4.0 - i as f32));
+    }
+    for &method in &METHODS {
+        let out = simplify(vec![closed(points.clone())], &with(method, 0.01, false));
+        assert!(out[0].closed);
+        assert_eq!(
+            &out[0].path[..],
+            &[
+                point2(0.0, 0.0),
+                point2(4.0, 0.0),
+                point2(4.0, 4.0),
+                point2(0.0, 4.0),
+            ]
+        );
+    }
+}
+
+#[test]
+fn closed_paths_can_start_partway_along_a_side() {
+    let points = vec![
+        point2(2.0, 0.0),
+        point2(4.0, 0.0),
+        point2(4.0, 4.0),
+        point2(0.0, 4.0),
+        point2(0.0, 0.0),
+    ];
+    for &method in &METHODS {
+        let out = simplify(vec![closed(points.clone())], &with(method, 0.01, false));
+        assert_eq!(&out[0].path[..], &points[1..]);
+    }
+}
+
+#[test]
+fn closed_paths_keep_three_points() {
+    let points = wobbly_circle(1.0, 0.0, 32);
+    for &method in &METHODS {
+        let out = simplify(vec![closed(points.clone())], &with(method, 100.0, false));
+        assert!(out[0].closed);
+        assert_eq!(out[0].path.len(), 3, "{:?}", out[0]);
+    }
+}
+
+#[test]
+fn douglas_peucker_stays_within_its_tolerance() {
+    let points: Vec<_> = (0..200)
+        .map(|i| {
+            let x = i as f32 * 0.1;
+            point2(x, 3.0 * (x * 0.7).sin())
+        })
+        .collect();
+    let out = simplify(
+        vec![PathSegment::new(points.clone())],
+        &with(SimplifyMethod::DouglasPeucker, 0.05, false),
+    );
+    assert!(out[0].path.len() < 40, "{}", out[0].path.len());
+    for p in &points {
+        let off = out[0]
+            .path
+            .windows(2)
+            .map(|w| {
+                let ab = w[1] - w[0];
+                let t = (ab.dot(*p - w[0]) / ab.square_length()).clamp(0.0, 1.0);
+                (w[0] + ab * t - *p).length()
+            })
+            .fold(f32::INFINITY, f32::min);
+        assert!(off <= 0.05 + 1e-5, "{:?} is {} off", p, off);
+    }
+}
+
+#[test]
+fn visvalingam_whyatt_removes_small_triangles() {
+    // The bump at x = 1 makes a triangle with an area of 0.1, and the one at
+    // x = 3 makes one of 1.
+    let path = PathSegment::new(vec![
+        point2(0.0, 0.0),
+        point2(1.0, 0.1),
+        point2(2.0, 0.0),
+        point2(3.0, 1.0),
+        point2(4.0, 0.0),
+    ]);
+    let out = simplify(
+        vec![path],
+        &with(SimplifyMethod::VisvalingamWhyatt, 0.5, false),
+    );
+    assert_eq!(
+        &out[0].path[..],
+        &[
+            point2(0.0, 0.0),
+            point2(2.0, 0.0),
+            point2(3.0, 1.0),
+            point2(4.0, 0.0),
+        ]
+    );
+}
+
+#[test]
+fn preserving_topology_keeps_paths_on_their_own_side() {
+    let bump = PathSegment::new(vec![point2(0.0, 0.0), point2(5.0, 2.0), point2(10.0, 0.0)]);
+    let under = PathSegment::new(vec![point2(4.0, 1.0), point2(6.0, 1.0)]);
+    for &(method, tolerance) in &[
+        (SimplifyMethod::DouglasPeucker, 3.0),
+        (SimplifyMethod::VisvalingamWhyatt, 20.0),
+    ] {
+        let loose = simplify(
+            vec![bump.clone(), under.clone()],
+            &with(method, tolerance, false),
+        );
+        assert_eq!(loose[0].path.len(), 2);
+
+        let kept = simplify(
+            vec![bump.clone(), under.clone()],
+            &with(method, tolerance, true),
+        );
+        assert_eq!(&kept[0].path[..], &bump.path[..]);
+        assert_eq!(&kept[1].path[..], &under.path[..]);
+    }
+}
+
+#[test]
+fn preserving_topology_keeps_close_loops_apart() {
+    let outer = closed(wobbly_circle(10.0, 0.3, 400));
+    let inner = closed(wobbly_circle(9.7, 0.3, 400));
+    for &(method, tolerance) in &[
+        (SimplifyMethod::DouglasPeucker, 1.0),
+        (SimplifyMethod::VisvalingamWhyatt, 1.0),
+    ] {
+        let out = simplify(
+            vec![outer.clone(), inner.clone()],
+            &with(method, tolerance, true),
+        );
+        assert!(out[0].path.len() < 400 && out[1].path.len() < 400);
+        assert_apart(&out[0], &out[1]);
+    }
+}
+
+#[test]
+fn paths_without_lines_are_left_alone() {
+    let square = closed(vec![
+        point2(0.0, 0.0),
+        point2(10.0, 0.0),
+        point2(10.0, 10.0),
+        point2(0.0, 10.0),
+    ]);
+    let mut empty = PathSegment::new(vec![point2(0.0, 0.0), point2(1.0, 0.0)]);
+    empty.path.clear();
+    let mut single = PathSegment::new(vec![point2(5.0, 5.0), point2(6.0, 5.0)]);
+    single.path.truncate(1);
+    single.closed = true;
+
+    for &method in &METHODS {
+        for &preserve in &[false, true] {
+            let paths = vec![empty.clone(), square.clone(), single.clone()];
+            let out = simplify(paths.clone(), &with(method, 1.0, preserve));
+            assert_eq!(out, paths);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use super::util::square;
+use euclid::{point2, UnknownUnit};
+use {order_for_travel, travel_distance};
+
+type Point = ::Point<UnknownUnit>;
+type PathSegment = ::PathSegment<UnknownUnit>;
+
+fn line(a: (f32, f32), b: (f32, f32)) -> PathSegment {
+    PathSegment::new(vec![point2(a.0, a.1), point2(b.0, b.1)])
+}
+
+// The same path, whichever end or point it starts from.
+fn same_path(a: &PathSegment, b: &PathSegment) -> bool {
+    let n = a.path.len();
+    if a.closed != b.closed || n != b.path.len() {
+        return false;
+    }
+    if !a.closed {
+        let reversed: Vec<Point> = b.path.iter().rev().cloned().collect();
+        return a.path[..] == b.path[..] || a.path[..] == reversed[..];
+    }
+    (0..n).any(|r| (0..n).all(|i| a.path[i] == b.path[(i + r) % n]))
+}
+
+#[test]
+fn open_paths_are_reversed_to_start_at_the_near_end() {
+    let start = point2(0.0, 0.0);
+    let paths = vec![line((10.0, 0.0), (20.0, 0.0)), line((9.0, 0.0), (1.0, 0.0))];
+    assert_eq!(travel_distance(&paths, start), 21.0);
+
+    let (ordered, report) = order_for_travel(paths, start);
+    assert_eq!(report.before, 21.0);
+    assert_eq!(report.after, 2.0);
+    assert_eq!(
+        ordered,
+        vec![line((1.0, 0.0), (9.0, 0.0)), line((10.0, 0.0), (20.0, 0.0))]
+    );
+}
+
+#[test]
+fn closed_paths_start_between_their_neighbours() {
+    let start = point2(0.0, 0.0);
+    let (ordered, report) = order_for_travel(
+        vec![square(5.0, 5.0, 1.0, false), square(3.0, 0.0, 1.0, false)],
+        start,
+    );
+    // The corner that's nearest to the start isn't the best one, since the
+    // pen comes back to it before going on to the other square.
+    assert_eq!(ordered[0].path[0], point2(3.0, 1.0));
+    assert_eq!(ordered[1].path[0], point2(5.0, 5.0));
+    assert_eq!(report.after, 10f32.sqrt() + 20f32.sqrt());
+    assert_eq!(report.after, travel_distance(&ordered, start));
+}
+
+#[test]
+fn good_orders_are_left_alone() {
+    let start = point2(0.0, 0.0);
+    let paths = vec![line((0.0, 0.0), (1.0, 0.0)), line((1.0, 0.0), (2.0, 0.0))];
+    let (ordered, report) = order_for_travel(paths.clone(), start);
+    assert_eq!(ordered, paths);
+    assert_eq!(report.before, 0.0);
+    assert_eq!(report.after, 0.0);
+}
+
+#[test]
+fn scattered_paths_travel_less() {
+    // A deterministic scatter of short lines and small squares.
+    let mut paths = vec![];
+    let mut seed = 12345u32;
+    let mut next = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 8) as f32 / (1 << 24) as f32 * 100.0
+    };
+    for i in 0..300 {
+        let (x, y) = (next(), next());
+        if i % 3 == 0 {
+            paths.push(square(x, y, 1.0, false));
+        } else {
+            paths.push(line((x, y), (x + next() / 50.0, y + next() / 50.0)));
+        }
+    }
+
+    let start = point2(0.0, 0.0);
+    let (ordered, report) = order_for_travel(paths.clone(), start);
+    assert_eq!(ordered.len(), paths.len());
+    for path in &paths {
+        assert!(ordered.iter().any(|o| same_path(o, path)));
+    }
+    assert!((report.after - travel_distance(&ordered, start)).abs() < 1e-2);
+    // Random order travels about a third of the scene per path; a good order
+    // travels about the distance between neighbours.
+    assert!(report.after < report.before / 4.0, "{:?}", report);
+}
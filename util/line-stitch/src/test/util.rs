@@ -1,5 +1,26 @@
+type Point = ::Point<::euclid::UnknownUnit>;
 type PathSegment = ::PathSegment<::euclid::UnknownUnit>;
 
+pub fn closed(path: Vec<Point>) -> PathSegment {
+    let mut segment = PathSegment::new(path);
+    segment.closed = true;
+    segment
+}
+
+/// A closed square with its corner at (`x`, `y`), wound clockwise or not.
+pub fn square(x: f32, y: f32, size: f32, clockwise: bool) -> PathSegment {
+    let mut points = vec![
+        ::euclid::point2(x, y),
+        ::euclid::point2(x, y + size),
+        ::euclid::point2(x + size, y + size),
+        ::euclid::point2(x + size, y),
+    ];
+    if !clockwise {
+        points.reverse();
+    }
+    closed(points)
+}
+
 pub fn rotate_1<T>(arr: &mut [T]) {
     {
         let (left, right) = arr.split_at_mut(1);
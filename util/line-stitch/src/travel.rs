@@ -0,0 +1,267 @@
+use aabb_quadtree::ItemId;
+use util::{centered_with_radius, compute_bounding_box, index_margin, quad_tree_over, Index};
+use {PathSegment, Point};
+
+/// How many times the order is swept for reversals that shorten it.
+const TWO_OPT_PASSES: usize = 8;
+
+/// How far apart (in the order) the ends of a reversal can be.  Reversals
+/// between paths that are far apart in the order rarely help once they've
+/// been ordered by nearest neighbour, and checking all of them is quadratic.
+const TWO_OPT_WINDOW: usize = 256;
+
+// The points that the pen can start a path at, named by the path and the
+// index of the point in it.
+type EntryIndex<S> = Index<(usize, usize), S>;
+
+/// How far the pen travels between paths without drawing, before and after
+/// the paths are ordered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TravelReport {
+    /// The travel for the paths in the order that they were given.
+    pub before: f32,
+    /// The travel for the paths in the order that they're returned in.
+    pub after: f32,
+}
+
+/// How far the pen travels without drawing, starting from `start` and
+/// drawing each path in order.  Closed paths end where they start.
+pub fn travel_distance<S>(paths: &[PathSegment<S>], start: Point<S>) -> f32 {
+    let mut pen = start;
+    let mut total = 0.0;
+    for path in paths {
+        total += (path.path[0] - pen).length();
+        pen = exit_of(path);
+    }
+    total
+}
+
+/// Orders `paths` so that a plotter starting at `start` moves as little as
+/// possible between them.  Paths are picked by nearest neighbour, and the
+/// order is then shortened by reversing runs of it (2-opt).  Open paths can
+/// be drawn from either end, and closed paths are started from whichever of
+/// their points is closest to the paths around them.  The paths are
+/// returned as they were if none of that helps.
+pub fn order_for_travel<S>(
+    paths: Vec<PathSegment<S>>,
+    start: Point<S>,
+) -> (Vec<PathSegment<S>>, TravelReport) {
+    let _guard = ::flame::start_guard("order_for_travel");
+    let before = travel_distance(&paths, start);
+    if paths.is_empty() {
+        return (
+            paths,
+            TravelReport {
+                before,
+                after: before,
+            },
+        );
+    }
+
+    let mut order = ::flame::span_of("nearest neighbour", || nearest_neighbour(&paths, start));
+    ::flame::span_of("2-opt", || two_opt(&paths, &mut order, start));
+    choose_loop_starts(&paths, &mut order, start);
+
+    let mut pen = start;
+    let mut after = 0.0;
+    for &visit in &order {
+        after += (entry(&paths, visit) - pen).length();
+        pen = exit(&paths, visit);
+    }
+    if after >= before {
+        return (
+            paths,
+            TravelReport {
+                before,
+                after: before,
+            },
+        );
+    }
+
+    let mut paths: Vec<Option<PathSegment<S>>> = paths.into_iter().map(Some).collect();
+    let ordered = order
+        .iter()
+        .map(|visit| {
+            let mut path = paths[visit.path].take().unwrap();
+            if path.closed {
+                path.path.rotate_left(visit.start);
+            } else if visit.start != 0 {
+                path.path.reverse();
+            }
+            path
+        })
+        .collect();
+    (ordered, TravelReport { before, after })
+}
+
+/// A path, and the index of the point that it's drawn from.  Open paths are
+/// drawn from their first point or their last.
+#[derive(Clone, Copy, Debug)]
+struct Visit {
+    path: usize,
+    start: usize,
+}
+
+fn exit_of<S>(path: &PathSegment<S>) -> Point<S> {
+    if path.closed {
+        path.path[0]
+    } else {
+        *path.path.last().unwrap()
+    }
+}
+
+fn entry<S>(paths: &[PathSegment<S>], visit: Visit) -> Point<S> {
+    paths[visit.path].path[visit.start]
+}
+
+fn exit<S>(paths: &[PathSegment<S>], visit: Visit) -> Point<S> {
+    let path = &paths[visit.path].path;
+    if paths[visit.path].closed {
+        path[visit.start]
+    } else if visit.start == 0 {
+        path[path.len() - 1]
+    } else {
+        path[0]
+    }
+}
+
+/// The same path, drawn the other way.
+fn flip<S>(paths: &[PathSegment<S>], visit: Visit) -> Visit {
+    if paths[visit.path].closed {
+        visit
+    } else {
+        Visit {
+            path: visit.path,
+            start: if visit.start == 0 {
+                paths[visit.path].path.len() - 1
+            } else {
+                0
+            },
+        }
+    }
+}
+
+fn nearest_neighbour<S>(paths: &[PathSegment<S>], start: Point<S>) -> Vec<Visit> {
+    let entries = |path: &PathSegment<S>| -> Vec<usize> {
+        if path.closed {
+            (0..path.path.len()).collect()
+        } else {
+            vec![0, path.path.len() - 1]
+        }
+    };
+
+    let scene = compute_bounding_box(paths.iter().flat_map(|p| p.path.iter().cloned()));
+    let margin = index_margin(scene);
+    let count: usize = paths.iter().map(|p| entries(p).len()).sum();
+    let mut index: EntryIndex<S> = quad_tree_over(scene, margin, count);
+    let ids: Vec<Vec<ItemId>> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            entries(path)
+                .into_iter()
+                .map(|e| {
+                    let aabb = centered_with_radius(path.path[e], margin);
+                    index.insert_with_box((i, e), aabb).unwrap()
+                })
+                .collect()
+        })
+        .collect();
+
+    // About the distance between neighbouring entries, if they were spread
+    // evenly over the scene.
+    let first_radius = ((scene.size.width + scene.size.height) / (count as f32).sqrt()).max(margin);
+
+    let mut pen = start;
+    let mut order = Vec::with_capacity(paths.len());
+    while order.len() < paths.len() {
+        // Anything outside of the box is further away than its radius, so
+        // the nearest point in the box is only the nearest overall if it's
+        // within the radius.
+        let mut radius = first_radius;
+        let (path, start) = loop {
+            let nearest = index
+                .query(centered_with_radius(pen, radius))
+                .into_iter()
+                .map(|(&(path, start), _, _)| {
+                    let distance = (paths[path].path[start] - pen).length();
+                    (distance, path, start)
+                })
+                .min_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+            match nearest {
+                Some((distance, path, start)) if distance <= radius => break (path, start),
+                _ => radius *= 2.0,
+            }
+        };
+
+        for &id in &ids[path] {
+            index.remove(id);
+        }
+        let visit = Visit { path, start };
+        pen = exit(paths, visit);
+        order.push(visit);
+    }
+    order
+}
+
+fn two_opt<S>(paths: &[PathSegment<S>], order: &mut [Visit], start: Point<S>) {
+    let distance = |a: Point<S>, b: Point<S>| (b - a).length();
+    for _ in 0..TWO_OPT_PASSES {
+        let mut improved = false;
+        for i in 0..order.len() {
+            let last = (i + TWO_OPT_WINDOW).min(order.len());
+            for j in i..last {
+                let before = if i == 0 {
+                    start
+                } else {
+                    exit(paths, order[i - 1])
+                };
+                let after = order.get(j + 1).map(|&v| entry(paths, v));
+                let (first, last) = (order[i], order[j]);
+
+                // Reversing the run from i to j draws each of its paths the
+                // other way, so the run starts where it used to end.
+                let old = distance(before, entry(paths, first))
+                    + after.map_or(0.0, |a| distance(exit(paths, last), a));
+                let new = distance(before, exit(paths, last))
+                    + after.map_or(0.0, |a| distance(entry(paths, first), a));
+                if new < old * (1.0 - 1e-6) {
+                    order[i..j + 1].reverse();
+                    for visit in &mut order[i..j + 1] {
+                        *visit = flip(paths, *visit);
+                    }
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+/// Starts each closed path at the point that's closest to the end of the
+/// path before it and the start of the path after it.
+fn choose_loop_starts<S>(paths: &[PathSegment<S>], order: &mut [Visit], start: Point<S>) {
+    for k in 0..order.len() {
+        let path = &paths[order[k].path];
+        if !path.closed {
+            continue;
+        }
+        let before = if k == 0 {
+            start
+        } else {
+            exit(paths, order[k - 1])
+        };
+        let after = order.get(k + 1).map(|&v| entry(paths, v));
+        let cost = |p: Point<S>| (p - before).length() + after.map_or(0.0, |a| (a - p).length());
+        let best = (0..path.path.len())
+            .min_by(|&a, &b| {
+                cost(path.path[a])
+                    .partial_cmp(&cost(path.path[b]))
+                    .unwrap_or(::std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        order[k].start = best;
+    }
+}
@@ -1,6 +1,14 @@
+use aabb_quadtree::{ItemId, QuadTree};
 use euclid::*;
 use *;
 
+/// How many items a leaf of a quad tree built by `quad_tree_over` holds
+/// before it's split.
+pub(crate) const QUAD_TREE_LEAF_SIZE: usize = 32;
+
+/// A quad tree of `T`s, with boxes in the units `S`.
+pub(crate) type Index<T, S> = QuadTree<T, S, [(ItemId, TypedRect<f32, S>); QUAD_TREE_LEAF_SIZE]>;
+
 pub fn populate<I, P, S: 'static>(segments: I, epsilon: f32) -> DualQuadTree<S>
 where
     I: IntoIterator<Item = P>,
@@ -65,3 +73,65 @@ pub fn compute_bounding_box<S, I: IntoIterator<Item = Point<S>>>(i: I) -> TypedR
         vec2(max_x - min_x, max_y - min_y).to_size(),
     )
 }
+
+/// How far to pad the boxes of things in `scene` before they go into a quad
+/// tree, so that horizontal and vertical lines, and single points, are still
+/// found by queries that touch them.
+pub(crate) fn index_margin<S>(scene: TypedRect<f32, S>) -> f32 {
+    (scene.size.width + scene.size.height) * 1e-5 + 1e-5
+}
+
+/// An empty quad tree with room for everything in `scene` once its boxes
+/// are padded by `margin`.
+pub(crate) fn quad_tree_over<T, S>(
+    scene: TypedRect<f32, S>,
+    margin: f32,
+    size_hint: usize,
+) -> Index<T, S> {
+    QuadTree::new(
+        scene.inflate(margin * 2.0, margin * 2.0),
+        true,
+        0,
+        QUAD_TREE_LEAF_SIZE,
+        8,
+        size_hint,
+    )
+}
+
+/// Positive for paths that `vectorphile` calls clockwise.
+pub(crate) fn signed_area<S>(points: &[Point<S>]) -> f32 {
+    let mut total = 0.0;
+    for (i, &a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        total += (b.x - a.x) * (b.y + a.y);
+    }
+    total / 2.0
+}
+
+pub(crate) fn is_clockwise<S>(points: &[Point<S>]) -> bool {
+    signed_area(points) > 0.0
+}
+
+/// Whether `p` is inside of the polygon with the corners `points`, by the
+/// even-odd rule.
+pub(crate) fn inside<S>(p: Point<S>, points: &[Point<S>]) -> bool {
+    let mut inside = false;
+    for (i, &a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// How far `p` is from the closest point on the line from `a` to `b`.
+pub(crate) fn distance_to_line<S>(p: Point<S>, a: Point<S>, b: Point<S>) -> f32 {
+    let ab = b - a;
+    let length_2 = ab.square_length();
+    if length_2 == 0.0 {
+        return (p - a).length();
+    }
+    let t = (ab.dot(p - a) / length_2).clamp(0.0, 1.0);
+    (a + ab * t - p).length()
+}
@@ -75,6 +75,11 @@ pub enum Command {
     },
     CloseShape,
     EndShape,
+    // Groups the shapes up to the matching EndGroup under a name
+    StartGroup {
+        id: String,
+    },
+    EndGroup,
 }
 
 pub trait DrawBackend {
@@ -70,8 +70,26 @@ impl<W: Write> DrawBackend for SvgBackend<W> {
                 cy2,
                 x,
                 y,
-            } => unimplemented!(),
-            QuadraticCurveTo { cx, cy, x, y } => unimplemented!(),
+            } => write!(
+                &mut self.out,
+                "\nC{:.p$},{:.p$} {:.p$},{:.p$} {:.p$},{:.p$} ",
+                cx1,
+                cy1,
+                cx2,
+                cy2,
+                x,
+                y,
+                p = self.float_precision
+            ),
+            QuadraticCurveTo { cx, cy, x, y } => write!(
+                &mut self.out,
+                "\nQ{:.p$},{:.p$} {:.p$},{:.p$} ",
+                cx,
+                cy,
+                x,
+                y,
+                p = self.float_precision
+            ),
             ArcTo {
                 rx,
                 ry,
@@ -80,16 +98,29 @@ impl<W: Write> DrawBackend for SvgBackend<W> {
                 sweep,
                 x,
                 y,
-            } => unimplemented!(),
+            } => write!(
+                &mut self.out,
+                "\nA{:.p$},{:.p$} {:.p$} {} {} {:.p$},{:.p$} ",
+                rx,
+                ry,
+                rotation,
+                large_arc as u8,
+                sweep as u8,
+                x,
+                y,
+                p = self.float_precision
+            ),
             CloseShape => writeln!(&mut self.out, r#"z"/>"#),
             EndShape => writeln!(&mut self.out, r#""/>"#),
+            StartGroup { id } => writeln!(&mut self.out, r#"<g id="{}">"#, escape(&id)),
+            EndGroup => writeln!(&mut self.out, "</g>"),
         }
     }
     fn close(mut self) -> Result<(), Error> {
         let script = r#"
 <script>
 let svgs = document.children[0];
-let paths = Array.from(svgs.children).filter(function(c) {
+let paths = Array.from(svgs.querySelectorAll("path")).filter(function(c) {
     var orig = c.style.stroke;
     c.onmouseover = function() {
         c.style.stroke = "rgb(0, 0, 255)";
@@ -111,6 +142,20 @@ for (let path of paths) {
     }
 }
 
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
 /*
 #[cfg(test)]
 mod test {